@@ -16,6 +16,9 @@ pub const TEXT_FILE: &str = "text-file";
 pub const HTML_FILE: &str = "html-file";
 pub const ARCHIVE: &str = "archive";
 pub const ARCHIVE_DIR: &str = "archive-dir";
+pub const ARCHIVE_FORMAT: &str = "archive-format";
+pub const ARCHIVE_COMPRESSION: &str = "archive-compression";
+pub const DEDUP_ATTACHMENTS: &str = "dedup-attachments";
 pub const RECEIVER_FILE: &str = "receiver-file";
 pub const RECEIVER_QUERY: &str = "receiver-query";
 pub const RECEIVER_COLUMN: &str = "receiver-column";
@@ -29,6 +32,45 @@ pub const IMAGE_COLUMN: &str = "image-column";
 pub const IMAGE_NAME: &str = "image-name";
 pub const SSH_TUNNEL: &str = "ssh-tunnel";
 pub const CONNECTION: &str = "connection";
+pub const IGNORE_QUOTA: &str = "ignore-quota";
+pub const PORT: &str = "port";
+pub const DIR: &str = "dir";
+pub const BATCH_SIZE: &str = "batch-size";
+pub const STREAM: &str = "stream";
+pub const MAX_MESSAGE_SIZE: &str = "max-message-size";
+pub const FAILOVER_AFTER: &str = "failover-after";
+pub const OUTPUT: &str = "output";
+pub const SUPPRESSION_FILE: &str = "suppression-file";
+pub const IMAP_FOLDER: &str = "imap-folder";
+pub const SINCE: &str = "since";
+pub const UNTIL: &str = "until";
+pub const NO_DRY_RUN: &str = "no-dry-run";
+pub const STATUS: &str = "status";
+pub const OLDER_THAN: &str = "older-than";
+pub const PREVIEW_DIR: &str = "preview-dir";
+pub const PREVIEW_COUNT: &str = "preview-count";
+pub const PREVIEW_RECEIVER: &str = "preview-receiver";
+pub const TEST_TO: &str = "test-to";
+pub const SAMPLE: &str = "sample";
+pub const SAMPLE_ROWS: &str = "sample-rows";
+pub const SMIME_CERT: &str = "smime-cert";
+pub const SMIME_KEY: &str = "smime-key";
+pub const SMIME_PKCS12: &str = "smime-pkcs12";
+pub const SMIME_CERT_DIR: &str = "smime-cert-dir";
+pub const SMIME_CERT_COLUMN: &str = "smime-cert-column";
+pub const PGP_KEY: &str = "pgp-key";
+pub const PGP_KEY_DIR: &str = "pgp-key-dir";
+pub const PGP_KEY_COLUMN: &str = "pgp-key-column";
+pub const SENDER_COLUMN: &str = "sender-column";
+pub const SUBJECT_COLUMN: &str = "subject-column";
+pub const TEMPLATE_COLUMN: &str = "template-column";
+pub const LOCALE_COLUMN: &str = "locale-column";
+pub const DEFAULT_LOCALE: &str = "default-locale";
+pub const SPLIT: &str = "split";
+pub const SPLIT_SEED: &str = "split-seed";
+pub const VARIANT: &str = "variant";
+pub const DUPLICATES: &str = "duplicates";
+pub const NORMALIZE_GMAIL: &str = "normalize-gmail";
 
 // values for args
 pub mod val {
@@ -38,6 +80,26 @@ pub mod val {
     // possible values for argument CONNECTION and subcommand CONNECT
     pub const SMTP: &str = "smtp";
     pub const AWS: &str = "aws";
+    pub const MX: &str = "mx";
+
+    // possible values for argument ARCHIVE_FORMAT
+    pub const EML: &str = "eml";
+    pub const MBOX: &str = "mbox";
+    pub const MAILDIR: &str = "maildir";
+
+    // possible values for argument ARCHIVE_COMPRESSION
+    pub const GZIP: &str = "gzip";
+    pub const ZSTD: &str = "zstd";
+
+    // possible values for argument STATUS
+    pub const OK: &str = "ok";
+    pub const FAILED: &str = "failed";
+    pub const DRY_RUN: &str = "dry-run";
+
+    // possible values for argument DUPLICATES
+    pub const FIRST: &str = "first";
+    pub const LAST: &str = "last";
+    pub const FAIL: &str = "fail";
 }
 
 pub fn value<'a>(name: &str, matches: &'a ArgMatches) -> Result<&'a str, anyhow::Error> {
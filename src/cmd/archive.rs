@@ -0,0 +1,299 @@
+use crate::{
+    arg::{self, val},
+    cmd,
+    email_archive::{self, Archive, ArchiveFilter, ArchivedEmail, Body},
+    email_builder::Confirmed,
+    utils::confirm,
+};
+use anyhow::{anyhow, Result};
+use chrono::{SecondsFormat, Utc};
+use clap::ArgMatches;
+use std::path::Path;
+
+pub fn archive(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    if matches.get_flag(arg::VERBOSE) {
+        println!("matches: {:#?}", matches);
+    }
+
+    match matches.subcommand() {
+        Some((cmd::LIST, matches)) => list(matches),
+        Some((cmd::SEARCH, matches)) => search(matches),
+        Some((cmd::SHOW, matches)) => show(matches),
+        Some((cmd::PRUNE, matches)) => prune(matches),
+        _ => Err(anyhow!("Subcommand not found")),
+    }
+}
+
+fn list(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    if matches.get_flag(arg::VERBOSE) {
+        println!("matches: {:#?}", matches);
+    }
+
+    let archive = open(matches)?;
+    let filter = ArchiveFilter::from_args(matches)?;
+    let emails = archive
+        .emails
+        .iter()
+        .filter(|email| filter.matches(email))
+        .collect::<Vec<_>>();
+    let mut rows = vec![];
+
+    let row =
+        |campaign: &str, dry_run: bool, sender: &str, subject: &str, emails: &[&&ArchivedEmail]| {
+            vec![
+                campaign.to_owned(),
+                emails.len().to_string(),
+                emails
+                    .iter()
+                    .filter(|email| email.status() == val::FAILED)
+                    .count()
+                    .to_string(),
+                if dry_run { "yes" } else { "no" }.to_owned(),
+                sender.to_owned(),
+                subject.to_owned(),
+            ]
+        };
+
+    for campaign in &archive.campaigns {
+        let campaign_emails = emails
+            .iter()
+            .filter(|email| email.campaign.as_ref() == Some(&campaign.id))
+            .collect::<Vec<_>>();
+
+        if !campaign_emails.is_empty() {
+            rows.push(row(
+                &campaign.id,
+                campaign.dry_run,
+                &campaign.sender,
+                &campaign.subject,
+                &campaign_emails,
+            ));
+        }
+    }
+
+    // Emails archived without campaign directory
+    for dry_run in [false, true] {
+        let other_emails = emails
+            .iter()
+            .filter(|email| email.campaign.is_none() && email.dry_run == dry_run)
+            .collect::<Vec<_>>();
+
+        if !other_emails.is_empty() {
+            rows.push(row("-", dry_run, "-", "-", &other_emails));
+        }
+    }
+
+    print_table(
+        &[
+            "CAMPAIGN", "EMAILS", "FAILED", "DRY RUN", "SENDER", "SUBJECT",
+        ],
+        &rows,
+    );
+    println!("Found {} campaigns", rows.len());
+
+    Ok(())
+}
+
+fn search(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    if matches.get_flag(arg::VERBOSE) {
+        println!("matches: {:#?}", matches);
+    }
+
+    let archive = open(matches)?;
+    let mut filter = ArchiveFilter::from_args(matches)?;
+    filter.text = matches.get_one::<String>(cmd::SEARCH).cloned();
+
+    let mut emails = archive
+        .emails
+        .iter()
+        .filter(|email| filter.matches(email))
+        .collect::<Vec<_>>();
+    emails.sort_by_key(|email| email.date);
+
+    let rows = emails
+        .iter()
+        .map(|email| {
+            vec![
+                email
+                    .date
+                    .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true))
+                    .unwrap_or_else(|| "-".to_owned()),
+                email.status().to_owned(),
+                email.receivers.join(", "),
+                email.subject.clone(),
+                email.message_id.clone().unwrap_or_else(|| "-".to_owned()),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    print_table(
+        &["DATE", "STATUS", "RECEIVERS", "SUBJECT", "MESSAGE-ID"],
+        &rows,
+    );
+    println!("Found {} emails", rows.len());
+
+    Ok(())
+}
+
+fn show(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    if matches.get_flag(arg::VERBOSE) {
+        println!("matches: {:#?}", matches);
+    }
+
+    let id = arg::value(cmd::SHOW, matches)?;
+    let archive = open(matches)?;
+    let email = archive
+        .find(id)
+        .ok_or(anyhow!("Archived email '{}' not found", id))?;
+
+    println!("Message-ID: {}", email.message_id.as_deref().unwrap_or("-"));
+    println!(
+        "Date: {}",
+        email
+            .date
+            .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true))
+            .unwrap_or_else(|| "-".to_owned())
+    );
+    println!("From: {}", email.sender);
+    println!("To: {}", email.receivers.join(", "));
+    println!("Subject: {}", email.subject);
+    println!("Campaign: {}", email.campaign.as_deref().unwrap_or("-"));
+    println!("Status: {}", email.status());
+
+    if let Some(variant) = &email.variant {
+        println!("Variant: {}", variant);
+    }
+
+    if let Some(transport) = &email.transport {
+        println!("Transport: {}", transport);
+    }
+
+    println!("File: {}", email.path.display());
+
+    for (key, value) in &email.personalization {
+        println!("Personalization: {} = {}", key, value);
+    }
+
+    for part in email.parts()? {
+        println!();
+
+        match (&part.file_name, &part.body) {
+            (Some(file_name), body) => {
+                let size = match body {
+                    Body::Text(text) => text.len(),
+                    Body::Binary(bytes) => bytes.len(),
+                };
+                println!(
+                    "--- Attachment '{}' ({}, {} bytes) ---",
+                    file_name, part.content_type, size
+                );
+            }
+            (None, Body::Text(text)) => {
+                println!("--- {} ---", part.content_type);
+                println!("{}", text.trim_end());
+            }
+            (None, Body::Binary(bytes)) => {
+                println!("--- {} ({} bytes) ---", part.content_type, bytes.len());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn prune(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    if matches.get_flag(arg::VERBOSE) {
+        println!("matches: {:#?}", matches);
+    }
+
+    let older_than = arg::value(arg::OLDER_THAN, matches)?;
+    let cutoff = Utc::now() - email_archive::parse_age(older_than)?;
+    let archive_dir = Path::new(arg::value(arg::ARCHIVE_DIR, matches)?);
+
+    println!("Reading archive '{}' ...", archive_dir.display());
+    let paths = email_archive::expired(archive_dir, cutoff)?;
+
+    if paths.is_empty() {
+        println!("No archived campaigns older than {}", older_than);
+        return Ok(());
+    }
+
+    let mut size = 0;
+
+    for path in &paths {
+        size += email_archive::size(path)?;
+        println!("{}", path.display());
+    }
+
+    if !matches.get_flag(arg::ASSUME_YES)
+        && matches!(confirm_prune(paths.len(), size)?, Confirmed::No)
+    {
+        return Ok(());
+    }
+
+    for path in &paths {
+        email_archive::remove(path)?;
+    }
+
+    println!(
+        "Pruned {} archived campaigns ({})",
+        paths.len(),
+        format_size(size)
+    );
+
+    Ok(())
+}
+
+fn confirm_prune(count: usize, size: u64) -> Result<Confirmed, anyhow::Error> {
+    println!(
+        "Should {} archived campaigns ({}) be deleted? Yes (y) or no (n)",
+        count,
+        format_size(size)
+    );
+    confirm()
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} bytes", bytes),
+        1024..=1_048_575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+fn open(matches: &ArgMatches) -> Result<Archive, anyhow::Error> {
+    let archive_dir = Path::new(arg::value(arg::ARCHIVE_DIR, matches)?);
+    println!("Reading archive '{}' ...", archive_dir.display());
+    Archive::open(archive_dir)
+}
+
+/// Print rows as table with left-aligned columns.
+fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let widths = header
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain([column.len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    let format_row = |row: Vec<&str>| {
+        row.iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{:width$}", value, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_owned()
+    };
+
+    println!("{}", format_row(header.to_vec()));
+
+    for row in rows {
+        println!("{}", format_row(row.iter().map(String::as_str).collect()));
+    }
+}
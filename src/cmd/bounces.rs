@@ -0,0 +1,87 @@
+use crate::{
+    arg, cmd,
+    email_feedback::{self, BounceType, SuppressionList},
+    utils::format_green,
+};
+use anyhow::{anyhow, Result};
+use clap::ArgMatches;
+use std::path::Path;
+
+pub fn bounces(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    if matches.get_flag(arg::VERBOSE) {
+        println!("matches: {:#?}", matches);
+    }
+
+    match matches.subcommand() {
+        Some((cmd::IMPORT, matches)) => import(matches),
+        _ => Err(anyhow!("Subcommand not found")),
+    }
+}
+
+fn import(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    if matches.get_flag(arg::VERBOSE) {
+        println!("matches: {:#?}", matches);
+    }
+
+    let paths = match matches.get_many::<String>(cmd::IMPORT) {
+        Some(paths) => paths.map(Path::new).collect::<Vec<_>>(),
+        None => return Err(anyhow!("Missing value for argument '{}'", cmd::IMPORT)),
+    };
+    let mut bounces = vec![];
+
+    for path in paths {
+        println!("Importing bounces from '{}' ...", path.display());
+        bounces.extend(email_feedback::import(path)?);
+    }
+
+    let count = |bounce_type| {
+        bounces
+            .iter()
+            .filter(|bounce| bounce.bounce_type == bounce_type)
+            .count()
+    };
+    println!(
+        "Found {} hard bounces, {} soft bounces, and {} complaints",
+        count(BounceType::Hard),
+        count(BounceType::Soft),
+        count(BounceType::Complaint)
+    );
+
+    if matches.get_flag(arg::DISPLAY) {
+        for bounce in &bounces {
+            println!(
+                "{} ... {} {} {}",
+                bounce.email, bounce.bounce_type, bounce.status, bounce.diagnostic
+            );
+        }
+    }
+
+    if let Some(output) = matches.get_one::<String>(arg::OUTPUT).map(Path::new) {
+        email_feedback::write_bounces(output, &bounces)?;
+        println!(
+            "Writing bounces to '{}' ... {}",
+            output.display(),
+            format_green("ok")
+        );
+    }
+
+    if let Some(suppression_file) = matches
+        .get_one::<String>(arg::SUPPRESSION_FILE)
+        .map(Path::new)
+    {
+        let mut suppression_list = SuppressionList::load_or_create(suppression_file)?;
+        let added = bounces
+            .iter()
+            .filter(|bounce| suppression_list.insert(bounce))
+            .count();
+        suppression_list.save()?;
+        println!(
+            "Adding {} receivers to suppression list '{}' ... {}",
+            added,
+            suppression_file.display(),
+            format_green("ok")
+        );
+    }
+
+    Ok(())
+}
@@ -20,7 +20,16 @@ pub fn connect(matches: &ArgMatches) -> Result<(), anyhow::Error> {
                     Ok(())
                 }
                 val::AWS => {
-                    let _client = AwsSesClient::new(matches)?;
+                    let domain = match matches.get_one::<String>(arg::SENDER) {
+                        Some(sender) => match sender.rsplit_once('@') {
+                            Some((_, domain)) => Some(domain),
+                            None => Some(sender.as_str()),
+                        },
+                        None => None,
+                    };
+                    let client = AwsSesClient::new()?;
+                    client.check_credentials()?;
+                    client.display_account(domain)?;
                     Ok(())
                 }
                 other => Err(anyhow!(
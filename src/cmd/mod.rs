@@ -1,18 +1,26 @@
+mod archive;
+mod bounces;
 mod connect;
 mod init;
 mod query;
 mod read;
+mod resend;
 mod send;
 mod send_bulk;
 mod simple_query;
+mod smtp_sink;
 
+pub use archive::archive;
+pub use bounces::bounces;
 pub use connect::connect;
 pub use init::init;
 pub use query::query;
 pub use read::read;
+pub use resend::resend;
 pub use send::send;
 pub use send_bulk::send_bulk;
 pub use simple_query::simple_query;
+pub use smtp_sink::smtp_sink;
 
 // Binary name
 pub const BIN: &str = "pigeon";
@@ -25,3 +33,12 @@ pub const SIMPLE_QUERY: &str = "simple-query";
 pub const READ: &str = "read";
 pub const SEND: &str = "send";
 pub const SEND_BULK: &str = "send-bulk";
+pub const SMTP_SINK: &str = "smtp-sink";
+pub const BOUNCES: &str = "bounces";
+pub const IMPORT: &str = "import";
+pub const ARCHIVE: &str = "archive";
+pub const LIST: &str = "list";
+pub const SEARCH: &str = "search";
+pub const SHOW: &str = "show";
+pub const PRUNE: &str = "prune";
+pub const RESEND: &str = "resend";
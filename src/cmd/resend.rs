@@ -0,0 +1,79 @@
+use super::send_bulk::{confirm_emails, process_emails};
+use crate::{
+    arg, cmd,
+    email_archive::Archive,
+    email_builder::{Confirmed, Email, Message, Receiver, Sender},
+    email_transmission::Client,
+    utils::format_green,
+};
+use anyhow::{anyhow, Result};
+use clap::ArgMatches;
+use std::path::Path;
+
+pub fn resend(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    if matches.get_flag(arg::VERBOSE) {
+        println!("matches: {:#?}", matches);
+    }
+
+    let dry_run = matches.get_flag(arg::DRY_RUN);
+    let path = Path::new(arg::value(cmd::RESEND, matches)?);
+    let status = matches.get_one::<String>(arg::STATUS);
+
+    println!("Reading archive '{}' ...", path.display());
+    let archive = Archive::open(path)?;
+    let archived_emails = archive
+        .emails
+        .iter()
+        .filter(|email| status.map_or(true, |status| email.status() == status))
+        .collect::<Vec<_>>();
+
+    if archived_emails.is_empty() {
+        return Err(anyhow!("No archived emails found in '{}'", path.display()));
+    }
+
+    let mut emails = vec![];
+
+    for archived_email in &archived_emails {
+        let mime_format = archived_email.mime_format()?;
+        // The message is only used for display, the archived email is sent instead
+        let message = Message::new(archived_email.subject.as_str(), None, None);
+        let sender = Sender(&archived_email.sender);
+        let receivers = archived_email
+            .receivers
+            .iter()
+            .map(|receiver| Receiver(receiver))
+            .collect::<Vec<_>>();
+        let email = match receivers.as_slice() {
+            [receiver] => Email::new(sender, *receiver, &message, &mime_format)?,
+            _ => Email::new_batch(sender, receivers, &message, &mime_format)?,
+        };
+        emails.push(email);
+    }
+
+    let client = Client::from_args(matches)?;
+
+    if matches.get_flag(arg::DISPLAY) {
+        println!("Display emails: {:#?}", emails);
+    }
+
+    if dry_run {
+        println!("Dry run: {}", format_green("activated"));
+    }
+
+    if matches.get_flag(arg::ASSUME_YES) {
+        process_emails(&client, None, &emails)?;
+    } else {
+        match confirm_emails(&emails, &[])? {
+            Confirmed::Yes => process_emails(&client, None, &emails)?,
+            Confirmed::No => (),
+        }
+    }
+
+    if dry_run {
+        println!("All emails resent (dry run)");
+    } else {
+        println!("All emails resent");
+    }
+
+    Ok(())
+}
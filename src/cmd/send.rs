@@ -1,14 +1,15 @@
+use super::send_bulk::{check_message_size, email_security};
 use crate::{
     arg,
-    email_builder::{Confirmed, Email, Message, MimeFormat, Receiver, Sender},
-    email_formatter::EmlFormatter,
+    email_builder::{Attachment, Confirmed, Email, Message, MimeFormat, Receiver, Sender},
+    email_formatter::{ArchiveOptions, Campaign, CampaignArchive, PreviewFormatter, Source},
     email_transmission::Client,
     utils::format_green,
 };
 use anyhow::Context;
 use chrono::Utc;
 use clap::ArgMatches;
-use std::{io, path::Path, time::SystemTime};
+use std::{collections::HashMap, io, path::Path, time::SystemTime};
 
 pub fn send(matches: &ArgMatches) -> Result<(), anyhow::Error> {
     if matches.get_flag(arg::VERBOSE) {
@@ -19,47 +20,73 @@ pub fn send(matches: &ArgMatches) -> Result<(), anyhow::Error> {
     let dry_run = matches.get_flag(arg::DRY_RUN);
     let is_archived = matches.get_flag(arg::ARCHIVE);
     let archive_dir = Path::new(arg::value(arg::ARCHIVE_DIR, matches)?);
+    let archive_options = ArchiveOptions::from_args(matches)?;
     let sender = Sender(arg::value(arg::SENDER, matches)?);
     let receiver = Receiver(arg::value(arg::RECEIVER, matches)?);
     let message = Message::from_args(matches)?;
-    let attachment = matches.get_one::<String>(arg::ATTACHMENT).map(Path::new);
-    let mime_format = MimeFormat::new(sender, receiver, &message, attachment, now)?;
-    let email = Email::new(sender, receiver, &message, &mime_format)?;
+    let attachments = Attachment::from_args(matches, &message.attachments)?;
+    let mime_format = MimeFormat::new(sender, receiver, &message, &attachments, now)?;
+    let security = email_security(matches)?;
+    let mut email = Email::new(sender, receiver, &message, &mime_format)?;
 
     if matches.get_flag(arg::DISPLAY) {
         println!("Display email: {:#?}", email);
     }
 
+    if let Some(preview_dir) = matches.get_one::<String>(arg::PREVIEW_DIR).map(Path::new) {
+        PreviewFormatter::new(preview_dir)?.preview(&email, 1)?;
+        return Ok(());
+    }
+
+    if let Some(security) = &security {
+        security.secure(&mut email, &HashMap::new())?;
+    }
+
     if dry_run {
         println!("Dry run: {}", format_green("activated"));
     }
 
     let now = Utc::now();
     let client = Client::from_args(matches)?;
-    let eml_formatter = EmlFormatter::new(archive_dir)?;
+    check_message_size(matches, &client, &attachments)?;
 
     println!("Sending email to 1 receiver ...");
 
-    if matches.get_flag(arg::ASSUME_YES) {
-        let sent_email = client.send(&email)?;
-        sent_email.display_status();
-
-        if is_archived {
-            eml_formatter.archive(&email, now, dry_run)?;
-        }
+    let confirmation = if matches.get_flag(arg::ASSUME_YES) {
+        Confirmed::Yes
     } else {
-        let confirmation = confirm_email(&email)?;
-        match confirmation {
-            Confirmed::Yes => {
-                let sent_email = client.send(&email)?;
-                sent_email.display_status();
-
-                if is_archived {
-                    eml_formatter.archive(&email, now, dry_run)?;
-                }
+        confirm_email(&email)?
+    };
+
+    match confirmation {
+        Confirmed::Yes => {
+            // The campaign is created only after the email is confirmed
+            let campaign_archive = if is_archived {
+                let campaign = Campaign::new(
+                    sender,
+                    &message,
+                    Source::Argument,
+                    &archive_options,
+                    dry_run,
+                    now,
+                );
+                Some(CampaignArchive::new(
+                    archive_dir,
+                    &archive_options,
+                    &campaign,
+                )?)
+            } else {
+                None
+            };
+
+            let sent_email = client.send(&email)?;
+            sent_email.display_status();
+
+            if let Some(campaign_archive) = &campaign_archive {
+                campaign_archive.archive(&email, &sent_email, now)?;
             }
-            Confirmed::No => (),
         }
+        Confirmed::No => (),
     }
 
     if matches.get_flag(arg::DRY_RUN) {
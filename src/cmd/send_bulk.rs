@@ -1,28 +1,277 @@
 use crate::{
     arg,
-    email_builder::{BulkEmail, BulkReceiver, Confirmed, Email, Message, Sender},
-    email_formatter::EmlFormatter,
-    email_transmission::Client,
-    utils::format_green,
+    email_builder::{
+        Attachment, BulkEmail, BulkReceiver, Confirmed, Deduplication, Duplicate, DuplicatePolicy,
+        Email, Message, MessageColumns, Pgp, Receiver, ReceiverRow, ReceiverStream, SecureEmail,
+        Sender, Smime, SplitTest, StreamedRow,
+    },
+    email_feedback::SuppressionList,
+    email_formatter::{ArchiveOptions, Campaign, CampaignArchive, PreviewFormatter, Source},
+    email_transmission::{Client, TransmissionType},
+    utils::{confirm, format_green, format_red, parse_size},
 };
 use anyhow::{anyhow, Context, Result};
 use chrono::Utc;
-use clap::ArgMatches;
-use std::{io, path::Path};
+use clap::{parser::ValueSource, ArgMatches};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+    time::SystemTime,
+};
 
 pub fn send_bulk(matches: &ArgMatches) -> Result<(), anyhow::Error> {
     if matches.get_flag(arg::VERBOSE) {
         println!("matches: {:#?}", matches);
     }
 
+    if matches.get_flag(arg::STREAM) {
+        return send_bulk_stream(matches);
+    }
+
     let dry_run = matches.get_flag(arg::DRY_RUN);
-    let is_archived = matches.get_flag(arg::ARCHIVE);
-    let archive_dir = Path::new(arg::value(arg::ARCHIVE_DIR, matches)?);
     let sender = Sender(arg::value(arg::SENDER, matches)?);
-    let receivers = BulkReceiver::from_args(matches)?;
+    let mut receivers = BulkReceiver::from_args(matches)?;
     let message = Message::from_args(matches)?;
-    let attachment = matches.get_one::<String>(arg::ATTACHMENT).map(Path::new);
 
+    if matches.contains_id(arg::TEST_TO) {
+        let rows = match matches.get_many::<usize>(arg::SAMPLE_ROWS) {
+            Some(rows) => rows.map(|row| row - 1).collect::<Vec<_>>(),
+            None => {
+                let sample = arg::value(arg::SAMPLE, matches)?
+                    .parse::<usize>()
+                    .context(format!("Invalid value for argument '{}'", arg::SAMPLE))?;
+
+                if sample == 0 {
+                    return Err(anyhow!("Sample size must be greater than zero"));
+                }
+
+                (0..sample.min(receivers.height())).collect()
+            }
+        };
+
+        receivers.select_rows(&rows)?;
+        println!(
+            "Selecting {} rows for test emails ... {}",
+            receivers.height(),
+            format_green("ok")
+        );
+    }
+
+    // Duplicates are removed first, so that their rows match the receiver file
+    let duplicates = receivers.dedup(&Deduplication::from_args(matches)?)?;
+
+    if !duplicates.is_empty() {
+        println!(
+            "Skipping {} rows of duplicate receivers ... {}",
+            duplicates
+                .iter()
+                .map(|duplicate| duplicate.skipped_rows().count())
+                .sum::<usize>(),
+            format_green("ok")
+        );
+    }
+
+    if let Some(suppression_file) = matches
+        .get_one::<String>(arg::SUPPRESSION_FILE)
+        .map(Path::new)
+    {
+        let suppression_list = SuppressionList::load(suppression_file)?;
+        let suppressed = receivers.retain(|receiver| !suppression_list.contains(receiver))?;
+        println!(
+            "Skipping {} receivers in suppression list '{}' ... {}",
+            suppressed,
+            suppression_file.display(),
+            format_green("ok")
+        );
+    }
+
+    let attachments = Attachment::from_args(matches, &message.attachments)?;
+    let mut message_columns = MessageColumns::from_args(matches);
+    message_columns.load_templates(&receivers)?;
+    let security = email_security(matches)?;
+
+    let split_test = SplitTest::from_args(matches, &message)?;
+    let split_groups = match &split_test {
+        Some(split_test) => {
+            let groups = split_test.groups(&receivers)?;
+            let assigned = groups
+                .iter()
+                .map(|group| group.receivers.height())
+                .sum::<usize>();
+
+            for group in groups.iter() {
+                println!(
+                    "Assigning {} receivers to variant '{}' ... {}",
+                    group.receivers.height(),
+                    group.variant,
+                    format_green("ok")
+                );
+            }
+
+            println!(
+                "Skipping {} receivers not assigned in this run",
+                receivers.height() - assigned
+            );
+
+            Some(groups)
+        }
+        None => None,
+    };
+
+    let mut bulk_email = match &split_groups {
+        Some(groups) => {
+            let mut emails = vec![];
+
+            for group in groups {
+                let mut group_email = build_bulk_email(
+                    matches,
+                    sender,
+                    &group.receivers,
+                    group.message,
+                    &attachments,
+                    &message_columns,
+                )?;
+
+                for email in group_email.emails.iter_mut() {
+                    email.variant = Some(group.variant.to_owned());
+                }
+
+                emails.append(&mut group_email.emails);
+            }
+
+            BulkEmail { emails }
+        }
+        None => build_bulk_email(
+            matches,
+            sender,
+            &receivers,
+            &message,
+            &attachments,
+            &message_columns,
+        )?,
+    };
+
+    if let Some(preview_dir) = matches.get_one::<String>(arg::PREVIEW_DIR).map(Path::new) {
+        return preview_emails(matches, preview_dir, &bulk_email.emails);
+    }
+
+    if let Some(test_receivers) = matches.get_many::<String>(arg::TEST_TO) {
+        let test_receivers = test_receivers
+            .map(|receiver| Receiver(receiver))
+            .collect::<Vec<_>>();
+        let mut test_email = bulk_email.test_emails(&test_receivers)?;
+
+        if let Some(security) = &security {
+            secure_emails(security.as_ref(), &mut test_email.emails, &HashMap::new())?;
+        }
+
+        return send_test_emails(matches, &attachments, &message_columns, &test_email.emails);
+    }
+
+    if let Some(security) = &security {
+        let key_paths = match security.key_column() {
+            Some(column) => receivers.values_by_receiver(column)?,
+            None => HashMap::new(),
+        };
+        secure_emails(security.as_ref(), &mut bulk_email.emails, &key_paths)?;
+    }
+
+    let client = Client::from_args(matches)?;
+    client.check_quota(
+        bulk_email.receiver_count(),
+        matches.get_flag(arg::IGNORE_QUOTA),
+    )?;
+    check_message_size(matches, &client, &attachments)?;
+
+    for attachments in message_columns.template_attachments() {
+        check_message_size(matches, &client, attachments)?;
+    }
+
+    if matches.contains_id(arg::BATCH_SIZE)
+        && client.transmission_types().contains(&TransmissionType::Mx)
+    {
+        return Err(anyhow!(
+            "Argument '{}' is not supported for direct delivery",
+            arg::BATCH_SIZE
+        ));
+    }
+
+    if client.transmission_type() == &TransmissionType::Mx {
+        bulk_email.sort_by_domain();
+    }
+
+    if matches.get_flag(arg::DISPLAY) {
+        println!("Display emails: {:#?}", bulk_email);
+    }
+
+    if dry_run {
+        println!("Dry run: {}", format_green("activated"));
+    }
+
+    let confirmation = if matches.get_flag(arg::ASSUME_YES) {
+        Confirmed::Yes
+    } else {
+        confirm_emails(&bulk_email.emails, &duplicates)?
+    };
+
+    match confirmation {
+        Confirmed::Yes => {
+            let campaign_archive = campaign_archive(matches, sender, &message)?;
+            process_emails(&client, campaign_archive.as_ref(), &bulk_email.emails)?;
+        }
+        Confirmed::No => (),
+    }
+
+    if dry_run {
+        println!("All emails sent (dry run)");
+    } else {
+        println!("All emails sent");
+    }
+
+    Ok(())
+}
+
+/// Create the archive of the campaign if sent emails are archived. The
+/// archive is created only after the emails are confirmed.
+fn campaign_archive(
+    matches: &ArgMatches,
+    sender: Sender,
+    message: &Message,
+) -> Result<Option<CampaignArchive>, anyhow::Error> {
+    if !matches.get_flag(arg::ARCHIVE) {
+        return Ok(None);
+    }
+
+    let archive_dir = Path::new(arg::value(arg::ARCHIVE_DIR, matches)?);
+    let archive_options = ArchiveOptions::from_args(matches)?;
+    let mut campaign = Campaign::new(
+        sender,
+        message,
+        Source::from_args(matches),
+        &archive_options,
+        matches.get_flag(arg::DRY_RUN),
+        Utc::now(),
+    );
+    campaign.split = matches.get_one::<String>(arg::SPLIT).cloned();
+    campaign.winner = matches.get_one::<String>(arg::VARIANT).cloned();
+
+    Ok(Some(CampaignArchive::new(
+        archive_dir,
+        &archive_options,
+        &campaign,
+    )?))
+}
+
+/// Create the emails of the receivers, which are personalized or sent in
+/// batches as requested.
+fn build_bulk_email<'a>(
+    matches: &ArgMatches,
+    sender: Sender<'a>,
+    receivers: &'a BulkReceiver,
+    message: &'a Message,
+    attachments: &'a [Attachment],
+    message_columns: &'a MessageColumns,
+) -> Result<BulkEmail<'a>, anyhow::Error> {
     let bulk_email = if matches.contains_id(arg::PERSONALIZE) {
         if let Some(personalized_columns) = matches.get_many::<String>(arg::PERSONALIZE) {
             let personalized_columns = personalized_columns
@@ -30,116 +279,490 @@ pub fn send_bulk(matches: &ArgMatches) -> Result<(), anyhow::Error> {
                 .collect::<Vec<_>>();
             BulkEmail::new(
                 sender,
-                &receivers,
-                &message,
-                attachment,
+                receivers,
+                message,
+                attachments,
                 &personalized_columns,
+                message_columns,
             )?
         } else {
             return Err(anyhow!("Missing value for argument '{}'", arg::PERSONALIZE));
         }
+    } else if matches.contains_id(arg::BATCH_SIZE) {
+        let batch_size = arg::value(arg::BATCH_SIZE, matches)?
+            .parse::<usize>()
+            .context(format!("Invalid value for argument '{}'", arg::BATCH_SIZE))?;
+        BulkEmail::new_batched(sender, receivers, message, attachments, batch_size)?
     } else {
-        BulkEmail::new(sender, &receivers, &message, attachment, &[])?
+        BulkEmail::new(
+            sender,
+            receivers,
+            message,
+            attachments,
+            &[],
+            message_columns,
+        )?
     };
-    let client = Client::from_args(matches)?;
-    let eml_formatter = EmlFormatter::new(archive_dir)?;
 
-    if matches.get_flag(arg::DISPLAY) {
-        println!("Display emails: {:#?}", bulk_email);
+    Ok(bulk_email)
+}
+
+/// Send bulk emails while reading the receivers row by row. Each email is
+/// built right before it is sent and dropped afterwards.
+fn send_bulk_stream(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let dry_run = matches.get_flag(arg::DRY_RUN);
+    let sender = Sender(arg::value(arg::SENDER, matches)?);
+    let receivers = ReceiverStream::from_args(matches)?;
+    let message = Message::from_args(matches)?;
+    let attachments = Attachment::from_args(matches, &message.attachments)?;
+    let mut message_columns = MessageColumns::from_args(matches);
+    let split_test = SplitTest::from_args(matches, &message)?;
+    // Detecting duplicates keeps the addresses of all receivers in memory, so
+    // it is opt-in when streaming
+    let deduplication = if matches.value_source(arg::DUPLICATES) == Some(ValueSource::CommandLine)
+        || matches.get_flag(arg::NORMALIZE_GMAIL)
+    {
+        Some(Deduplication::from_args(matches)?)
+    } else {
+        None
+    };
+
+    if let Some(deduplication) = deduplication
+        .as_ref()
+        .filter(|deduplication| deduplication.policy != DuplicatePolicy::KeepFirst)
+    {
+        return Err(anyhow!(
+            "Value '{}' for argument '{}' is not supported for argument '{}'",
+            deduplication.policy,
+            arg::DUPLICATES,
+            arg::STREAM
+        ));
     }
 
+    let security = email_security(matches)?;
+    let suppression_list = match matches.get_one::<String>(arg::SUPPRESSION_FILE) {
+        Some(suppression_file) => Some(SuppressionList::load(Path::new(suppression_file))?),
+        None => None,
+    };
+    let client = Client::from_args(matches)?;
+    // The number of receivers is unknown before all rows are read, so sending
+    // stops as soon as the remaining quota is used up
+    client.check_quota(1, matches.get_flag(arg::IGNORE_QUOTA))?;
+    let remaining_quota = if matches.get_flag(arg::IGNORE_QUOTA) {
+        None
+    } else {
+        client.remaining_quota()?
+    };
+    check_message_size(matches, &client, &attachments)?;
+
     if dry_run {
         println!("Dry run: {}", format_green("activated"));
     }
 
-    if matches.get_flag(arg::ASSUME_YES) {
-        process_emails(
+    if !matches.get_flag(arg::ASSUME_YES) {
+        println!(
+            "Should an email be sent to each receiver in '{}'? Yes (y) or no (n)",
+            receivers.source
+        );
+
+        if let Confirmed::No = confirm()? {
+            return Ok(());
+        }
+    }
+
+    let campaign_archive = campaign_archive(matches, sender, &message)?;
+
+    println!("Sending email to receivers in '{}' ...", receivers.source);
+
+    let receiver_column = receivers.column_name.clone();
+    let now = SystemTime::now();
+    let mut receiver_count = 0;
+    let mut suppressed = 0;
+    let mut unassigned = 0;
+    // The normalized addresses of the receivers read so far
+    let mut seen_receivers = HashSet::new();
+    let mut duplicates = 0;
+    let mut receivers_by_variant = BTreeMap::<&str, usize>::new();
+    // Rows whose email can't be built are reported and skipped, numbered from
+    // one
+    let mut failed_rows = vec![];
+    let mut stopped_at = None;
+
+    for row in receivers {
+        let row = match row? {
+            StreamedRow::Receiver(row) => row,
+            StreamedRow::MissingReceiver(index) => {
+                println!(
+                    "{}: Skipping row {}: Missing value for column '{}'",
+                    format_red("Error"),
+                    index + 1,
+                    receiver_column
+                );
+                failed_rows.push(index + 1);
+                continue;
+            }
+        };
+
+        if deduplication.as_ref().is_some_and(|deduplication| {
+            !seen_receivers.insert(deduplication.normalize(&row.receiver))
+        }) {
+            println!(
+                "Skipping duplicate receiver '{}' in row {}",
+                row.receiver,
+                row.index + 1
+            );
+            duplicates += 1;
+            continue;
+        }
+
+        if suppression_list
+            .as_ref()
+            .is_some_and(|suppression_list| suppression_list.contains(&row.receiver))
+        {
+            suppressed += 1;
+            continue;
+        }
+
+        let (variant, message) = match &split_test {
+            Some(split_test) => match split_test.select(&row.receiver) {
+                Some((variant, message)) => (Some(variant), message),
+                None => {
+                    unassigned += 1;
+                    continue;
+                }
+            },
+            None => (None, &message),
+        };
+
+        if remaining_quota.is_some_and(|remaining_quota| receiver_count as u64 >= remaining_quota) {
+            stopped_at = Some(row.index);
+            break;
+        }
+
+        let email = build_row_email(
+            matches,
             &client,
-            &eml_formatter,
-            &bulk_email.emails,
-            dry_run,
-            is_archived,
-        )?;
-    } else {
-        let confirmation = confirm_emails(&bulk_email.emails)?;
-        match confirmation {
-            Confirmed::Yes => {
-                process_emails(
-                    &client,
-                    &eml_formatter,
-                    &bulk_email.emails,
-                    dry_run,
-                    is_archived,
-                )?;
+            &row,
+            sender,
+            message,
+            &attachments,
+            &mut message_columns,
+            security.as_deref(),
+            now,
+        );
+        let mut email = match email {
+            Ok(email) => email,
+            Err(err) => {
+                println!(
+                    "{}: Skipping receiver '{}': {:#}",
+                    format_red("Error"),
+                    row.receiver,
+                    err
+                );
+                failed_rows.push(row.index + 1);
+                continue;
             }
-            Confirmed::No => (),
+        };
+        email.variant = variant.map(str::to_owned);
+
+        let sent_email = client.send(&email)?;
+        sent_email.display_status();
+
+        if let Some(campaign_archive) = &campaign_archive {
+            campaign_archive.archive(&email, &sent_email, Utc::now())?;
         }
+
+        if let Some(variant) = variant {
+            *receivers_by_variant.entry(variant).or_default() += 1;
+        }
+
+        receiver_count += 1;
+    }
+
+    if suppressed > 0 {
+        println!("Skipped {} receivers in suppression list", suppressed);
+    }
+
+    if duplicates > 0 {
+        println!("Skipped {} rows of duplicate receivers", duplicates);
+    }
+
+    for (variant, count) in receivers_by_variant {
+        println!("Sent variant '{}' to {} receivers", variant, count);
+    }
+
+    if unassigned > 0 {
+        println!("Skipped {} receivers not assigned in this run", unassigned);
+    }
+
+    if !failed_rows.is_empty() {
+        let rows = failed_rows
+            .iter()
+            .map(|row| row.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!(
+            "{}: Skipped {} rows which couldn't be built (rows {})",
+            format_red("Error"),
+            failed_rows.len(),
+            rows
+        );
+    }
+
+    if let Some(row) = stopped_at {
+        return Err(anyhow!(
+            "Sending quota exhausted after {} receivers, stopped before row {}. Use '--{}' to send anyway",
+            receiver_count,
+            row + 1,
+            arg::IGNORE_QUOTA
+        ));
     }
 
     if dry_run {
-        println!("All emails sent (dry run)");
+        println!("All emails sent to {} receivers (dry run)", receiver_count);
     } else {
-        println!("All emails sent");
+        println!("All emails sent to {} receivers", receiver_count);
     }
 
     Ok(())
 }
 
-pub fn process_emails<'a>(
-    client: &Client<'a>,
-    eml_formatter: &EmlFormatter,
-    emails: &'a [Email],
-    dry_run: bool,
-    is_archived: bool,
+/// Build the email of a single row of streamed receivers.
+#[allow(clippy::too_many_arguments)]
+fn build_row_email<'a>(
+    matches: &ArgMatches,
+    client: &Client,
+    row: &'a ReceiverRow,
+    sender: Sender<'a>,
+    message: &'a Message,
+    attachments: &'a [Attachment],
+    message_columns: &'a mut MessageColumns,
+    security: Option<&dyn SecureEmail>,
+    now: SystemTime,
+) -> Result<Email<'a>, anyhow::Error> {
+    let mut personalization = BTreeMap::new();
+
+    if let Some(personalized_columns) = matches.get_many::<String>(arg::PERSONALIZE) {
+        for col_name in personalized_columns {
+            personalization.insert(col_name.to_owned(), row.value(col_name)?.to_owned());
+        }
+    }
+
+    message_columns.load_row(|col_name| row.optional_value(col_name))?;
+    let message_columns: &'a MessageColumns = message_columns;
+
+    let (sender, message, attachments) =
+        message_columns.select(sender, message, attachments, |col_name| {
+            row.optional_value(col_name)
+        })?;
+
+    if message_columns.selects_template() {
+        check_message_size(matches, client, attachments)?;
+    }
+
+    let mut email = Email::new_personalized(
+        sender,
+        Receiver(&row.receiver),
+        &message,
+        attachments,
+        personalization,
+        now,
+    )?;
+
+    if let Some(security) = security {
+        let key_paths = match security.key_column() {
+            Some(column) => HashMap::from([(row.receiver.clone(), row.value(column)?.to_owned())]),
+            None => HashMap::new(),
+        };
+        security.secure(&mut email, &key_paths)?;
+    }
+
+    Ok(email)
+}
+
+pub fn process_emails(
+    client: &Client,
+    campaign_archive: Option<&CampaignArchive>,
+    emails: &[Email],
 ) -> Result<(), anyhow::Error> {
-    println!("Sending email to {} receivers ...", emails.len());
+    let receiver_count = emails
+        .iter()
+        .map(|email| email.receivers().len())
+        .sum::<usize>();
+    println!("Sending email to {} receivers ...", receiver_count);
 
     for email in emails {
         let sent_email = client.send(email)?;
         sent_email.display_status();
 
-        if is_archived {
-            let now = Utc::now();
-            eml_formatter.archive(email, now, dry_run)?;
+        if let Some(campaign_archive) = campaign_archive {
+            campaign_archive.archive(email, &sent_email, Utc::now())?;
         }
     }
 
     Ok(())
 }
 
-pub fn confirm_emails(emails: &[Email]) -> Result<Confirmed, anyhow::Error> {
-    let mut input = String::new();
-    let email_count = emails.len();
+/// Sign and encrypt emails via S/MIME or PGP/MIME, if requested.
+pub fn email_security(matches: &ArgMatches) -> Result<Option<Box<dyn SecureEmail>>, anyhow::Error> {
+    if let Some(smime) = Smime::from_args(matches)? {
+        return Ok(Some(Box::new(smime)));
+    }
+
+    if let Some(pgp) = Pgp::from_args(matches)? {
+        return Ok(Some(Box::new(pgp)));
+    }
+
+    Ok(None)
+}
+
+/// Sign and encrypt the emails.
+fn secure_emails(
+    security: &dyn SecureEmail,
+    emails: &mut [Email],
+    key_paths: &HashMap<String, String>,
+) -> Result<(), anyhow::Error> {
+    println!("Signing and encrypting {} emails ...", emails.len());
+
+    for email in emails {
+        security.secure(email, key_paths)?;
+    }
+
+    Ok(())
+}
+
+/// Check the attachments against the maximum message size given by argument or
+/// by the email provider.
+pub fn check_message_size(
+    matches: &ArgMatches,
+    client: &Client,
+    attachments: &[Attachment],
+) -> Result<(), anyhow::Error> {
+    let max_message_size = match matches.get_one::<String>(arg::MAX_MESSAGE_SIZE) {
+        Some(max_message_size) => Some(parse_size(max_message_size).context(format!(
+            "Invalid value for argument '{}'",
+            arg::MAX_MESSAGE_SIZE
+        ))?),
+        None => None,
+    };
+
+    client.check_message_size(attachments, max_message_size)
+}
+
+/// Send test emails without archiving them.
+fn send_test_emails(
+    matches: &ArgMatches,
+    attachments: &[Attachment],
+    message_columns: &MessageColumns,
+    emails: &[Email],
+) -> Result<(), anyhow::Error> {
+    let dry_run = matches.get_flag(arg::DRY_RUN);
+    let client = Client::from_args(matches)?;
+    check_message_size(matches, &client, attachments)?;
+
+    for attachments in message_columns.template_attachments() {
+        check_message_size(matches, &client, attachments)?;
+    }
+
+    if dry_run {
+        println!("Dry run: {}", format_green("activated"));
+    }
+
+    if matches.get_flag(arg::ASSUME_YES) {
+        process_emails(&client, None, emails)?;
+    } else {
+        match confirm_emails(emails, &[])? {
+            Confirmed::Yes => process_emails(&client, None, emails)?,
+            Confirmed::No => return Ok(()),
+        }
+    }
+
+    if dry_run {
+        println!("All test emails sent (dry run)");
+    } else {
+        println!("All test emails sent");
+    }
+
+    Ok(())
+}
+
+/// Render the first emails, or the emails to the selected receivers, as HTML
+/// files.
+fn preview_emails(
+    matches: &ArgMatches,
+    preview_dir: &Path,
+    emails: &[Email],
+) -> Result<(), anyhow::Error> {
+    let preview_count = arg::value(arg::PREVIEW_COUNT, matches)?
+        .parse::<usize>()
+        .context(format!(
+            "Invalid value for argument '{}'",
+            arg::PREVIEW_COUNT
+        ))?;
+    let selected_receivers = matches
+        .get_many::<String>(arg::PREVIEW_RECEIVER)
+        .map(|receivers| receivers.map(String::as_str).collect::<Vec<_>>());
+    let preview_formatter = PreviewFormatter::new(preview_dir)?;
+    let mut rendered = 0;
+
+    for (i, email) in emails
+        .iter()
+        .enumerate()
+        .filter(|(_, email)| {
+            selected_receivers.as_ref().map_or(true, |selected| {
+                email
+                    .receivers()
+                    .iter()
+                    .any(|receiver| selected.contains(&receiver.0))
+            })
+        })
+        .take(preview_count)
+    {
+        preview_formatter.preview(email, i + 1)?;
+        rendered += 1;
+    }
+
+    println!(
+        "Rendered {} of {} emails in '{}'",
+        rendered,
+        emails.len(),
+        preview_dir.display()
+    );
+
+    Ok(())
+}
+
+pub fn confirm_emails(
+    emails: &[Email],
+    duplicates: &[Duplicate],
+) -> Result<Confirmed, anyhow::Error> {
     let receivers = emails
         .iter()
-        .map(|email| email.receiver.as_ref())
+        .flat_map(|email| email.receivers())
+        .map(|receiver| receiver.0)
         .collect::<Vec<_>>();
+    let email_count = receivers.len();
     println!(
         "Preparing to send an email to {} recipients: {:#?}",
         email_count, receivers
     );
 
+    if !duplicates.is_empty() {
+        let duplicates = duplicates
+            .iter()
+            .map(|duplicate| duplicate.to_string())
+            .collect::<Vec<_>>();
+        println!(
+            "Skipping {} duplicate receivers: {:#?}",
+            duplicates.len(),
+            duplicates
+        );
+    }
+
     println!(
         "Should an email be sent to {} recipients? Yes (y) or no (n)",
         email_count
     );
-    let confirmation = loop {
-        io::stdin()
-            .read_line(&mut input)
-            .context("Can't read input")?;
-        match input.trim() {
-            "y" | "yes" | "Yes" => {
-                break Confirmed::Yes;
-            }
-            "n" | "no" | "No" => {
-                println!("Aborted ...");
-                break Confirmed::No;
-            }
-            _ => {
-                println!("Choose yes (y) or no (n). Try again.");
-                continue;
-            }
-        }
-    };
-    Ok(confirmation)
+
+    confirm()
 }
@@ -0,0 +1,25 @@
+use crate::{arg, email_transmission::SmtpSink, utils::format_green};
+use anyhow::Context;
+use clap::ArgMatches;
+use std::path::Path;
+
+pub fn smtp_sink(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    if matches.get_flag(arg::VERBOSE) {
+        println!("matches: {:#?}", matches);
+    }
+
+    let port = arg::value(arg::PORT, matches)?
+        .parse::<u16>()
+        .context(format!("Invalid value for argument '{}'", arg::PORT))?;
+    let target_dir = Path::new(arg::value(arg::DIR, matches)?);
+    let sink = SmtpSink::bind(port, target_dir)?;
+
+    println!(
+        "Starting SMTP sink on '{}' ... {}",
+        sink.local_addr()?,
+        format_green("ok")
+    );
+    println!("Saving caught emails to '{}'", target_dir.display());
+
+    sink.run()
+}
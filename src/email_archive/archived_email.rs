@@ -0,0 +1,296 @@
+use crate::{arg::val, email_builder::MimeFormat};
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use lettre::{address::Envelope, Address};
+use mailparse::{MailAddr, MailHeader, MailHeaderMap, ParsedMail};
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// An email in the archive.
+#[derive(Debug, Clone)]
+pub struct ArchivedEmail {
+    /// The id of the campaign, or `None` for emails archived without
+    /// campaign directory.
+    pub campaign: Option<String>,
+    /// The file the email is stored in.
+    pub path: PathBuf,
+    pub message: Vec<u8>,
+    pub message_id: Option<String>,
+    pub sender: String,
+    pub receivers: Vec<String>,
+    pub subject: String,
+    pub date: Option<DateTime<Utc>>,
+    pub dry_run: bool,
+    /// The status as stored in the index of the campaign.
+    pub status: Option<String>,
+    pub personalization: BTreeMap<String, String>,
+    /// The variant of the message as stored in the index of the campaign.
+    pub variant: Option<String>,
+    /// The transport the email was sent via as stored in the index of the
+    /// campaign.
+    pub transport: Option<String>,
+}
+
+impl ArchivedEmail {
+    pub fn parse(
+        message: Vec<u8>,
+        path: PathBuf,
+        campaign: Option<String>,
+        dry_run: bool,
+    ) -> Result<Self, anyhow::Error> {
+        let (headers, _) = mailparse::parse_headers(&message)
+            .context(format!("Can't parse email in '{}'", path.display()))?;
+        let message_id = headers.get_first_value("Message-ID");
+        let sender = addresses(&headers, "From")
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        let receivers = addresses(&headers, "To");
+        let subject = headers.get_first_value("Subject").unwrap_or_default();
+        let date = headers
+            .get_first_value("Date")
+            .and_then(|date| mailparse::dateparse(&date).ok())
+            .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0));
+
+        Ok(Self {
+            campaign,
+            path,
+            message,
+            message_id,
+            sender,
+            receivers,
+            subject,
+            date,
+            dry_run,
+            status: None,
+            personalization: BTreeMap::new(),
+            variant: None,
+            transport: None,
+        })
+    }
+
+    /// The status as stored in the index, or derived from the dry-run flag
+    /// for emails archived without index.
+    pub fn status(&self) -> &str {
+        match &self.status {
+            Some(status) => status,
+            None if self.dry_run => val::DRY_RUN,
+            None => "-",
+        }
+    }
+
+    /// The archived email, addressed to the receivers in the index, or to the
+    /// receivers in the `To` header for emails archived without index.
+    ///
+    /// The message is re-normalized, i.e. its line endings are converted to
+    /// CRLF, and mbox escaping has already been removed when reading the
+    /// archive. Otherwise, the message is unchanged.
+    pub fn mime_format(&self) -> Result<MimeFormat, anyhow::Error> {
+        let sender = self
+            .sender
+            .parse::<Address>()
+            .context(format!("Can't parse sender '{}'", self.sender))?;
+        let receivers = self
+            .receivers
+            .iter()
+            .map(|receiver| {
+                receiver
+                    .parse::<Address>()
+                    .context(format!("Can't parse receiver '{}'", receiver))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if receivers.is_empty() {
+            return Err(anyhow!(
+                "Missing receivers of archived email '{}'",
+                self.path.display()
+            ));
+        }
+
+        // Batched emails are addressed to the sender, and their receivers in
+        // Bcc are only stored in the index
+        if self.status.is_none() && receivers.iter().all(|receiver| *receiver == sender) {
+            return Err(anyhow!(
+                "Can't resend archived email '{}' without index entry, as it is addressed to its sender, e.g. because it was sent as batch",
+                self.path.display()
+            ));
+        }
+
+        let envelope = Envelope::new(Some(sender), receivers).context("Can't create envelope")?;
+        MimeFormat::from_raw(envelope, crlf(&self.message))
+    }
+
+    /// The leaf parts of the email with decoded bodies.
+    pub fn parts(&self) -> Result<Vec<Part>, anyhow::Error> {
+        parse_parts(&self.message)
+            .context(format!("Can't parse email in '{}'", self.path.display()))
+    }
+
+    /// The decoded text of all text parts.
+    pub fn text(&self) -> Result<String, anyhow::Error> {
+        let text = self
+            .parts()?
+            .into_iter()
+            .filter_map(|part| match part.body {
+                Body::Text(text) => Some(text),
+                Body::Binary(_) => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Ok(text)
+    }
+}
+
+/// Parse the leaf parts of a MIME message with decoded bodies.
+pub fn parse_parts(message: &[u8]) -> Result<Vec<Part>, anyhow::Error> {
+    let mail = mailparse::parse_mail(message).context("Can't parse email")?;
+    let mut parts = vec![];
+    collect_parts(&mail, &mut parts)?;
+    Ok(parts)
+}
+
+/// A leaf part of a MIME message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Part {
+    pub content_type: String,
+    pub file_name: Option<String>,
+    pub body: Body,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Body {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+fn collect_parts(mail: &ParsedMail, parts: &mut Vec<Part>) -> Result<(), anyhow::Error> {
+    if !mail.subparts.is_empty() {
+        for subpart in &mail.subparts {
+            collect_parts(subpart, parts)?;
+        }
+
+        return Ok(());
+    }
+
+    let content_type = mail.ctype.mimetype.to_lowercase();
+    let disposition = mail.get_content_disposition();
+    let file_name = disposition
+        .params
+        .get("filename")
+        .or_else(|| mail.ctype.params.get("name"))
+        .cloned();
+    let body = if content_type.starts_with("text/") && file_name.is_none() {
+        Body::Text(mail.get_body().context("Can't decode email body")?)
+    } else {
+        Body::Binary(mail.get_body_raw().context("Can't decode email body")?)
+    };
+
+    parts.push(Part {
+        content_type,
+        file_name,
+        body,
+    });
+
+    Ok(())
+}
+
+/// Restore CRLF line endings, e.g. of emails stored in mbox format.
+fn crlf(message: &[u8]) -> Vec<u8> {
+    let mut normalized = Vec::with_capacity(message.len());
+
+    for line in message.split_inclusive(|byte| *byte == b'\n') {
+        match line.strip_suffix(b"\n") {
+            Some(line) => {
+                normalized.extend_from_slice(line.strip_suffix(b"\r").unwrap_or(line));
+                normalized.extend_from_slice(b"\r\n");
+            }
+            None => normalized.extend_from_slice(line),
+        }
+    }
+
+    normalized
+}
+
+fn addresses(headers: &[MailHeader], key: &str) -> Vec<String> {
+    headers
+        .get_all_headers(key)
+        .into_iter()
+        .filter_map(|header| mailparse::addrparse_header(header).ok())
+        .flat_map(|addresses| {
+            addresses
+                .iter()
+                .flat_map(|address| match address {
+                    MailAddr::Single(info) => vec![info.addr.clone()],
+                    MailAddr::Group(group) => {
+                        group.addrs.iter().map(|info| info.addr.clone()).collect()
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archived_email_parse() {
+        let message = b"Message-ID: <1234@einstein.com>\r\nFrom: albert@einstein.com\r\nTo: Marie <marie@curie.com>, emmy@noether.com\r\nSubject: =?utf-8?q?Gr=C3=BC=C3=9Fe?=\r\nDate: Mon, 01 Jan 2024 14:00:00 +0000\r\nMIME-Version: 1.0\r\nContent-Type: multipart/mixed; boundary=\"b1\"\r\n\r\n--b1\r\nContent-Type: text/plain; charset=utf-8\r\n\r\nDear Marie\r\n--b1\r\nContent-Type: text/plain\r\nContent-Disposition: attachment; filename=\"test.txt\"\r\n\r\nabc\r\n--b1--\r\n";
+
+        let res = ArchivedEmail::parse(message.to_vec(), PathBuf::from("test.eml"), None, true);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let email = res.unwrap();
+        assert_eq!(email.message_id.as_deref(), Some("<1234@einstein.com>"));
+        assert_eq!(email.sender, "albert@einstein.com");
+        assert_eq!(email.receivers, vec!["marie@curie.com", "emmy@noether.com"]);
+        assert_eq!(email.subject, "Grüße");
+        assert_eq!(
+            email.date.unwrap().to_rfc3339(),
+            "2024-01-01T14:00:00+00:00"
+        );
+        assert_eq!(email.status(), "dry-run");
+
+        let parts = email.parts().unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].content_type, "text/plain");
+        assert_eq!(parts[0].body, Body::Text("Dear Marie\r\n".to_owned()));
+        assert_eq!(parts[1].file_name.as_deref(), Some("test.txt"));
+        assert_eq!(parts[1].body, Body::Binary(b"abc\r\n".to_vec()));
+        assert_eq!(email.text().unwrap(), "Dear Marie\r\n");
+    }
+
+    #[test]
+    fn test_archived_email_mime_format() {
+        let message = b"From: albert@einstein.com\nTo: marie@curie.com\nMessage-ID: <1234@einstein.com>\n\nDear Marie\r\n";
+
+        let mut email =
+            ArchivedEmail::parse(message.to_vec(), PathBuf::from("test.mbox"), None, false)
+                .unwrap();
+        let mime_format = email.mime_format().unwrap();
+        assert_eq!(
+            mime_format.formatted(),
+            b"From: albert@einstein.com\r\nTo: marie@curie.com\r\nMessage-ID: <1234@einstein.com>\r\n\r\nDear Marie\r\n"
+        );
+        assert_eq!(mime_format.message_id(), Some("<1234@einstein.com>"));
+        assert_eq!(
+            mime_format.envelope().to()[0].to_string(),
+            "marie@curie.com"
+        );
+
+        email.receivers = vec![];
+        assert!(email.mime_format().is_err());
+
+        // A batched email without index entry
+        email.receivers = vec!["albert@einstein.com".to_owned()];
+        let err = email.mime_format().unwrap_err();
+        assert!(err.to_string().contains("without index entry"), "{}", err);
+
+        email.status = Some("ok".to_owned());
+        assert_eq!(
+            email.mime_format().unwrap().envelope().to()[0].to_string(),
+            "albert@einstein.com"
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use super::ArchivedEmail;
+use crate::arg;
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use clap::ArgMatches;
+
+/// Filter for archived emails. Receivers, subject, and text are matched
+/// case-insensitively as substrings.
+#[derive(Debug, Default)]
+pub struct ArchiveFilter {
+    pub receiver: Option<String>,
+    pub subject: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub dry_run: Option<bool>,
+    /// Text in receivers, subject, or body.
+    pub text: Option<String>,
+}
+
+impl ArchiveFilter {
+    pub fn from_args(matches: &ArgMatches) -> Result<Self, anyhow::Error> {
+        let since = match matches.get_one::<String>(arg::SINCE) {
+            Some(since) => Some(parse_date(since, arg::SINCE, NaiveTime::MIN)?),
+            None => None,
+        };
+        let until = match matches.get_one::<String>(arg::UNTIL) {
+            Some(until) => Some(parse_date(until, arg::UNTIL, end_of_day())?),
+            None => None,
+        };
+        let dry_run = if matches.get_flag(arg::DRY_RUN) {
+            Some(true)
+        } else if matches.get_flag(arg::NO_DRY_RUN) {
+            Some(false)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            receiver: matches.get_one::<String>(arg::RECEIVER).cloned(),
+            subject: matches.get_one::<String>(arg::SUBJECT).cloned(),
+            since,
+            until,
+            dry_run,
+            text: None,
+        })
+    }
+
+    pub fn matches(&self, email: &ArchivedEmail) -> bool {
+        if let Some(receiver) = &self.receiver {
+            let receiver = receiver.to_lowercase();
+
+            if !email
+                .receivers
+                .iter()
+                .any(|email| email.to_lowercase().contains(&receiver))
+            {
+                return false;
+            }
+        }
+
+        if let Some(subject) = &self.subject {
+            if !email
+                .subject
+                .to_lowercase()
+                .contains(&subject.to_lowercase())
+            {
+                return false;
+            }
+        }
+
+        if self.since.is_some() || self.until.is_some() {
+            let Some(date) = email.date else {
+                return false;
+            };
+
+            if self.since.is_some_and(|since| date < since)
+                || self.until.is_some_and(|until| date > until)
+            {
+                return false;
+            }
+        }
+
+        if self.dry_run.is_some_and(|dry_run| dry_run != email.dry_run) {
+            return false;
+        }
+
+        if let Some(text) = &self.text {
+            let text = text.to_lowercase();
+            let is_match = email
+                .receivers
+                .iter()
+                .any(|receiver| receiver.to_lowercase().contains(&text))
+                || email.subject.to_lowercase().contains(&text)
+                || email
+                    .text()
+                    .is_ok_and(|body| body.to_lowercase().contains(&text));
+
+            if !is_match {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Parse a date (`2024-01-01`) at the given time, or a date time in RFC 3339
+/// format (`2024-01-01T14:00:00Z`).
+fn parse_date(value: &str, arg: &str, time: NaiveTime) -> Result<DateTime<Utc>, anyhow::Error> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_time(time).and_utc());
+    }
+
+    DateTime::parse_from_rfc3339(value)
+        .map(|date_time| date_time.with_timezone(&Utc))
+        .context(format!("Invalid value '{}' for argument '{}'", value, arg))
+}
+
+fn end_of_day() -> NaiveTime {
+    NaiveTime::from_hms_opt(23, 59, 59).expect("Invalid time")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn archived_email(dry_run: bool) -> ArchivedEmail {
+        let message = b"From: albert@einstein.com\r\nTo: marie@curie.com\r\nSubject: Test subject\r\nDate: Mon, 01 Jan 2024 14:00:00 +0000\r\n\r\nDear Marie\r\n";
+        ArchivedEmail::parse(message.to_vec(), PathBuf::from("test.eml"), None, dry_run).unwrap()
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let res = parse_date("2024-01-01", arg::UNTIL, end_of_day());
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        assert_eq!(res.unwrap().to_rfc3339(), "2024-01-01T23:59:59+00:00");
+
+        let res = parse_date("2024-01-01T14:00:00+01:00", arg::SINCE, NaiveTime::MIN);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        assert_eq!(res.unwrap().to_rfc3339(), "2024-01-01T13:00:00+00:00");
+
+        let res = parse_date("01.01.2024", arg::SINCE, NaiveTime::MIN);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_archive_filter() {
+        let email = archived_email(false);
+        assert!(ArchiveFilter::default().matches(&email));
+
+        let filter = ArchiveFilter {
+            receiver: Some("Curie".to_owned()),
+            subject: Some("test".to_owned()),
+            since: Some(parse_date("2024-01-01", arg::SINCE, NaiveTime::MIN).unwrap()),
+            until: Some(parse_date("2024-01-01", arg::UNTIL, end_of_day()).unwrap()),
+            dry_run: Some(false),
+            text: Some("dear MARIE".to_owned()),
+        };
+        assert!(filter.matches(&email));
+        assert!(!filter.matches(&archived_email(true)));
+
+        let filter = ArchiveFilter {
+            receiver: Some("noether".to_owned()),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&email));
+
+        let filter = ArchiveFilter {
+            since: Some(parse_date("2024-01-02", arg::SINCE, NaiveTime::MIN).unwrap()),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&email));
+
+        let filter = ArchiveFilter {
+            text: Some("emmy".to_owned()),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&email));
+    }
+}
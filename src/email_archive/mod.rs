@@ -0,0 +1,472 @@
+mod archived_email;
+mod filter;
+mod retention;
+
+use crate::{
+    email_feedback::mailbox,
+    email_formatter::{
+        ArchiveCompression, AttachmentStore, Campaign, IndexEntry, ATTACHMENT_DIR, CAMPAIGN_FILE,
+        DRY_RUN_FOLDER, FAILED_DIR, FAILED_FOLDER, INDEX_FILE,
+    },
+};
+use anyhow::{anyhow, Context};
+pub use archived_email::{parse_parts, ArchivedEmail, Body};
+pub use filter::ArchiveFilter;
+pub use retention::{expired, parse_age, remove, size};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Campaigns and emails in the archive directory.
+///
+/// Emails are read from `.eml` files (optionally compressed), mbox files, and
+/// Maildirs. Campaign directories are enriched by the campaign metadata and
+/// the index, and de-duplicated attachments are restored. Emails
+/// archived without campaign directory are read from the archive directory
+/// itself.
+#[derive(Debug)]
+pub struct Archive {
+    pub campaigns: Vec<Campaign>,
+    pub emails: Vec<ArchivedEmail>,
+}
+
+impl Archive {
+    /// Open the archive directory, a single campaign directory, or a single
+    /// archived file.
+    pub fn open(path: &Path) -> Result<Self, anyhow::Error> {
+        if path.is_file() {
+            return Self::open_file(path);
+        }
+
+        if !path.is_dir() {
+            return Err(anyhow!("Archive directory '{}' not found", path.display()));
+        }
+
+        if path.join(CAMPAIGN_FILE).is_file() {
+            let (campaign, emails) = read_campaign(path)?;
+            return Ok(Self {
+                campaigns: vec![campaign],
+                emails,
+            });
+        }
+
+        let mut campaigns = vec![];
+        let mut emails = read_emails(path, None)?;
+
+        for dir in sorted_paths(path)?
+            .into_iter()
+            .filter(|path| path.join(CAMPAIGN_FILE).is_file())
+        {
+            let (campaign, campaign_emails) = read_campaign(&dir)?;
+            campaigns.push(campaign);
+            emails.extend(campaign_emails);
+        }
+
+        Ok(Self { campaigns, emails })
+    }
+
+    fn open_file(path: &Path) -> Result<Self, anyhow::Error> {
+        let mut dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+
+        // Failed emails are stored in a subdirectory of the campaign
+        if dir.ends_with(FAILED_DIR) {
+            dir = dir.parent().unwrap_or(dir);
+        }
+
+        // Look up the file in the index of the campaign
+        if dir.join(CAMPAIGN_FILE).is_file() {
+            let mut archive = Self::open(dir)?;
+            archive
+                .emails
+                .retain(|email| email.path.file_name() == path.file_name());
+            return Ok(archive);
+        }
+
+        let content = read_file(path)?;
+        let messages = if mailbox::is_mbox(&content) {
+            mailbox::split_mbox(&content)
+        } else {
+            vec![content]
+        };
+        let dry_run = path.to_string_lossy().contains("_dry-run");
+        let emails = messages
+            .into_iter()
+            .map(|message| ArchivedEmail::parse(message, path.to_owned(), None, dry_run))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            campaigns: vec![],
+            emails,
+        })
+    }
+
+    /// Find an email by Message-ID (with or without angle brackets) or by
+    /// the path of an `.eml` file.
+    pub fn find(&self, id: &str) -> Option<&ArchivedEmail> {
+        let message_id = id.trim_start_matches('<').trim_end_matches('>');
+
+        self.emails.iter().find(|email| {
+            email
+                .message_id
+                .as_deref()
+                .is_some_and(|id| id.trim_start_matches('<').trim_end_matches('>') == message_id)
+                || email.path.ends_with(id)
+        })
+    }
+}
+
+/// Read emails from `.eml` files, mbox files, and the Maildir in a directory.
+fn read_emails(
+    dir: &Path,
+    campaign: Option<&Campaign>,
+) -> Result<Vec<ArchivedEmail>, anyhow::Error> {
+    let campaign_id = campaign.map(|campaign| campaign.id.clone());
+    let mut emails = vec![];
+
+    for path in sorted_paths(dir)?.into_iter().filter(|path| path.is_file()) {
+        let dry_run = campaign.map_or_else(
+            || path.to_string_lossy().contains("_dry-run"),
+            |campaign| campaign.dry_run,
+        );
+        // The extension of the archived file without the extension of the
+        // compression, e.g. `eml` for `.eml.gz`
+        let extension = match ArchiveCompression::from_path(&path) {
+            Some(_) => path.with_extension(""),
+            None => path.clone(),
+        }
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_owned);
+        let messages = match extension.as_deref() {
+            Some("eml") => vec![read_file(&path)?],
+            Some("mbox") => mailbox::split_mbox(&read_file(&path)?),
+            _ => continue,
+        };
+
+        for message in messages {
+            emails.push(ArchivedEmail::parse(
+                message,
+                path.clone(),
+                campaign_id.clone(),
+                dry_run,
+            )?);
+        }
+    }
+
+    for (maildir, dry_run) in [(dir.to_owned(), false), (dir.join(DRY_RUN_FOLDER), true)] {
+        if !mailbox::is_maildir(&maildir) {
+            continue;
+        }
+
+        let dry_run = campaign.map_or(dry_run, |campaign| campaign.dry_run);
+
+        for message in mailbox::read_maildir(&maildir)? {
+            emails.push(ArchivedEmail::parse(
+                message,
+                maildir.clone(),
+                campaign_id.clone(),
+                dry_run,
+            )?);
+        }
+    }
+
+    Ok(emails)
+}
+
+/// Read the emails of a campaign directory enriched by the index.
+fn read_campaign(dir: &Path) -> Result<(Campaign, Vec<ArchivedEmail>), anyhow::Error> {
+    let path = dir.join(CAMPAIGN_FILE);
+    let campaign =
+        fs::read_to_string(&path).context(format!("Can't read file '{}'", path.display()))?;
+    let campaign = serde_json::from_str::<Campaign>(&campaign)
+        .context(format!("Can't parse campaign in '{}'", path.display()))?;
+    let index = read_index(dir)?;
+    let mut emails = read_emails(dir, Some(&campaign))?;
+
+    for failed_dir in [dir.join(FAILED_DIR), dir.join(FAILED_FOLDER)] {
+        if failed_dir.is_dir() {
+            emails.extend(read_emails(&failed_dir, Some(&campaign))?);
+        }
+    }
+
+    let attachment_dir = dir.join(ATTACHMENT_DIR);
+    let attachment_store = attachment_dir
+        .is_dir()
+        .then(|| AttachmentStore::new(&attachment_dir));
+
+    for email in emails.iter_mut() {
+        if let Some(attachment_store) = &attachment_store {
+            email.message = attachment_store
+                .restore(&email.message)
+                .context(format!("Can't restore email in '{}'", email.path.display()))?;
+        }
+
+        if let Some(entry) = email
+            .message_id
+            .as_ref()
+            .and_then(|message_id| index.get(message_id))
+        {
+            email.receivers = entry.receivers.clone();
+            email.status = Some(entry.status.clone());
+            email.personalization = entry.personalization.clone();
+            email.variant = entry.variant.clone();
+            email.transport = entry.transport.clone();
+        }
+    }
+
+    Ok((campaign, emails))
+}
+
+/// Read the index of a campaign by Message-ID.
+fn read_index(dir: &Path) -> Result<HashMap<String, IndexEntry>, anyhow::Error> {
+    let path = dir.join(INDEX_FILE);
+    let mut index = HashMap::new();
+
+    if !path.is_file() {
+        return Ok(index);
+    }
+
+    let content =
+        fs::read_to_string(&path).context(format!("Can't read file '{}'", path.display()))?;
+
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        let entry = serde_json::from_str::<IndexEntry>(line)
+            .context(format!("Can't parse index in '{}'", path.display()))?;
+
+        if let Some(message_id) = &entry.message_id {
+            index.insert(message_id.clone(), entry);
+        }
+    }
+
+    Ok(index)
+}
+
+/// Read a file, and decompress it depending on its extension.
+fn read_file(path: &Path) -> Result<Vec<u8>, anyhow::Error> {
+    let content = fs::read(path).context(format!("Can't read file '{}'", path.display()))?;
+
+    match ArchiveCompression::from_path(path) {
+        Some(compression) => compression
+            .decompress(&content)
+            .context(format!("Can't read file '{}'", path.display())),
+        None => Ok(content),
+    }
+}
+
+fn sorted_paths(dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut paths = fs::read_dir(dir)
+        .context(format!("Can't read directory '{}'", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .context(format!("Can't read directory '{}'", dir.display()))?;
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        email_builder::{Attachment, Email, Message, MimeFormat, Receiver, Sender},
+        email_formatter::{ArchiveFormat, ArchiveOptions, CampaignArchive, EmlFormatter, Source},
+        email_transmission::{SentEmail, Status},
+    };
+    use chrono::{DateTime, Utc};
+    use std::time::SystemTime;
+    use tempfile::tempdir;
+
+    fn create_email<'a>(receiver: &'a str, message: &Message) -> Email<'a> {
+        let sender = Sender("albert@einstein.com");
+        let receiver = Receiver(receiver);
+        let mime_format =
+            MimeFormat::new(sender, receiver, message, &[], SystemTime::now()).unwrap();
+        Email::new(sender, receiver, message, &mime_format).unwrap()
+    }
+
+    fn archive_options(format: ArchiveFormat) -> ArchiveOptions {
+        ArchiveOptions {
+            format,
+            compression: None,
+            dedup_attachments: false,
+        }
+    }
+
+    fn archive_campaign(
+        archive_dir: &Path,
+        archive_format: ArchiveFormat,
+        dry_run: bool,
+        now: DateTime<Utc>,
+    ) {
+        let message = Message::new("Test subject", Some("This is a test message."), None);
+        let archive_options = archive_options(archive_format);
+        let campaign = Campaign::new(
+            Sender("albert@einstein.com"),
+            &message,
+            Source::Argument,
+            &archive_options,
+            dry_run,
+            now,
+        );
+        let campaign_archive =
+            CampaignArchive::new(archive_dir, &archive_options, &campaign).unwrap();
+
+        for receiver in ["marie@curie.com", "emmy@noether.com"] {
+            let email = create_email(receiver, &message);
+            let status = if dry_run {
+                Status::DryRun
+            } else if receiver == "emmy@noether.com" {
+                Status::SentError("550 mailbox unavailable".to_owned())
+            } else {
+                Status::SentOk("250 OK".to_owned())
+            };
+            campaign_archive
+                .archive(&email, &SentEmail::new(&email, status), now)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_archive_open() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path();
+        let now = Utc::now();
+
+        archive_campaign(temp_path, ArchiveFormat::Mbox, false, now);
+        archive_campaign(temp_path, ArchiveFormat::Maildir, true, now);
+        archive_campaign(
+            temp_path,
+            ArchiveFormat::Maildir,
+            false,
+            now + chrono::Duration::seconds(1),
+        );
+
+        // Email archived without campaign directory
+        let message = Message::new("Legacy subject", Some("This is a test message."), None);
+        let email = create_email("pierre@curie.com", &message);
+        EmlFormatter::new(temp_path, ArchiveFormat::Eml, None)
+            .unwrap()
+            .archive(&email, now, true)
+            .unwrap();
+
+        let res = Archive::open(temp_path);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let archive = res.unwrap();
+        assert_eq!(archive.campaigns.len(), 3);
+        assert_eq!(archive.emails.len(), 7);
+
+        let legacy = &archive.emails[0];
+        assert_eq!(legacy.campaign, None);
+        assert_eq!(legacy.subject, "Legacy subject");
+        assert_eq!(legacy.status(), "dry-run");
+
+        let sent = archive
+            .emails
+            .iter()
+            .filter(|email| email.status.as_deref() == Some("ok"))
+            .collect::<Vec<_>>();
+        assert_eq!(sent.len(), 2);
+        assert!(sent.iter().all(|email| !email.dry_run));
+
+        let failed = archive
+            .emails
+            .iter()
+            .filter(|email| email.status.as_deref() == Some("failed"))
+            .collect::<Vec<_>>();
+        assert_eq!(failed.len(), 2);
+        assert!(failed
+            .iter()
+            .all(|email| email.receivers == vec!["emmy@noether.com"]));
+
+        let dry_run = archive
+            .emails
+            .iter()
+            .filter(|email| email.status.as_deref() == Some("dry-run"))
+            .collect::<Vec<_>>();
+        assert_eq!(dry_run.len(), 2);
+        assert!(dry_run.iter().all(|email| email.dry_run));
+
+        let message_id = sent[0].message_id.as_deref().unwrap();
+        let found = archive.find(message_id.trim_matches(['<', '>']));
+        assert_eq!(found.unwrap().message_id.as_deref(), Some(message_id));
+        assert!(archive.find("<unknown@einstein.com>").is_none());
+    }
+
+    #[test]
+    fn test_archive_open_compressed_dedup() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path();
+        let now = Utc::now();
+
+        let message = Message::new("Test subject", Some("This is a test message."), None);
+        let archive_options = ArchiveOptions {
+            compression: Some(ArchiveCompression::Zstd),
+            dedup_attachments: true,
+            ..archive_options(ArchiveFormat::Eml)
+        };
+        let sender = Sender("albert@einstein.com");
+        let campaign = Campaign::new(
+            sender,
+            &message,
+            Source::Argument,
+            &archive_options,
+            false,
+            now,
+        );
+        let campaign_archive =
+            CampaignArchive::new(temp_path, &archive_options, &campaign).unwrap();
+
+        let mut formatted = vec![];
+
+        for receiver in ["marie@curie.com", "emmy@noether.com"] {
+            let receiver = Receiver(receiver);
+            let attachment = Attachment::new(Path::new("./test_data/test.pdf")).unwrap();
+            let mime_format =
+                MimeFormat::new(sender, receiver, &message, &[attachment], SystemTime::now())
+                    .unwrap();
+            let email = Email::new(sender, receiver, &message, &mime_format).unwrap();
+            let status = Status::SentOk("250 OK".to_owned());
+            campaign_archive
+                .archive(&email, &SentEmail::new(&email, status), now)
+                .unwrap();
+            formatted.push(mime_format.formatted().to_vec());
+        }
+
+        let campaign_dir = temp_path.join(&campaign.id);
+        assert_eq!(
+            fs::read_dir(campaign_dir.join(ATTACHMENT_DIR))
+                .unwrap()
+                .count(),
+            1
+        );
+
+        let res = Archive::open(temp_path);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let archive = res.unwrap();
+        assert_eq!(archive.emails.len(), 2);
+
+        for email in &archive.emails {
+            assert!(email.path.to_str().unwrap().ends_with(".eml.zst"));
+            assert!(formatted.contains(&email.message));
+            assert_eq!(email.status(), "ok");
+        }
+
+        // A single archived file is restored as well
+        let res = Archive::open(&archive.emails[0].path);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        assert_eq!(res.unwrap().emails[0].message, archive.emails[0].message);
+    }
+
+    #[test]
+    fn test_archive_open_missing() {
+        let temp_dir = tempdir().unwrap();
+        let res = Archive::open(&temp_dir.path().join("missing"));
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::email_formatter::{ArchiveCompression, Campaign, CAMPAIGN_FILE};
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Duration, Utc};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Parse an age in seconds (`s`), minutes (`m`), hours (`h`), days (`d`), or
+/// weeks (`w`), e.g. `90d`.
+pub fn parse_age(value: &str) -> Result<Duration, anyhow::Error> {
+    let invalid = || anyhow!("Invalid age '{}', expected e.g. '90d'", value);
+    let value = value.trim();
+    let unit_index = value
+        .find(|char: char| !char.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (amount, unit) = value.split_at(unit_index);
+    let amount = amount.parse::<i64>().map_err(|_| invalid())?;
+
+    let age = match unit {
+        "s" => Duration::try_seconds(amount),
+        "m" => Duration::try_minutes(amount),
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        _ => None,
+    };
+
+    age.ok_or_else(invalid)
+}
+
+/// Campaign directories created before the cutoff, and emails archived
+/// without campaign directory which were last modified before the cutoff.
+pub fn expired(archive_dir: &Path, cutoff: DateTime<Utc>) -> Result<Vec<PathBuf>, anyhow::Error> {
+    if !archive_dir.is_dir() {
+        return Err(anyhow!(
+            "Archive directory '{}' not found",
+            archive_dir.display()
+        ));
+    }
+
+    let mut paths = fs::read_dir(archive_dir)
+        .context(format!("Can't read directory '{}'", archive_dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .context(format!("Can't read directory '{}'", archive_dir.display()))?;
+    paths.sort();
+
+    let mut expired = vec![];
+
+    for path in paths {
+        let campaign_file = path.join(CAMPAIGN_FILE);
+
+        let created_at = if campaign_file.is_file() {
+            let campaign = fs::read_to_string(&campaign_file)
+                .context(format!("Can't read file '{}'", campaign_file.display()))?;
+            let campaign = serde_json::from_str::<Campaign>(&campaign).context(format!(
+                "Can't parse campaign in '{}'",
+                campaign_file.display()
+            ))?;
+            DateTime::parse_from_rfc3339(&campaign.created_at)
+                .context(format!(
+                    "Can't parse creation date in '{}'",
+                    campaign_file.display()
+                ))?
+                .with_timezone(&Utc)
+        } else if path.is_file() && is_archived_file(&path) {
+            let modified = fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .context(format!("Can't read metadata of '{}'", path.display()))?;
+            DateTime::<Utc>::from(modified)
+        } else {
+            continue;
+        };
+
+        if created_at < cutoff {
+            expired.push(path);
+        }
+    }
+
+    Ok(expired)
+}
+
+/// The size of a file, or of all files in a directory, in bytes.
+pub fn size(path: &Path) -> Result<u64, anyhow::Error> {
+    let metadata =
+        fs::metadata(path).context(format!("Can't read metadata of '{}'", path.display()))?;
+
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut size = 0;
+
+    for entry in fs::read_dir(path).context(format!("Can't read directory '{}'", path.display()))? {
+        let entry = entry.context(format!("Can't read directory '{}'", path.display()))?;
+        size += self::size(&entry.path())?;
+    }
+
+    Ok(size)
+}
+
+/// Delete a campaign directory or an archived file.
+pub fn remove(path: &Path) -> Result<(), anyhow::Error> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+    .context(format!("Can't delete '{}'", path.display()))
+}
+
+fn is_archived_file(path: &Path) -> bool {
+    let path = match ArchiveCompression::from_path(path) {
+        Some(_) => path.with_extension(""),
+        None => path.to_owned(),
+    };
+
+    matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("eml" | "mbox")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn write_campaign(archive_dir: &Path, created_at: &str) -> PathBuf {
+        let dir = archive_dir.join(created_at);
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("test.eml"), "Subject: Test subject\r\n\r\n").unwrap();
+        fs::write(
+            dir.join(CAMPAIGN_FILE),
+            format!(
+                r#"{{"id":"{0}","created_at":"{0}","dry_run":false,"sender":"albert@einstein.com","subject":"Test subject","source":"argument","archive_format":"eml","archive_compression":null}}"#,
+                created_at
+            ),
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("90d").unwrap(), Duration::days(90));
+        assert_eq!(parse_age("12h").unwrap(), Duration::hours(12));
+        assert_eq!(parse_age("2w").unwrap(), Duration::weeks(2));
+        assert!(parse_age("90").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("90y").is_err());
+    }
+
+    #[test]
+    fn test_expired() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path();
+
+        let old = write_campaign(temp_path, "2024-01-01T14:00:00Z");
+        let new = write_campaign(temp_path, "2024-06-01T14:00:00Z");
+        fs::write(temp_path.join("legacy.eml.gz"), "").unwrap();
+        fs::write(temp_path.join("notes.txt"), "").unwrap();
+
+        let cutoff = DateTime::parse_from_rfc3339("2024-03-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let res = expired(temp_path, cutoff);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        assert_eq!(res.unwrap(), vec![old.clone()]);
+
+        // The legacy file was just modified
+        let res = expired(temp_path, Utc::now() + Duration::days(1)).unwrap();
+        assert_eq!(res, vec![old.clone(), new, temp_path.join("legacy.eml.gz")]);
+
+        assert!(size(&old).unwrap() > 0);
+        remove(&old).unwrap();
+        assert!(!old.exists());
+    }
+}
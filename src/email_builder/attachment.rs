@@ -0,0 +1,248 @@
+use crate::arg;
+use anyhow::{anyhow, Context};
+use clap::ArgMatches;
+use lettre::message::{header, Body, SinglePart};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// An attachment as specified in the message file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AttachmentFile {
+    pub path: PathBuf,
+    /// The file name presented to the receivers instead of the name of the
+    /// file.
+    pub name: Option<String>,
+    /// The content type instead of the detected one, e.g. `text/csv`.
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub disposition: Disposition,
+}
+
+impl AttachmentFile {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+            name: None,
+            content_type: None,
+            disposition: Disposition::default(),
+        }
+    }
+}
+
+/// Whether the attachment is displayed as separate file or inline in the
+/// message.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Disposition {
+    #[default]
+    Attachment,
+    Inline,
+}
+
+/// An attachment which is read, type-detected, and encoded once, and then
+/// shared by all emails.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    file_name: String,
+    content_type: header::ContentType,
+    disposition: Disposition,
+    body: Body,
+}
+
+impl Attachment {
+    pub fn new(path: &Path) -> Result<Self, anyhow::Error> {
+        Self::from_file(&AttachmentFile::new(path))
+    }
+
+    pub fn from_file(file: &AttachmentFile) -> Result<Self, anyhow::Error> {
+        let path = &file.path;
+        let file_name = match (&file.name, path.file_name()) {
+            (Some(name), _) => name.to_owned(),
+            // Non-UTF-8 file names are replaced lossily; use `name` to choose
+            // the presented file name
+            (None, Some(file_name)) => file_name.to_string_lossy().into_owned(),
+            (None, None) => {
+                return Err(anyhow!(
+                    "Can't find file name of attachment '{}'",
+                    path.display()
+                ))
+            }
+        };
+        let bytes =
+            fs::read(path).context(format!("Can't read attachment '{}'", path.display()))?;
+        let content_type = match &file.content_type {
+            Some(content_type) => content_type.as_str(),
+            None => match infer::get(&bytes) {
+                Some(file_type) => file_type.mime_type(),
+                // Text files can't be detected by their content
+                None => content_type_by_extension(path),
+            },
+        };
+        let content_type = header::ContentType::parse(content_type).context(format!(
+            "File type '{}' not supported: {}",
+            content_type, file_name
+        ))?;
+
+        Ok(Self {
+            file_name,
+            content_type,
+            disposition: file.disposition,
+            body: Body::new(bytes),
+        })
+    }
+
+    /// The attachments given by the message file and by argument.
+    pub fn from_args(
+        matches: &ArgMatches,
+        attachment_files: &[AttachmentFile],
+    ) -> Result<Vec<Self>, anyhow::Error> {
+        Self::from_files(
+            attachment_files,
+            matches.get_one::<String>(arg::ATTACHMENT).map(Path::new),
+        )
+    }
+
+    /// The attachments given by the message file and the optional attachment
+    /// given by argument.
+    pub fn from_files(
+        attachment_files: &[AttachmentFile],
+        attachment: Option<&Path>,
+    ) -> Result<Vec<Self>, anyhow::Error> {
+        let mut attachments = attachment_files
+            .iter()
+            .map(Self::from_file)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(path) = attachment {
+            attachments.push(Self::new(path)?);
+        }
+
+        Ok(attachments)
+    }
+
+    /// The size of the encoded attachment in bytes.
+    pub fn encoded_len(&self) -> usize {
+        self.body.len()
+    }
+
+    pub fn singlepart(&self) -> SinglePart {
+        // Non-ASCII file names are encoded according to RFC 2231
+        let content_disposition = match self.disposition {
+            Disposition::Attachment => header::ContentDisposition::attachment(&self.file_name),
+            Disposition::Inline => header::ContentDisposition::inline_with_name(&self.file_name),
+        };
+
+        SinglePart::builder()
+            .header(self.content_type.clone())
+            .header(content_disposition)
+            .body(self.body.clone())
+    }
+}
+
+/// Guess the content type of files which `infer` can't detect, e.g. text
+/// files. Compare internet standard RFC-2046, RFC-7231, and https://stackoverflow.com/questions/1176022/unknown-file-type-mime
+fn content_type_by_extension(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+
+    match extension.as_deref() {
+        Some("txt") => "text/plain",
+        Some("csv") => "text/csv",
+        Some("ics") => "text/calendar",
+        Some("vcf") => "text/vcard",
+        Some("htm" | "html") => "text/html",
+        Some("md") => "text/markdown",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_attachment() {
+        let res = Attachment::new(Path::new("./test_data/test.pdf"));
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let attachment = res.unwrap();
+        assert_eq!(attachment.file_name, "test.pdf");
+        assert_eq!(
+            attachment.content_type,
+            header::ContentType::parse("application/pdf").unwrap()
+        );
+
+        // Base64 encoded with line breaks
+        let size = fs::metadata("./test_data/test.pdf").unwrap().len() as usize;
+        assert!(attachment.encoded_len() > size * 4 / 3);
+
+        let singlepart = String::from_utf8(attachment.singlepart().formatted()).unwrap();
+        assert!(singlepart.contains("Content-Type: application/pdf"));
+        assert!(singlepart.contains("Content-Disposition: attachment; filename=\"test.pdf\""));
+        assert!(singlepart.contains("Content-Transfer-Encoding: base64"));
+    }
+
+    #[test]
+    fn test_attachment_missing() {
+        assert!(Attachment::new(Path::new("./test_data/missing.pdf")).is_err());
+    }
+
+    #[test]
+    fn test_attachment_content_type_by_extension() {
+        let temp_dir = tempdir().unwrap();
+
+        for (file_name, content_type) in [
+            ("test.txt", "text/plain"),
+            ("receivers.CSV", "text/csv"),
+            ("invite.ics", "text/calendar"),
+            ("test.unknown", "application/octet-stream"),
+        ] {
+            let path = temp_dir.path().join(file_name);
+            fs::write(&path, "BEGIN").unwrap();
+
+            let attachment = Attachment::new(&path).unwrap();
+            assert_eq!(
+                attachment.content_type,
+                header::ContentType::parse(content_type).unwrap(),
+                "{}",
+                file_name
+            );
+        }
+    }
+
+    #[test]
+    fn test_attachment_from_file() {
+        let file = AttachmentFile {
+            path: PathBuf::from("./test_data/test.txt"),
+            name: Some("Prüfbericht März.txt".to_owned()),
+            content_type: Some("text/plain; charset=utf-8".to_owned()),
+            disposition: Disposition::Inline,
+        };
+
+        let res = Attachment::from_file(&file);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let attachment = res.unwrap();
+        assert_eq!(attachment.file_name, "Prüfbericht März.txt");
+
+        let singlepart = String::from_utf8(attachment.singlepart().formatted()).unwrap();
+        assert!(singlepart.contains("Content-Type: text/plain; charset=utf-8"));
+        assert!(singlepart.contains(
+            "Content-Disposition: inline;\r\n filename*0*=utf-8''Pr%C3%BCfbericht%20M%C3%A4rz.txt"
+        ));
+
+        let file = AttachmentFile {
+            content_type: Some("no content type".to_owned()),
+            ..file
+        };
+        assert!(Attachment::from_file(&file).is_err());
+    }
+}
@@ -0,0 +1,311 @@
+use crate::arg::{self, val};
+use anyhow::anyhow;
+use clap::ArgMatches;
+use std::{collections::HashMap, fmt, str::FromStr};
+
+/// Which row is kept if the same receiver occurs in several rows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DuplicatePolicy {
+    KeepFirst,
+    KeepLast,
+    Fail,
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            val::FIRST => Ok(Self::KeepFirst),
+            val::LAST => Ok(Self::KeepLast),
+            val::FAIL => Ok(Self::Fail),
+            other => Err(anyhow!(
+                "Value '{}' for argument '{}' not supported",
+                other,
+                arg::DUPLICATES
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DuplicatePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let policy = match self {
+            Self::KeepFirst => val::FIRST,
+            Self::KeepLast => val::LAST,
+            Self::Fail => val::FAIL,
+        };
+
+        write!(f, "{}", policy)
+    }
+}
+
+/// A receiver which occurs in several rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Duplicate {
+    /// The normalized email address.
+    pub receiver: String,
+    /// The positions of the rows, starting at zero.
+    pub rows: Vec<usize>,
+    /// The position of the row which is kept.
+    pub kept_row: usize,
+}
+
+impl Duplicate {
+    /// The positions of the rows which are skipped.
+    pub fn skipped_rows(&self) -> impl Iterator<Item = usize> + '_ {
+        self.rows
+            .iter()
+            .copied()
+            .filter(|&row| row != self.kept_row)
+    }
+}
+
+/// Displays the rows numbered from one, as in the receiver file without
+/// header.
+impl fmt::Display for Duplicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| (row + 1).to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        write!(
+            f,
+            "{} (rows {}, keeping row {})",
+            self.receiver,
+            rows,
+            self.kept_row + 1
+        )
+    }
+}
+
+/// Detection of receivers which occur in several rows. Email addresses are
+/// compared case-insensitively, and optionally without the dots and the
+/// `+tag` of Gmail addresses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deduplication {
+    pub policy: DuplicatePolicy,
+    pub normalize_gmail: bool,
+}
+
+impl Deduplication {
+    pub fn new(policy: DuplicatePolicy, normalize_gmail: bool) -> Self {
+        Self {
+            policy,
+            normalize_gmail,
+        }
+    }
+
+    pub fn from_args(matches: &ArgMatches) -> Result<Self, anyhow::Error> {
+        let policy = arg::value(arg::DUPLICATES, matches)?.parse::<DuplicatePolicy>()?;
+
+        Ok(Self::new(policy, matches.get_flag(arg::NORMALIZE_GMAIL)))
+    }
+
+    /// The email address which identifies the receiver, e.g. `marie@curie.com`
+    /// for `Marie Curie <Marie@Curie.com>`.
+    pub fn normalize(&self, receiver: &str) -> String {
+        let address = match receiver.rsplit_once('<') {
+            Some((_, address)) => address.trim_end_matches('>'),
+            None => receiver,
+        };
+        let address = address.trim().to_lowercase();
+
+        if !self.normalize_gmail {
+            return address;
+        }
+
+        match address.rsplit_once('@') {
+            Some((local_part, "gmail.com" | "googlemail.com")) => {
+                let local_part = local_part
+                    .split('+')
+                    .next()
+                    .unwrap_or_default()
+                    .replace('.', "");
+                format!("{}@gmail.com", local_part)
+            }
+            _ => address,
+        }
+    }
+
+    /// Find the receivers which occur in several rows, in the order of their
+    /// first row. Rows without receiver are ignored. Fails for the policy
+    /// `fail` if there are duplicates.
+    pub fn find<'a>(
+        &self,
+        receivers: impl IntoIterator<Item = Option<&'a str>>,
+    ) -> Result<Vec<Duplicate>, anyhow::Error> {
+        let mut rows_by_receiver = HashMap::<String, Vec<usize>>::new();
+        let mut receivers_in_order = vec![];
+
+        for (row, receiver) in receivers.into_iter().enumerate() {
+            if let Some(receiver) = receiver {
+                let receiver = self.normalize(receiver);
+                let rows = rows_by_receiver.entry(receiver.clone()).or_default();
+
+                if rows.is_empty() {
+                    receivers_in_order.push(receiver);
+                }
+
+                rows.push(row);
+            }
+        }
+
+        let duplicates = receivers_in_order
+            .into_iter()
+            .filter_map(|receiver| {
+                let rows = rows_by_receiver.remove(&receiver)?;
+
+                if rows.len() < 2 {
+                    return None;
+                }
+
+                let kept_row = match self.policy {
+                    DuplicatePolicy::KeepLast => rows[rows.len() - 1],
+                    DuplicatePolicy::KeepFirst | DuplicatePolicy::Fail => rows[0],
+                };
+
+                Some(Duplicate {
+                    receiver,
+                    rows,
+                    kept_row,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if self.policy == DuplicatePolicy::Fail && !duplicates.is_empty() {
+            let duplicates = duplicates
+                .iter()
+                .map(|duplicate| duplicate.to_string())
+                .collect::<Vec<_>>()
+                .join("; ");
+            return Err(anyhow!("Found duplicate receivers: {}", duplicates));
+        }
+
+        Ok(duplicates)
+    }
+}
+
+impl Default for Deduplication {
+    fn default() -> Self {
+        Self::new(DuplicatePolicy::KeepFirst, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_policy_from_str() {
+        assert_eq!(
+            "first".parse::<DuplicatePolicy>().unwrap(),
+            DuplicatePolicy::KeepFirst
+        );
+        assert_eq!(
+            "Last".parse::<DuplicatePolicy>().unwrap(),
+            DuplicatePolicy::KeepLast
+        );
+        assert_eq!(
+            "fail".parse::<DuplicatePolicy>().unwrap(),
+            DuplicatePolicy::Fail
+        );
+        assert!("none".parse::<DuplicatePolicy>().is_err());
+    }
+
+    #[test]
+    fn test_normalize() {
+        let dedup = Deduplication::default();
+        assert_eq!(dedup.normalize(" Marie@Curie.com "), "marie@curie.com");
+        assert_eq!(
+            dedup.normalize("Marie Curie <Marie@Curie.com>"),
+            "marie@curie.com"
+        );
+        assert_eq!(
+            dedup.normalize("marie.curie+news@gmail.com"),
+            "marie.curie+news@gmail.com"
+        );
+
+        let dedup = Deduplication::new(DuplicatePolicy::KeepFirst, true);
+        assert_eq!(
+            dedup.normalize("Marie.Curie+news@gmail.com"),
+            "mariecurie@gmail.com"
+        );
+        assert_eq!(
+            dedup.normalize("marie.curie@googlemail.com"),
+            "mariecurie@gmail.com"
+        );
+        assert_eq!(
+            dedup.normalize("marie.curie+news@curie.com"),
+            "marie.curie+news@curie.com"
+        );
+    }
+
+    #[test]
+    fn test_find() {
+        let receivers = [
+            Some("marie@curie.com"),
+            Some("emmy@noether.com"),
+            None,
+            Some("Marie@Curie.com"),
+            Some("emmy@noether.com"),
+            Some("marie@curie.com"),
+            Some("lise@meitner.com"),
+        ];
+
+        let dedup = Deduplication::default();
+        let duplicates = dedup.find(receivers).unwrap();
+        assert_eq!(
+            duplicates,
+            vec![
+                Duplicate {
+                    receiver: "marie@curie.com".to_owned(),
+                    rows: vec![0, 3, 5],
+                    kept_row: 0,
+                },
+                Duplicate {
+                    receiver: "emmy@noether.com".to_owned(),
+                    rows: vec![1, 4],
+                    kept_row: 1,
+                },
+            ]
+        );
+        assert_eq!(duplicates[0].skipped_rows().collect::<Vec<_>>(), vec![3, 5]);
+        assert_eq!(
+            duplicates[0].to_string(),
+            "marie@curie.com (rows 1, 4, 6, keeping row 1)"
+        );
+
+        let dedup = Deduplication::new(DuplicatePolicy::KeepLast, false);
+        let duplicates = dedup.find(receivers).unwrap();
+        assert_eq!(duplicates[0].kept_row, 5);
+        assert_eq!(duplicates[1].kept_row, 4);
+
+        let dedup = Deduplication::new(DuplicatePolicy::Fail, false);
+        assert!(dedup.find(receivers).is_err());
+        assert!(dedup
+            .find([Some("marie@curie.com"), Some("emmy@noether.com")])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_find_gmail() {
+        let receivers = [
+            Some("marie.curie@gmail.com"),
+            Some("mariecurie+news@gmail.com"),
+        ];
+
+        assert!(Deduplication::default().find(receivers).unwrap().is_empty());
+        assert_eq!(
+            Deduplication::new(DuplicatePolicy::KeepFirst, true)
+                .find(receivers)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
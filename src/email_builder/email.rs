@@ -1,14 +1,23 @@
-use super::{BulkReceiver, Receiver, Sender};
+use super::{Attachment, BulkReceiver, MessageColumns, Receiver, Sender};
 use crate::email_builder::{Message, MimeFormat};
-use anyhow::Result;
-use std::{path::Path, time::SystemTime};
+use anyhow::{anyhow, Result};
+use std::{collections::BTreeMap, time::SystemTime};
 
 #[derive(Debug)]
 pub struct Email<'a> {
     pub sender: Sender<'a>,
     pub receiver: Receiver<'a>,
+    /// Receivers which are only part of the envelope (blind carbon copy).
+    pub bcc: Vec<Receiver<'a>>,
     pub message: Message,
     pub mime_format: MimeFormat,
+    /// Values of the personalized columns by column name.
+    pub personalization: BTreeMap<String, String>,
+    /// The variant of the message if the receivers are split for A/B testing.
+    pub variant: Option<String>,
+    /// The attachments of the message, e.g. as selected by the template
+    /// column of the receiver.
+    pub attachments: &'a [Attachment],
 }
 
 impl<'a> Email<'a> {
@@ -21,11 +30,69 @@ impl<'a> Email<'a> {
         let email = Email {
             sender,
             receiver,
+            bcc: vec![],
             message: message.to_owned(),
             mime_format: mime_format.to_owned(),
+            personalization: BTreeMap::new(),
+            variant: None,
+            attachments: &[],
         };
         Ok(email)
     }
+
+    /// Create an email whose message is personalized by the given values of
+    /// the personalized columns.
+    pub fn new_personalized(
+        sender: Sender<'a>,
+        receiver: Receiver<'a>,
+        message: &Message,
+        attachments: &'a [Attachment],
+        personalization: BTreeMap<String, String>,
+        now: SystemTime,
+    ) -> Result<Self, anyhow::Error> {
+        let mut message = message.clone();
+
+        for (col_name, col_value) in personalization.iter() {
+            message.personalize(col_name, col_value);
+        }
+
+        let mime_format = MimeFormat::new(sender, receiver, &message, attachments, now)?;
+        let mut email = Email::new(sender, receiver, &message, &mime_format)?;
+        email.personalization = personalization;
+        email.attachments = attachments;
+
+        Ok(email)
+    }
+
+    /// Create a single email for multiple receivers which is addressed to the
+    /// sender. The receivers are only part of the envelope.
+    pub fn new_batch(
+        sender: Sender<'a>,
+        receivers: Vec<Receiver<'a>>,
+        message: &Message,
+        mime_format: &MimeFormat,
+    ) -> Result<Self, anyhow::Error> {
+        let email = Email {
+            sender,
+            receiver: Receiver(sender.0),
+            bcc: receivers,
+            message: message.to_owned(),
+            mime_format: mime_format.to_owned(),
+            personalization: BTreeMap::new(),
+            variant: None,
+            attachments: &[],
+        };
+        Ok(email)
+    }
+
+    /// The receivers of the email as specified in the envelope.
+    pub fn receivers(&self) -> Vec<Receiver<'a>> {
+        if self.bcc.is_empty() {
+            vec![self.receiver]
+        } else {
+            self.bcc.clone()
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -34,37 +101,50 @@ pub struct BulkEmail<'a> {
 }
 
 impl<'a> BulkEmail<'a> {
+    /// Create an email per receiver. The sender, subject, and message file
+    /// can be selected per receiver by the given message columns.
     pub fn new(
         sender: Sender<'a>,
         bulk_receiver: &'a BulkReceiver,
         message: &'a Message,
-        attachment: Option<&Path>,
+        attachments: &'a [Attachment],
         personalized_columns: &[&str],
+        message_columns: &'a MessageColumns,
     ) -> Result<Self, anyhow::Error> {
         let now = SystemTime::now();
         let mut emails: Vec<Email> = vec![];
 
-        if personalized_columns.is_empty() {
+        if personalized_columns.is_empty() && message_columns.is_empty() {
             let receivers = bulk_receiver.receiver_column()?;
             for receiver in receivers.into_iter().flatten() {
                 let mime_format =
-                    MimeFormat::new(sender, Receiver(receiver), message, attachment, now)?;
-                let email = Email::new(sender, Receiver(receiver), message, &mime_format)?;
+                    MimeFormat::new(sender, Receiver(receiver), message, attachments, now)?;
+                let mut email = Email::new(sender, Receiver(receiver), message, &mime_format)?;
+                email.attachments = attachments;
                 emails.push(email);
             }
         } else {
             for i in 0..bulk_receiver.height() {
-                let mut message = message.clone();
+                let mut personalization = BTreeMap::new();
 
                 for &col_name in personalized_columns.iter() {
                     let col_value = bulk_receiver.row(i, col_name)?;
-                    message.personalize(col_name, col_value);
+                    personalization.insert(col_name.to_owned(), col_value.to_owned());
                 }
 
                 let receiver = bulk_receiver.receiver_row(i)?;
-                let mime_format =
-                    MimeFormat::new(sender, Receiver(receiver), &message, attachment, now)?;
-                let email = Email::new(sender, Receiver(receiver), &message, &mime_format)?;
+                let (sender, message, attachments) =
+                    message_columns.select(sender, message, attachments, |col_name| {
+                        bulk_receiver.optional_row(i, col_name)
+                    })?;
+                let email = Email::new_personalized(
+                    sender,
+                    Receiver(receiver),
+                    &message,
+                    attachments,
+                    personalization,
+                    now,
+                )?;
 
                 emails.push(email);
             }
@@ -72,6 +152,88 @@ impl<'a> BulkEmail<'a> {
 
         Ok(BulkEmail { emails })
     }
+
+    /// Create identical emails for batches of up to `batch_size` receivers.
+    /// Each batch is sent as a single email, i.e. in a single SMTP transaction.
+    pub fn new_batched(
+        sender: Sender<'a>,
+        bulk_receiver: &'a BulkReceiver,
+        message: &'a Message,
+        attachments: &'a [Attachment],
+        batch_size: usize,
+    ) -> Result<Self, anyhow::Error> {
+        if batch_size == 0 {
+            return Err(anyhow!("Batch size must be greater than zero"));
+        }
+
+        let now = SystemTime::now();
+        let receivers = bulk_receiver
+            .receiver_column()?
+            .into_iter()
+            .flatten()
+            .map(Receiver)
+            .collect::<Vec<_>>();
+        let mut emails: Vec<Email> = vec![];
+
+        for batch in receivers.chunks(batch_size) {
+            let mime_format = MimeFormat::new_batch(sender, batch, message, attachments, now)?;
+            let mut email = Email::new_batch(sender, batch.to_vec(), message, &mime_format)?;
+            email.attachments = attachments;
+            emails.push(email);
+        }
+
+        Ok(BulkEmail { emails })
+    }
+
+    /// The number of receivers of all emails.
+    pub fn receiver_count(&self) -> usize {
+        self.emails
+            .iter()
+            .map(|email| email.receivers().len())
+            .sum()
+    }
+
+    /// Create test emails which send the messages of all emails to each of
+    /// the test receivers. The subject is prefixed by `[TEST]`.
+    pub fn test_emails(&self, test_receivers: &[Receiver<'a>]) -> Result<Self, anyhow::Error> {
+        let now = SystemTime::now();
+        let mut emails = vec![];
+
+        for email in self.emails.iter() {
+            let mut message = email.message.clone();
+            message.subject = format!("[TEST] {}", message.subject);
+
+            for &test_receiver in test_receivers {
+                let mime_format = MimeFormat::new(
+                    email.sender,
+                    test_receiver,
+                    &message,
+                    email.attachments,
+                    now,
+                )?;
+                let mut test_email =
+                    Email::new(email.sender, test_receiver, &message, &mime_format)?;
+                test_email.personalization = email.personalization.clone();
+                test_email.variant = email.variant.clone();
+                test_email.attachments = email.attachments;
+                emails.push(test_email);
+            }
+        }
+
+        Ok(BulkEmail { emails })
+    }
+
+    /// Group emails by the domain of the receivers, so that consecutive
+    /// emails can be delivered via the same connection.
+    pub fn sort_by_domain(&mut self) {
+        self.emails.sort_by_cached_key(|email| {
+            email
+                .receiver
+                .domain()
+                .map(|domain| domain.to_lowercase())
+                .ok()
+        });
+    }
 }
 
 #[cfg(test)]
@@ -91,7 +253,8 @@ mod tests {
         let df_receiver = DataFrame::new(vec![receiver_column]).unwrap();
         let receivers = BulkReceiver::new(column_name.to_owned(), df_receiver);
 
-        let res = BulkEmail::new(sender, &receivers, &message, None, &[]);
+        let message_columns = MessageColumns::default();
+        let res = BulkEmail::new(sender, &receivers, &message, &[], &[], &message_columns);
         assert!(res.is_ok());
 
         let emails = res.unwrap().emails;
@@ -124,12 +287,14 @@ This is a test message (html)."#;
             DataFrame::new(vec![first_name_column, last_name_column, email_column]).unwrap();
         let receivers = BulkReceiver::new("email".to_owned(), df_receiver);
 
+        let message_columns = MessageColumns::default();
         let res = BulkEmail::new(
             sender,
             &receivers,
             &message,
-            None,
+            &[],
             &["first_name", "last_name"],
+            &message_columns,
         );
         assert!(res.is_ok());
 
@@ -141,6 +306,13 @@ This is a test message (html)."#;
         assert!(receivers.contains(&Receiver("marie@curie.com")));
         assert!(receivers.contains(&Receiver("emmy@noether.com")));
 
+        let personalizations = emails
+            .iter()
+            .map(|email| email.personalization.values().cloned().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert!(personalizations.contains(&vec!["Marie".to_owned(), "Curie".to_owned()]));
+        assert!(personalizations.contains(&vec!["Emmy".to_owned(), "Noether".to_owned()]));
+
         let text_messages = emails
             .iter()
             .map(|email| email.message.text.as_ref().unwrap().as_str())
@@ -157,4 +329,155 @@ This is a test message (html)."#;
         assert!(html_messages
             .contains(&"Dear Emmy Noether,\n<br>\n<br>\nThis is a test message (html)."));
     }
+
+    #[test]
+    fn test_bulk_email_message_columns() {
+        let sender = Sender("albert@einstein.com");
+        let message = Message::new("Test subject", Some("Dear {first_name},"), None);
+        let df_receiver = crate::sources::read_csv_as_text(std::path::Path::new(
+            "./test_data/receiver_columns.csv",
+        ))
+        .unwrap();
+        let receivers = BulkReceiver::new("email".to_owned(), df_receiver);
+        let mut message_columns =
+            MessageColumns::new(Some("sender"), Some("subject"), Some("template"));
+        message_columns.load_templates(&receivers).unwrap();
+
+        let res = BulkEmail::new(
+            sender,
+            &receivers,
+            &message,
+            &[],
+            &["first_name"],
+            &message_columns,
+        );
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let emails = res.unwrap().emails;
+        assert_eq!(emails.len(), 2);
+        assert_eq!(emails[0].sender, Sender("paris@einstein.com"));
+        assert_eq!(emails[0].message.subject, "Message de test");
+        assert_eq!(emails[1].sender, sender);
+        assert_eq!(emails[1].message.subject, "Hello Alexandre");
+        assert_eq!(emails[1].message.text.as_deref(), Some("Dear Alexandre,"));
+    }
+
+    #[test]
+    fn test_bulk_email_test_emails() {
+        let sender = Sender("albert@einstein.com");
+        let message = Message::new("Test Subject", Some("Dear {first_name}"), None);
+        let first_name_column = Series::new("first_name", &["Marie", "Emmy", "Pierre"]);
+        let email_column = Series::new(
+            "email",
+            &["marie@curie.com", "emmy@noether.com", "pierre@curie.com"],
+        );
+        let df_receiver = DataFrame::new(vec![first_name_column, email_column]).unwrap();
+        let receivers = BulkReceiver::new("email".to_owned(), df_receiver);
+        let message_columns = MessageColumns::default();
+        let attachments = [Attachment::new(std::path::Path::new("./test_data/test.pdf")).unwrap()];
+        let bulk_email = BulkEmail::new(
+            sender,
+            &receivers,
+            &message,
+            &attachments,
+            &["first_name"],
+            &message_columns,
+        )
+        .unwrap();
+
+        let test_receivers = [Receiver("albert@einstein.com"), Receiver("max@planck.com")];
+        let res = bulk_email.test_emails(&test_receivers);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let emails = res.unwrap().emails;
+        assert_eq!(emails.len(), 6);
+        assert!(emails
+            .iter()
+            .all(|email| test_receivers.contains(&email.receiver)));
+        assert!(emails
+            .iter()
+            .all(|email| email.message.subject == "[TEST] Test Subject"));
+        assert!(emails.iter().all(|email| {
+            String::from_utf8_lossy(email.mime_format.formatted()).contains("filename=\"test.pdf\"")
+        }));
+
+        let text_messages = emails
+            .iter()
+            .map(|email| email.message.text.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            text_messages,
+            vec![
+                "Dear Marie",
+                "Dear Marie",
+                "Dear Emmy",
+                "Dear Emmy",
+                "Dear Pierre",
+                "Dear Pierre"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bulk_email_sort_by_domain() {
+        let sender = Sender("albert@einstein.com");
+        let message = Message::new("Test Subject", Some("This is a test message."), None);
+        let column_name = "email";
+        let receiver_column = Series::new(
+            column_name,
+            &["marie@curie.com", "emmy@noether.com", "pierre@Curie.com"],
+        );
+        let df_receiver = DataFrame::new(vec![receiver_column]).unwrap();
+        let receivers = BulkReceiver::new(column_name.to_owned(), df_receiver);
+
+        let message_columns = MessageColumns::default();
+        let mut bulk_email =
+            BulkEmail::new(sender, &receivers, &message, &[], &[], &message_columns).unwrap();
+        bulk_email.sort_by_domain();
+
+        let receivers = bulk_email
+            .emails
+            .iter()
+            .map(|email| email.receiver.0)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            receivers,
+            vec!["marie@curie.com", "pierre@Curie.com", "emmy@noether.com"]
+        );
+    }
+
+    #[test]
+    fn test_bulk_email_batched() {
+        let sender = Sender("albert@einstein.com");
+        let message = Message::new("Test Subject", Some("This is a test message."), None);
+        let column_name = "email";
+        let receiver_column = Series::new(
+            column_name,
+            &["marie@curie.com", "emmy@noether.com", "pierre@curie.com"],
+        );
+        let df_receiver = DataFrame::new(vec![receiver_column]).unwrap();
+        let receivers = BulkReceiver::new(column_name.to_owned(), df_receiver);
+
+        let res = BulkEmail::new_batched(sender, &receivers, &message, &[], 2);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let bulk_email = res.unwrap();
+        assert_eq!(bulk_email.emails.len(), 2);
+        assert_eq!(bulk_email.receiver_count(), 3);
+        assert_eq!(
+            bulk_email.emails[0].receivers(),
+            vec![Receiver("marie@curie.com"), Receiver("emmy@noether.com")]
+        );
+        assert_eq!(
+            bulk_email.emails[1].receivers(),
+            vec![Receiver("pierre@curie.com")]
+        );
+        assert!(bulk_email
+            .emails
+            .iter()
+            .all(|email| email.receiver == Receiver("albert@einstein.com")));
+
+        let res = BulkEmail::new_batched(sender, &receivers, &message, &[], 0);
+        assert!(res.is_err());
+    }
 }
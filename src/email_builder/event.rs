@@ -0,0 +1,413 @@
+use super::{Locale, Receiver, Sender};
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use lettre::message::Mailbox;
+use serde::Deserialize;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+/// Maximum length of a line in an iCalendar object in octets, excluding the
+/// line break. RFC 5545 allows 75 octets, but shorter lines can be sent
+/// without quoted-printable encoding.
+const MAX_LINE_LEN: usize = 72;
+const PRODUCT_ID: &str = "-//quambene//pigeon-rs//EN";
+
+/// A calendar event which is sent as invitation, i.e. as iCalendar object
+/// with method `REQUEST`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Event {
+    /// The title of the event. Defaults to the subject of the message.
+    pub summary: Option<String>,
+    /// Start of the event, e.g. `2024-06-01T14:00:00+02:00`, or
+    /// `2024-06-01T14:00:00` in the given time zone, or `2024-06-01` for an
+    /// all-day event.
+    pub start: String,
+    /// End of the event in the same format as the start.
+    pub end: String,
+    /// IANA time zone of start and end without UTC offset, e.g.
+    /// `Europe/Berlin`. Start and end are converted to UTC, so that the
+    /// invitation doesn't need a `VTIMEZONE` component.
+    pub timezone: Option<String>,
+    pub location: Option<String>,
+    /// Defaults to the sender.
+    pub organizer: Option<String>,
+    pub description: Option<String>,
+    /// Unique id of the event, e.g. `meetup-{email}@einstein.com`. Send an
+    /// invitation with the same uid to update the event. Defaults to a random
+    /// id.
+    pub uid: Option<String>,
+}
+
+impl Event {
+    pub fn personalize(&mut self, col_name: &str, col_value: &str, locale: &Locale) {
+        for field in [
+            &mut self.summary,
+            &mut self.location,
+            &mut self.description,
+            &mut self.uid,
+        ] {
+            *field = field
+                .as_ref()
+                .map(|value| locale.personalize(value, col_name, col_value));
+        }
+    }
+
+    /// Format the invitation as iCalendar object.
+    pub fn to_ical(
+        &self,
+        subject: &str,
+        sender: Sender,
+        attendees: &[Receiver],
+        now: SystemTime,
+    ) -> Result<String, anyhow::Error> {
+        let timezone = match &self.timezone {
+            Some(timezone) => Some(
+                timezone
+                    .parse::<Tz>()
+                    .map_err(|err| anyhow!("Invalid time zone '{}': {}", timezone, err))?,
+            ),
+            None => None,
+        };
+        let start = EventTime::parse(&self.start)
+            .and_then(|start| start.in_timezone(timezone))
+            .context("Can't parse start of event")?;
+        let end = EventTime::parse(&self.end)
+            .and_then(|end| end.in_timezone(timezone))
+            .context("Can't parse end of event")?;
+
+        if !start.is_before(&end)? {
+            return Err(anyhow!(
+                "End of event '{}' must be after start '{}'",
+                self.end,
+                self.start
+            ));
+        }
+
+        let organizer = match &self.organizer {
+            Some(organizer) => organizer.as_str(),
+            None => sender.0,
+        };
+        let organizer = organizer
+            .parse::<Mailbox>()
+            .context(format!("Can't parse organizer '{}'", organizer))?;
+        let uid = match &self.uid {
+            Some(uid) => uid.to_owned(),
+            None => format!("{}@{}", Uuid::new_v4(), organizer.email.domain()),
+        };
+        let mut lines = vec![
+            "BEGIN:VCALENDAR".to_owned(),
+            format!("PRODID:{}", PRODUCT_ID),
+            "VERSION:2.0".to_owned(),
+            "CALSCALE:GREGORIAN".to_owned(),
+            "METHOD:REQUEST".to_owned(),
+            "BEGIN:VEVENT".to_owned(),
+            format!("UID:{}", escape(&uid)),
+            format!(
+                "DTSTAMP:{}",
+                DateTime::<Utc>::from(now).format("%Y%m%dT%H%M%SZ")
+            ),
+            format!("DTSTART{}", start.format()),
+            format!("DTEND{}", end.format()),
+            format!(
+                "SUMMARY:{}",
+                escape(self.summary.as_deref().unwrap_or(subject))
+            ),
+        ];
+
+        if let Some(location) = &self.location {
+            lines.push(format!("LOCATION:{}", escape(location)));
+        }
+
+        if let Some(description) = &self.description {
+            lines.push(format!("DESCRIPTION:{}", escape(description)));
+        }
+
+        lines.push(format!("ORGANIZER{}", calendar_address(&organizer)));
+
+        for attendee in attendees {
+            let attendee = attendee
+                .0
+                .parse::<Mailbox>()
+                .context(format!("Can't parse attendee '{}'", attendee.0))?;
+            lines.push(format!(
+                "ATTENDEE;ROLE=REQ-PARTICIPANT;PARTSTAT=NEEDS-ACTION;RSVP=TRUE{}",
+                calendar_address(&attendee)
+            ));
+        }
+
+        lines.extend([
+            "SEQUENCE:0".to_owned(),
+            "STATUS:CONFIRMED".to_owned(),
+            "END:VEVENT".to_owned(),
+            "END:VCALENDAR".to_owned(),
+        ]);
+
+        Ok(lines.iter().map(|line| fold(line)).collect())
+    }
+}
+
+/// Start or end of an event.
+#[derive(Debug, PartialEq)]
+enum EventTime {
+    Utc(DateTime<Utc>),
+    /// Local time without time zone, i.e. floating time.
+    Local(NaiveDateTime),
+    /// Date of an all-day event.
+    Date(NaiveDate),
+}
+
+impl EventTime {
+    fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let value = value.trim();
+
+        if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+            return Ok(Self::Utc(date_time.with_timezone(&Utc)));
+        }
+
+        for format in [
+            "%Y-%m-%dT%H:%M:%S",
+            "%Y-%m-%dT%H:%M",
+            "%Y-%m-%d %H:%M:%S",
+            "%Y-%m-%d %H:%M",
+        ] {
+            if let Ok(date_time) = NaiveDateTime::parse_from_str(value, format) {
+                return Ok(Self::Local(date_time));
+            }
+        }
+
+        match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            Ok(date) => Ok(Self::Date(date)),
+            Err(_) => Err(anyhow!(
+                "Invalid date '{}', expected e.g. '2024-06-01T14:00:00+02:00'",
+                value
+            )),
+        }
+    }
+
+    /// Convert a local time in the given time zone to UTC.
+    fn in_timezone(self, timezone: Option<Tz>) -> Result<Self, anyhow::Error> {
+        match (self, timezone) {
+            (Self::Local(date_time), Some(timezone)) => {
+                // The earlier time is used if the local time is ambiguous
+                match timezone.from_local_datetime(&date_time).earliest() {
+                    Some(date_time) => Ok(Self::Utc(date_time.with_timezone(&Utc))),
+                    None => Err(anyhow!(
+                        "Local time '{}' doesn't exist in time zone '{}'",
+                        date_time,
+                        timezone
+                    )),
+                }
+            }
+            (event_time, _) => Ok(event_time),
+        }
+    }
+
+    fn is_before(&self, other: &Self) -> Result<bool, anyhow::Error> {
+        match (self, other) {
+            (Self::Utc(start), Self::Utc(end)) => Ok(start < end),
+            (Self::Local(start), Self::Local(end)) => Ok(start < end),
+            (Self::Date(start), Self::Date(end)) => Ok(start < end),
+            _ => Err(anyhow!("Start and end of event must be in the same format")),
+        }
+    }
+
+    /// Format the parameters and value of a `DTSTART` or `DTEND` property.
+    fn format(&self) -> String {
+        match self {
+            Self::Utc(date_time) => format!(":{}", date_time.format("%Y%m%dT%H%M%SZ")),
+            // Floating time, i.e. the same local time in every time zone
+            Self::Local(date_time) => format!(":{}", date_time.format("%Y%m%dT%H%M%S")),
+            Self::Date(date) => format!(";VALUE=DATE:{}", date.format("%Y%m%d")),
+        }
+    }
+}
+
+/// Format the parameters and value of an `ORGANIZER` or `ATTENDEE` property.
+fn calendar_address(mailbox: &Mailbox) -> String {
+    match &mailbox.name {
+        Some(name) => format!(
+            ";CN=\"{}\":mailto:{}",
+            name.replace('"', "'"),
+            mailbox.email
+        ),
+        None => format!(":mailto:{}", mailbox.email),
+    }
+}
+
+/// Escape text values according to RFC 5545.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Fold a content line into lines of at most `MAX_LINE_LEN` octets, and
+/// terminate it by a line break.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 2);
+    let mut line_len = 0;
+
+    for char in line.chars() {
+        if line_len + char.len_utf8() > MAX_LINE_LEN {
+            folded.push_str("\r\n ");
+            // The leading space counts towards the length of the next line
+            line_len = 1;
+        }
+
+        folded.push(char);
+        line_len += char.len_utf8();
+    }
+
+    folded.push_str("\r\n");
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn event() -> Event {
+        Event {
+            summary: Some("Meetup with {first_name}".to_owned()),
+            start: "2024-06-01T14:00:00+02:00".to_owned(),
+            end: "2024-06-01T15:30:00+02:00".to_owned(),
+            timezone: None,
+            location: Some("Room 1, Institute for Advanced Study".to_owned()),
+            organizer: None,
+            description: Some("Agenda:\nRelativity; quantum mechanics".to_owned()),
+            uid: Some("meetup-{first_name}@einstein.com".to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_to_ical() {
+        let mut event = event();
+        event.personalize("first_name", "Marie", &Locale::default());
+
+        let now = UNIX_EPOCH + Duration::from_secs(1_704_117_600);
+        let res = event.to_ical(
+            "Test subject",
+            Sender("Albert Einstein <albert@einstein.com>"),
+            &[Receiver("marie@curie.com")],
+            now,
+        );
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let ical = res.unwrap();
+        assert_eq!(
+            ical,
+            [
+                "BEGIN:VCALENDAR",
+                "PRODID:-//quambene//pigeon-rs//EN",
+                "VERSION:2.0",
+                "CALSCALE:GREGORIAN",
+                "METHOD:REQUEST",
+                "BEGIN:VEVENT",
+                "UID:meetup-Marie@einstein.com",
+                "DTSTAMP:20240101T140000Z",
+                "DTSTART:20240601T120000Z",
+                "DTEND:20240601T133000Z",
+                "SUMMARY:Meetup with Marie",
+                "LOCATION:Room 1\\, Institute for Advanced Study",
+                "DESCRIPTION:Agenda:\\nRelativity\\; quantum mechanics",
+                "ORGANIZER;CN=\"Albert Einstein\":mailto:albert@einstein.com",
+                "ATTENDEE;ROLE=REQ-PARTICIPANT;PARTSTAT=NEEDS-ACTION;RSVP=TRUE:mailto:mar",
+                " ie@curie.com",
+                "SEQUENCE:0",
+                "STATUS:CONFIRMED",
+                "END:VEVENT",
+                "END:VCALENDAR",
+                "",
+            ]
+            .join("\r\n")
+        );
+    }
+
+    #[test]
+    fn test_to_ical_timezone() {
+        let event = Event {
+            start: "2024-06-01 14:00".to_owned(),
+            end: "2024-06-01 15:30".to_owned(),
+            timezone: Some("Europe/Berlin".to_owned()),
+            uid: None,
+            ..event()
+        };
+
+        let ical = event
+            .to_ical(
+                "Test subject",
+                Sender("albert@einstein.com"),
+                &[],
+                SystemTime::now(),
+            )
+            .unwrap();
+        assert!(ical.contains("\r\nDTSTART:20240601T120000Z\r\n"));
+        assert!(ical.contains("\r\nDTEND:20240601T133000Z\r\n"));
+        assert!(!ical.contains("TZID"));
+        assert!(ical.contains("\r\nORGANIZER:mailto:albert@einstein.com\r\n"));
+        assert!(ical.contains("@einstein.com\r\n"));
+        assert!(!ical.contains("ATTENDEE"));
+    }
+
+    #[test]
+    fn test_to_ical_invalid() {
+        let sender = Sender("albert@einstein.com");
+
+        for (start, end) in [
+            ("2024-06-01T15:00:00Z", "2024-06-01T14:00:00Z"),
+            ("2024-06-01", "2024-06-01T14:00:00Z"),
+            ("June 1st", "2024-06-02"),
+        ] {
+            let event = Event {
+                start: start.to_owned(),
+                end: end.to_owned(),
+                ..event()
+            };
+            assert!(event
+                .to_ical("Test subject", sender, &[], SystemTime::now())
+                .is_err());
+        }
+
+        for (start, timezone) in [
+            ("2024-06-01 14:00", "Europe/Atlantis"),
+            // Skipped by the switch to daylight saving time
+            ("2024-03-31 02:30", "Europe/Berlin"),
+        ] {
+            let event = Event {
+                start: start.to_owned(),
+                end: "2024-06-01 15:00".to_owned(),
+                timezone: Some(timezone.to_owned()),
+                ..event()
+            };
+            assert!(event
+                .to_ical("Test subject", sender, &[], SystemTime::now())
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_event_time_all_day() {
+        let start = EventTime::parse("2024-06-01").unwrap();
+        let end = EventTime::parse("2024-06-02").unwrap();
+        assert!(start.is_before(&end).unwrap());
+        assert_eq!(
+            start
+                .in_timezone(Some(Tz::Europe__Berlin))
+                .unwrap()
+                .format(),
+            ";VALUE=DATE:20240601"
+        );
+    }
+
+    #[test]
+    fn test_fold() {
+        let line = format!("DESCRIPTION:{}", "ä".repeat(40));
+        let folded = fold(&line);
+        assert!(folded.split("\r\n").all(|line| line.len() <= MAX_LINE_LEN));
+        assert_eq!(folded.replace("\r\n ", "").trim_end(), line);
+    }
+}
@@ -0,0 +1,262 @@
+use chrono::{DateTime, NaiveDate};
+use std::path::{Path, PathBuf};
+
+/// The locale used if neither the receiver nor the message specify one.
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Separators and date format of a locale.
+struct LocaleFormat {
+    decimal_separator: &'static str,
+    group_separator: &'static str,
+    date_format: &'static str,
+}
+
+/// Formats by language tag, or by language if the region isn't listed.
+const LOCALE_FORMATS: [(&str, LocaleFormat); 15] = [
+    ("en", format(".", ",", "%m/%d/%Y")),
+    ("en-GB", format(".", ",", "%d/%m/%Y")),
+    ("de", format(",", ".", "%d.%m.%Y")),
+    ("de-CH", format(".", "’", "%d.%m.%Y")),
+    ("fr", format(",", "\u{202f}", "%d/%m/%Y")),
+    ("fr-CH", format(",", "\u{202f}", "%d.%m.%Y")),
+    ("es", format(",", ".", "%d/%m/%Y")),
+    ("it", format(",", ".", "%d/%m/%Y")),
+    ("pt", format(",", ".", "%d/%m/%Y")),
+    ("nl", format(",", ".", "%d-%m-%Y")),
+    ("pl", format(",", "\u{a0}", "%d.%m.%Y")),
+    ("sv", format(",", "\u{a0}", "%Y-%m-%d")),
+    ("da", format(",", ".", "%d.%m.%Y")),
+    ("ja", format(".", ",", "%Y/%m/%d")),
+    ("zh", format(".", ",", "%Y/%m/%d")),
+];
+
+/// Format of unknown locales.
+const FALLBACK_FORMAT: LocaleFormat = format(".", ",", "%Y-%m-%d");
+
+const fn format(
+    decimal_separator: &'static str,
+    group_separator: &'static str,
+    date_format: &'static str,
+) -> LocaleFormat {
+    LocaleFormat {
+        decimal_separator,
+        group_separator,
+        date_format,
+    }
+}
+
+/// A language tag like `de` or `de-CH`, which selects the variant of the
+/// message file and the formatting of numbers and dates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Locale(String);
+
+impl Locale {
+    /// Normalize the language tag, e.g. `de_ch` to `de-CH`.
+    pub fn new(tag: &str) -> Self {
+        let mut subtags = tag.trim().split(['-', '_']);
+        let language = subtags.next().unwrap_or_default().to_lowercase();
+
+        match subtags.next() {
+            Some(region) if !region.is_empty() => {
+                Self(format!("{}-{}", language, region.to_uppercase()))
+            }
+            _ => Self(language),
+        }
+    }
+
+    pub fn tag(&self) -> &str {
+        &self.0
+    }
+
+    /// The language without region, e.g. `de` for `de-CH`.
+    pub fn language(&self) -> &str {
+        self.0.split('-').next().unwrap_or_default()
+    }
+
+    /// The language tag, and the language if the tag has a region.
+    pub fn fallbacks(&self) -> Vec<&str> {
+        if self.language() == self.tag() {
+            vec![self.tag()]
+        } else {
+            vec![self.tag(), self.language()]
+        }
+    }
+
+    /// The variant of the message file for the locale, e.g. `message.de.yaml`
+    /// for `message.yaml`.
+    pub fn variant_path(path: &Path, tag: &str) -> PathBuf {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+        let file_name = match path.extension() {
+            Some(extension) => format!("{}.{}.{}", stem, tag, extension.to_string_lossy()),
+            None => format!("{}.{}", stem, tag),
+        };
+
+        path.with_file_name(file_name)
+    }
+
+    /// Replace the placeholders `{col_name}`, `{col_name:number}`, and
+    /// `{col_name:date}` by the value of the column. Numbers and dates are
+    /// formatted for the locale; other values are inserted as is.
+    pub fn personalize(&self, text: &str, col_name: &str, col_value: &str) -> String {
+        let text = text.replace(&format!("{{{}}}", col_name), col_value);
+        let number_placeholder = format!("{{{}:number}}", col_name);
+        let date_placeholder = format!("{{{}:date}}", col_name);
+
+        let text = if text.contains(&number_placeholder) {
+            text.replace(&number_placeholder, &self.format_number(col_value))
+        } else {
+            text
+        };
+
+        if text.contains(&date_placeholder) {
+            text.replace(&date_placeholder, &self.format_date(col_value))
+        } else {
+            text
+        }
+    }
+
+    /// Format a decimal number like `1234567.5` with the separators of the
+    /// locale, e.g. `1.234.567,5` for `de`.
+    pub fn format_number(&self, value: &str) -> String {
+        let trimmed = value.trim();
+        let (sign, unsigned) = match trimmed.strip_prefix('-') {
+            Some(unsigned) => ("-", unsigned),
+            None => ("", trimmed),
+        };
+        let (integer, fraction) = match unsigned.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (unsigned, None),
+        };
+
+        let is_digits = |digits: &str| digits.chars().all(|char| char.is_ascii_digit());
+        if integer.is_empty()
+            || !is_digits(integer)
+            || fraction.is_some_and(|fraction| fraction.is_empty() || !is_digits(fraction))
+        {
+            return value.to_owned();
+        }
+
+        let locale_format = self.locale_format();
+        let mut formatted = sign.to_owned();
+
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                formatted.push_str(locale_format.group_separator);
+            }
+            formatted.push(digit);
+        }
+
+        if let Some(fraction) = fraction {
+            formatted.push_str(locale_format.decimal_separator);
+            formatted.push_str(fraction);
+        }
+
+        formatted
+    }
+
+    /// Format a date like `2024-06-01`, or the date of an RFC 3339 date-time,
+    /// in the date format of the locale, e.g. `01.06.2024` for `de`.
+    pub fn format_date(&self, value: &str) -> String {
+        let date = NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+            .ok()
+            .or_else(|| {
+                DateTime::parse_from_rfc3339(value.trim())
+                    .ok()
+                    .map(|date_time| date_time.date_naive())
+            });
+
+        match date {
+            Some(date) => date.format(self.locale_format().date_format).to_string(),
+            None => value.to_owned(),
+        }
+    }
+
+    fn locale_format(&self) -> &'static LocaleFormat {
+        self.fallbacks()
+            .into_iter()
+            .find_map(|tag| {
+                LOCALE_FORMATS
+                    .iter()
+                    .find(|(locale, _)| *locale == tag)
+                    .map(|(_, locale_format)| locale_format)
+            })
+            .unwrap_or(&FALLBACK_FORMAT)
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self::new(DEFAULT_LOCALE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_new() {
+        assert_eq!(Locale::new("de").tag(), "de");
+        assert_eq!(Locale::new("de_ch").tag(), "de-CH");
+        assert_eq!(Locale::new(" DE-ch ").tag(), "de-CH");
+        assert_eq!(Locale::new("de-CH").language(), "de");
+        assert_eq!(Locale::new("de-CH").fallbacks(), vec!["de-CH", "de"]);
+        assert_eq!(Locale::new("fr").fallbacks(), vec!["fr"]);
+    }
+
+    #[test]
+    fn test_variant_path() {
+        assert_eq!(
+            Locale::variant_path(Path::new("./templates/message.yaml"), "de-CH"),
+            PathBuf::from("./templates/message.de-CH.yaml")
+        );
+        assert_eq!(
+            Locale::variant_path(Path::new("message"), "fr"),
+            PathBuf::from("message.fr")
+        );
+    }
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(Locale::new("en").format_number("1234567.5"), "1,234,567.5");
+        assert_eq!(Locale::new("de").format_number("1234567.5"), "1.234.567,5");
+        assert_eq!(Locale::new("de-AT").format_number("-1234.50"), "-1.234,50");
+        assert_eq!(Locale::new("de-CH").format_number("1234.5"), "1’234.5");
+        assert_eq!(Locale::new("fr").format_number("1234.5"), "1\u{202f}234,5");
+        assert_eq!(Locale::new("xx").format_number("1234"), "1,234");
+        assert_eq!(Locale::new("de").format_number("123"), "123");
+        assert_eq!(Locale::new("de").format_number("12a"), "12a");
+        assert_eq!(Locale::new("de").format_number("1."), "1.");
+        assert_eq!(Locale::new("de").format_number(""), "");
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(Locale::new("en").format_date("2024-06-01"), "06/01/2024");
+        assert_eq!(Locale::new("en-GB").format_date("2024-06-01"), "01/06/2024");
+        assert_eq!(Locale::new("de").format_date("2024-06-01"), "01.06.2024");
+        assert_eq!(
+            Locale::new("ja").format_date("2024-06-01T14:00:00+02:00"),
+            "2024/06/01"
+        );
+        assert_eq!(Locale::new("xx").format_date("2024-06-01"), "2024-06-01");
+        assert_eq!(Locale::new("de").format_date("June 1"), "June 1");
+    }
+
+    #[test]
+    fn test_personalize() {
+        let locale = Locale::new("de");
+        let text =
+            "Dear {first_name}, your invoice of {amount:number} EUR is due on {due_date:date}.";
+        let text = locale.personalize(text, "first_name", "Marie");
+        let text = locale.personalize(&text, "amount", "1234.5");
+        let text = locale.personalize(&text, "due_date", "2024-06-01");
+        assert_eq!(
+            text,
+            "Dear Marie, your invoice of 1.234,5 EUR is due on 01.06.2024."
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use super::{AttachmentFile, Event, Locale};
 use crate::{arg, utils};
 use anyhow::{anyhow, Context, Result};
 use clap::ArgMatches;
@@ -12,6 +13,7 @@ const TEMPLATE_FILE_NAME: &str = "message.yaml";
 
 static MESSAGE_TEMPLATE: &str = r##"# Specify the subject, plaintext and html version of your email.
 # Personalize message by wrapping variables in curly brackets, eg. {first_name}.
+# Format numbers and dates for the locale via {amount:number} and {due_date:date}.
 
 # The subject of your email
 subject: ""
@@ -19,6 +21,28 @@ subject: ""
 text: ""
 # The html version
 html: ""
+# Optional locale for formatting numbers and dates, eg. "de" or "de-CH" (defaults to "en")
+# locale: "en"
+# Optional variants for A/B testing via --split, which replace subject, text, or html, eg.
+# variants:
+#   - name: "A"
+#     subject: "Our new brochure"
+#   - name: "B"
+#     subject: "Have you seen our new brochure, {first_name}?"
+# Optional attachments with file name, content type, and disposition (attachment or inline), eg.
+# attachments:
+#   - path: "./report.csv"
+#     name: "Report 2024.csv"
+#     content_type: "text/csv"
+#     disposition: "attachment"
+# Optional calendar invitation with start and end as RFC 3339 date-time, or in the given IANA timezone, eg.
+# event:
+#   summary: "Meetup"
+#   start: "2024-06-01T14:00:00"
+#   end: "2024-06-01T15:30:00"
+#   timezone: "Europe/Berlin"
+#   location: "Institute for Advanced Study"
+#   description: "See you there, {first_name}!"
 "##;
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
@@ -26,6 +50,27 @@ pub struct Message {
     pub subject: String,
     pub text: Option<String>,
     pub html: Option<String>,
+    #[serde(default)]
+    pub attachments: Vec<AttachmentFile>,
+    #[serde(default)]
+    pub event: Option<Event>,
+    /// The locale for formatting numbers and dates, e.g. `de`.
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Variants of the message for A/B testing.
+    #[serde(default)]
+    pub variants: Vec<MessageVariant>,
+}
+
+/// A variant of the message for A/B testing, which replaces the subject, text,
+/// or html of the message.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MessageVariant {
+    /// The name of the variant, which defaults to `A`, `B`, and so on.
+    pub name: Option<String>,
+    pub subject: Option<String>,
+    pub text: Option<String>,
+    pub html: Option<String>,
 }
 
 impl Message {
@@ -37,6 +82,10 @@ impl Message {
             subject: subject.into(),
             text: text.map(|text| text.into()),
             html: html.map(|text| text.into()),
+            attachments: vec![],
+            event: None,
+            locale: None,
+            variants: vec![],
         }
     }
 
@@ -96,21 +145,57 @@ impl Message {
         }
     }
 
+    /// The messages of the variants by name. Unnamed variants are named `A`,
+    /// `B`, and so on.
+    pub fn variants(&self) -> Vec<(String, Message)> {
+        self.variants
+            .iter()
+            .enumerate()
+            .map(|(i, variant)| {
+                let name = match &variant.name {
+                    Some(name) => name.to_owned(),
+                    None if i < 26 => char::from(b'A' + i as u8).to_string(),
+                    None => format!("V{}", i + 1),
+                };
+                let mut message = self.clone();
+                message.variants = vec![];
+
+                if let Some(subject) = &variant.subject {
+                    message.subject = subject.to_owned();
+                }
+                if let Some(text) = &variant.text {
+                    message.text = Some(text.to_owned());
+                }
+                if let Some(html) = &variant.html {
+                    message.html = Some(html.to_owned());
+                }
+
+                (name, message)
+            })
+            .collect()
+    }
+
+    /// Replace the placeholders of the column by its value. Numbers and dates
+    /// are formatted for the locale of the message.
     pub fn personalize(&mut self, col_name: &str, col_value: &str) {
-        self.subject = self
-            .subject
-            .replace(&format!("{{{}}}", col_name), col_value);
+        let locale = self.locale.as_deref().map(Locale::new).unwrap_or_default();
+
+        self.subject = locale.personalize(&self.subject, col_name, col_value);
         self.text = self
             .text
             .as_ref()
-            .map(|text| text.replace(&format!("{{{}}}", col_name), col_value));
+            .map(|text| locale.personalize(text, col_name, col_value));
         self.html = self
             .html
             .as_ref()
-            .map(|html| html.replace(&format!("{{{}}}", col_name), col_value));
+            .map(|html| locale.personalize(html, col_name, col_value));
+
+        if let Some(event) = &mut self.event {
+            event.personalize(col_name, col_value, &locale);
+        }
     }
 
-    fn read_yaml(path: &Path) -> Result<Self, anyhow::Error> {
+    pub fn read_yaml(path: &Path) -> Result<Self, anyhow::Error> {
         println!("Reading message file '{}' ...", path.display());
         let yaml = fs::read_to_string(path)?;
         let message = serde_yaml::from_str(&yaml)?;
@@ -131,7 +216,7 @@ impl Message {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::app;
+    use crate::{app, email_builder::attachment::Disposition};
 
     #[test]
     fn test_read_yaml() {
@@ -145,7 +230,11 @@ mod tests {
             Message {
                 subject: "Test subject".to_owned(),
                 text: Some("This is a test message (plaintext).".to_owned()),
-                html: Some("<p>This is a test message (html).</p>".to_owned())
+                html: Some("<p>This is a test message (html).</p>".to_owned()),
+                attachments: vec![],
+                event: None,
+                locale: None,
+                variants: vec![],
             }
         )
     }
@@ -162,7 +251,11 @@ mod tests {
             Message {
                 subject: "Test subject".to_owned(),
                 text: Some("".to_owned()),
-                html: Some("".to_owned())
+                html: Some("".to_owned()),
+                attachments: vec![],
+                event: None,
+                locale: None,
+                variants: vec![],
             }
         )
     }
@@ -180,10 +273,82 @@ mod tests {
                 subject: "Test subject".to_owned(),
                 text: None,
                 html: None,
+                attachments: vec![],
+                event: None,
+                locale: None,
+                variants: vec![],
             }
         )
     }
 
+    #[test]
+    fn test_read_yaml_attachments() {
+        let yaml_path = Path::new("./test_data/message_attachments.yaml");
+        let res = Message::read_yaml(yaml_path);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let message = res.unwrap();
+        assert_eq!(
+            message.attachments,
+            vec![
+                AttachmentFile::new(Path::new("./test.pdf")),
+                AttachmentFile {
+                    path: "./test.txt".into(),
+                    name: Some("Prüfbericht März.csv".to_owned()),
+                    content_type: Some("text/csv".to_owned()),
+                    disposition: Disposition::Inline,
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_yaml_event() {
+        let yaml_path = Path::new("./test_data/message_event.yaml");
+        let res = Message::read_yaml(yaml_path);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let message = res.unwrap();
+        assert_eq!(
+            message.event,
+            Some(Event {
+                summary: Some("Meetup".to_owned()),
+                start: "2024-06-01T14:00:00".to_owned(),
+                end: "2024-06-01T15:30:00".to_owned(),
+                timezone: Some("Europe/Berlin".to_owned()),
+                location: Some("Institute for Advanced Study".to_owned()),
+                organizer: None,
+                description: Some("See you there, {first_name}!".to_owned()),
+                uid: Some("meetup-2024-{email}@einstein.com".to_owned()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_variants() {
+        let yaml_path = Path::new("./test_data/message_variants.yaml");
+        let res = Message::read_yaml(yaml_path);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let message = res.unwrap();
+        let variants = message.variants();
+        assert_eq!(variants.len(), 2);
+
+        let (name, variant) = &variants[0];
+        assert_eq!(name, "short");
+        assert_eq!(variant.subject, "New brochure");
+        assert_eq!(variant.text, message.text);
+        assert!(variant.variants.is_empty());
+
+        let (name, variant) = &variants[1];
+        assert_eq!(name, "B");
+        assert_eq!(variant.subject, message.subject);
+        assert_eq!(
+            variant.text.as_deref(),
+            Some("Dear {first_name}, our new brochure is out. Have a look!")
+        );
+    }
+
     #[test]
     fn test_personalize() {
         let text = r#"Dear {first_name} {last_name},
@@ -206,7 +371,11 @@ This is a test message (html)."#;
                 text: Some("Dear Marie Curie,\nThis is a test message (plaintext).".to_owned()),
                 html: Some(
                     "Dear Marie Curie,\n<br>\n<br>\nThis is a test message (html).".to_owned()
-                )
+                ),
+                attachments: vec![],
+                event: None,
+                locale: None,
+                variants: vec![],
             }
         );
     }
@@ -237,6 +406,10 @@ This is a test message (html)."#;
                 subject: "Test subject".to_owned(),
                 text: Some("This is a test message (plaintext).".to_owned()),
                 html: None,
+                attachments: vec![],
+                event: None,
+                locale: None,
+                variants: vec![],
             }
         );
     }
@@ -274,6 +447,10 @@ This is a test message (html)."#;
                     "<p>This is a test message (html).</p>\n\n<p>This is the last line.</p>"
                         .to_owned()
                 ),
+                attachments: vec![],
+                event: None,
+                locale: None,
+                variants: vec![],
             }
         );
     }
@@ -302,6 +479,10 @@ This is a test message (html)."#;
                 subject: "Test subject".to_owned(),
                 text: Some("This is a test message (plaintext).".to_owned()),
                 html: Some("<p>This is a test message (html).</p>".to_owned()),
+                attachments: vec![],
+                event: None,
+                locale: None,
+                variants: vec![],
             }
         );
     }
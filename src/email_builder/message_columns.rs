@@ -0,0 +1,372 @@
+use super::{Attachment, BulkReceiver, Locale, Message, Sender};
+use crate::arg;
+use anyhow::{anyhow, Context};
+use clap::ArgMatches;
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, HashMap},
+    path::{Path, PathBuf},
+};
+
+/// A message file and its attachments.
+type Template = (Message, Vec<Attachment>);
+
+/// Columns of the receiver file or query which select the sender, subject,
+/// and message file of each email. Rows without value fall back to the
+/// sender and message given by argument.
+#[derive(Debug, Default)]
+pub struct MessageColumns {
+    pub sender: Option<String>,
+    pub subject: Option<String>,
+    /// The column with the paths of the message files.
+    pub template: Option<String>,
+    /// The column with the locales which select the variants of the message
+    /// files, e.g. `message.de.yaml` for `message.yaml`.
+    pub locale: Option<String>,
+    /// The locale if the row has none, or no variant exists for it.
+    default_locale: Option<Locale>,
+    /// The message file given by argument.
+    message_file: Option<PathBuf>,
+    /// The attachment given by argument, which is added to each template.
+    attachment: Option<PathBuf>,
+    /// The messages and their attachments by path of the message file, or
+    /// `None` if a variant doesn't exist.
+    templates: HashMap<String, Option<Template>>,
+}
+
+impl MessageColumns {
+    pub fn new(sender: Option<&str>, subject: Option<&str>, template: Option<&str>) -> Self {
+        Self {
+            sender: sender.map(str::to_owned),
+            subject: subject.map(str::to_owned),
+            template: template.map(str::to_owned),
+            ..Default::default()
+        }
+    }
+
+    /// Select the variants of the message files, or of the given message
+    /// file, by the locale column.
+    pub fn with_locale(
+        mut self,
+        locale: &str,
+        default_locale: Option<&str>,
+        message_file: Option<&Path>,
+    ) -> Self {
+        self.locale = Some(locale.to_owned());
+        self.default_locale = default_locale.map(Locale::new);
+        self.message_file = message_file.map(Path::to_owned);
+        self
+    }
+
+    pub fn from_args(matches: &ArgMatches) -> Self {
+        let mut message_columns = Self::new(
+            matches
+                .get_one::<String>(arg::SENDER_COLUMN)
+                .map(String::as_str),
+            matches
+                .get_one::<String>(arg::SUBJECT_COLUMN)
+                .map(String::as_str),
+            matches
+                .get_one::<String>(arg::TEMPLATE_COLUMN)
+                .map(String::as_str),
+        );
+
+        if let Some(locale) = matches.get_one::<String>(arg::LOCALE_COLUMN) {
+            message_columns = message_columns.with_locale(
+                locale,
+                matches
+                    .get_one::<String>(arg::DEFAULT_LOCALE)
+                    .map(String::as_str),
+                matches.get_one::<String>(arg::MESSAGE_FILE).map(Path::new),
+            );
+        }
+
+        message_columns.attachment = matches
+            .get_one::<String>(arg::ATTACHMENT)
+            .map(PathBuf::from);
+        message_columns
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sender.is_none()
+            && self.subject.is_none()
+            && self.template.is_none()
+            && self.locale.is_none()
+    }
+
+    /// Whether the message file is selected per row.
+    pub fn selects_template(&self) -> bool {
+        self.template.is_some() || self.locale.is_some()
+    }
+
+    /// Read the message files of all receivers.
+    pub fn load_templates(&mut self, bulk_receiver: &BulkReceiver) -> Result<(), anyhow::Error> {
+        for i in 0..bulk_receiver.height() {
+            self.load_row(|col_name| bulk_receiver.optional_row(i, col_name))?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the message file of the row and its variants for the locale of
+    /// the row, unless they were read before. The values of the row are given
+    /// by column name.
+    pub fn load_row<'a>(
+        &mut self,
+        value: impl Fn(&str) -> Result<Option<&'a str>, anyhow::Error>,
+    ) -> Result<(), anyhow::Error> {
+        let template = column_value(&self.template, &value)?;
+        let locale = column_value(&self.locale, &value)?;
+
+        for (path, _) in self.variants(template, locale) {
+            if let Entry::Vacant(entry) = self.templates.entry(path) {
+                let template = if Path::new(entry.key()).exists() {
+                    Some(read_template(entry.key(), self.attachment.as_deref())?)
+                } else {
+                    None
+                };
+                entry.insert(template);
+            }
+        }
+
+        if let Some(path) = template {
+            self.load_template(path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the message file and its attachments, unless it was read before.
+    pub fn load_template(&mut self, path: &str) -> Result<(), anyhow::Error> {
+        if path.is_empty() || matches!(self.templates.get(path), Some(Some(_))) {
+            return Ok(());
+        }
+
+        let template = read_template(path, self.attachment.as_deref())?;
+        self.templates.insert(path.to_owned(), Some(template));
+
+        Ok(())
+    }
+
+    /// The attachments of all message files which have been read.
+    pub fn template_attachments(&self) -> impl Iterator<Item = &[Attachment]> {
+        self.templates
+            .values()
+            .flatten()
+            .map(|(_, attachments)| attachments.as_slice())
+    }
+
+    /// Select the sender, message, and attachments of an email by the values
+    /// of the row, which are given by column name. The message files of the
+    /// row must have been read before.
+    pub fn select<'a, 'm>(
+        &'m self,
+        sender: Sender<'a>,
+        message: &'m Message,
+        attachments: &'m [Attachment],
+        value: impl Fn(&str) -> Result<Option<&'a str>, anyhow::Error>,
+    ) -> Result<(Sender<'a>, Cow<'m, Message>, &'m [Attachment]), anyhow::Error> {
+        let sender = column_value(&self.sender, &value)?
+            .map(Sender)
+            .unwrap_or(sender);
+        let template = column_value(&self.template, &value)?;
+        let locale = column_value(&self.locale, &value)?;
+
+        let variant = self
+            .variants(template, locale)
+            .into_iter()
+            .find_map(|(path, locale)| match self.templates.get(&path) {
+                Some(Some(template)) => Some((template, locale)),
+                _ => None,
+            });
+        let (mut message, attachments, locale) = match (variant, template) {
+            (Some(((message, attachments), locale)), _) => {
+                (Cow::Borrowed(message), attachments.as_slice(), Some(locale))
+            }
+            (None, Some(path)) => match self.templates.get(path) {
+                Some(Some((message, attachments))) => {
+                    (Cow::Borrowed(message), attachments.as_slice(), None)
+                }
+                _ => return Err(anyhow!("Message file '{}' hasn't been read", path)),
+            },
+            (None, None) => (Cow::Borrowed(message), attachments, None),
+        };
+
+        if message.locale.is_none() {
+            if let Some(locale) = locale.or_else(|| self.default_locale.clone()) {
+                message.to_mut().locale = Some(locale.tag().to_owned());
+            }
+        }
+
+        if let Some(subject) = column_value(&self.subject, &value)? {
+            message.to_mut().subject = subject.to_owned();
+        }
+
+        Ok((sender, message, attachments))
+    }
+
+    /// The paths of the variants of the message file in the order of
+    /// preference: the locale of the row, its language, and the default
+    /// locale.
+    fn variants(&self, template: Option<&str>, locale: Option<&str>) -> Vec<(String, Locale)> {
+        let Some(path) = template.map(Path::new).or(self.message_file.as_deref()) else {
+            return vec![];
+        };
+
+        let mut locales = vec![];
+
+        for locale in locale.map(Locale::new).iter().chain(&self.default_locale) {
+            for tag in locale.fallbacks() {
+                let locale = Locale::new(tag);
+
+                if !locales.contains(&locale) {
+                    locales.push(locale);
+                }
+            }
+        }
+
+        locales
+            .into_iter()
+            .map(|locale| {
+                let path = Locale::variant_path(path, locale.tag());
+                (path.display().to_string(), locale)
+            })
+            .collect()
+    }
+}
+
+/// Read the message file and its attachments, and add the attachment given by
+/// argument.
+fn read_template(path: &str, attachment: Option<&Path>) -> Result<Template, anyhow::Error> {
+    let message = Message::read_yaml(Path::new(path))
+        .context(format!("Can't read message file '{}'", path))?;
+    let attachments = Attachment::from_files(&message.attachments, attachment)?;
+
+    Ok((message, attachments))
+}
+
+/// The value of the column in the row, if the column is given and the value
+/// isn't empty.
+fn column_value<'a>(
+    column_name: &Option<String>,
+    value: &impl Fn(&str) -> Result<Option<&'a str>, anyhow::Error>,
+) -> Result<Option<&'a str>, anyhow::Error> {
+    match column_name {
+        Some(column_name) => Ok(value(column_name)?.filter(|value| !value.is_empty())),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources;
+
+    #[test]
+    fn test_select() {
+        let df_receiver =
+            sources::read_csv_as_text(Path::new("./test_data/receiver_columns.csv")).unwrap();
+        let receivers = BulkReceiver::new("email".to_owned(), df_receiver);
+        let mut message_columns =
+            MessageColumns::new(Some("sender"), Some("subject"), Some("template"));
+        let res = message_columns.load_templates(&receivers);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let sender = Sender("albert@einstein.com");
+        let message = Message::new("Test subject", Some("This is a test message."), None);
+
+        let res = message_columns.select(sender, &message, &[], |column_name| {
+            receivers.optional_row(0, column_name)
+        });
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let (selected_sender, selected_message, _) = res.unwrap();
+        assert_eq!(selected_sender, Sender("paris@einstein.com"));
+        assert_eq!(selected_message.subject, "Message de test");
+        assert_eq!(
+            selected_message.text.as_deref(),
+            Some("Ceci est un message de test (texte brut).")
+        );
+
+        let res = message_columns.select(sender, &message, &[], |column_name| {
+            receivers.optional_row(1, column_name)
+        });
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let (selected_sender, selected_message, _) = res.unwrap();
+        assert_eq!(selected_sender, sender);
+        assert_eq!(selected_message.subject, "Hello {first_name}");
+        assert_eq!(selected_message.text, message.text);
+    }
+
+    #[test]
+    fn test_select_locale() {
+        let df_receiver =
+            sources::read_csv_as_text(Path::new("./test_data/receiver_locales.csv")).unwrap();
+        let receivers = BulkReceiver::new("email".to_owned(), df_receiver);
+        let message_file = Path::new("./test_data/locales/message.yaml");
+        let mut message_columns = MessageColumns::new(None, None, None).with_locale(
+            "locale",
+            Some("en-GB"),
+            Some(message_file),
+        );
+        let res = message_columns.load_templates(&receivers);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let sender = Sender("albert@einstein.com");
+        let message = Message::read_yaml(message_file).unwrap();
+        let texts = (0..receivers.height())
+            .map(|i| {
+                let (_, message, _) = message_columns
+                    .select(sender, &message, &[], |col_name| {
+                        receivers.optional_row(i, col_name)
+                    })
+                    .unwrap();
+                let mut message = message.into_owned();
+
+                for col_name in ["first_name", "amount", "due_date"] {
+                    message.personalize(col_name, receivers.row(i, col_name).unwrap());
+                }
+
+                (message.subject, message.text.unwrap())
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            texts,
+            vec![
+                (
+                    "Ihre Rechnung".to_owned(),
+                    "Hallo Lise, Ihre Rechnung über 1.234,5 EUR ist am 01.06.2024 fällig."
+                        .to_owned()
+                ),
+                (
+                    "Your invoice".to_owned(),
+                    "Dear Marie, your invoice of 99 EUR is due on 15/06/2024.".to_owned()
+                ),
+                (
+                    "Your invoice".to_owned(),
+                    "Dear Emmy, your invoice of 12,000 EUR is due on 01/07/2024.".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_select_unread_template() {
+        let message_columns = MessageColumns::new(None, None, Some("template"));
+        let message = Message::new("Test subject", Some("This is a test message."), None);
+
+        let res = message_columns.select(Sender("albert@einstein.com"), &message, &[], |_| {
+            Ok(Some("./test_data/message_fr.yaml"))
+        });
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_load_template_missing_file() {
+        let mut message_columns = MessageColumns::new(None, None, Some("template"));
+        let res = message_columns.load_template("./test_data/message_missing.yaml");
+        assert!(res.is_err());
+    }
+}
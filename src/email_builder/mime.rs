@@ -1,15 +1,31 @@
-use super::{Receiver, Sender};
+use super::{Attachment, Receiver, Sender};
 use crate::email_builder;
 use anyhow::{anyhow, Context};
 use lettre::{
-    message::{header, MultiPart, SinglePart},
+    address::Envelope,
+    message::{header, Mailbox, MessageBuilder, MultiPart, SinglePart},
     Message as LettreMessage,
 };
-use std::{fmt, fs, path::Path, str, time::SystemTime};
+use mailparse::MailHeaderMap;
+use std::{fmt, str, time::SystemTime};
+use uuid::Uuid;
 
+/// Headers which describe the content of an email, and which are therefore
+/// part of the MIME entity.
+const CONTENT_HEADERS: [&str; 5] = [
+    "content-type",
+    "content-transfer-encoding",
+    "content-disposition",
+    "content-id",
+    "content-description",
+];
+
+/// An email in MIME format together with its envelope.
 #[derive(Clone)]
 pub struct MimeFormat {
-    pub message: LettreMessage,
+    envelope: Envelope,
+    formatted: Vec<u8>,
+    message_id: Option<String>,
 }
 
 impl MimeFormat {
@@ -17,55 +33,180 @@ impl MimeFormat {
         sender: Sender,
         receiver: Receiver,
         message: &email_builder::Message,
-        attachment: Option<&Path>,
+        attachments: &[Attachment],
         now: SystemTime,
     ) -> Result<Self, anyhow::Error> {
-        let sender = sender.0.parse().context("Can't parse sender")?;
+        let calendar = match &message.event {
+            Some(event) => Some(event.to_ical(&message.subject, sender, &[receiver], now)?),
+            None => None,
+        };
+        let sender: Mailbox = sender.0.parse().context("Can't parse sender")?;
         let receiver = receiver.0.parse().context("Can't parse receiver")?;
         let message_builder = LettreMessage::builder()
+            .message_id(Some(message_id(&sender)))
             .from(sender)
             .to(receiver)
             .subject(&message.subject)
             .date(now);
-        let message = match (&message.text, &message.html, attachment) {
-            (Some(text), Some(html), Some(attachment)) => message_builder.multipart(
-                MultiPart::mixed()
-                    .multipart(
-                        MultiPart::alternative()
-                            .singlepart(Self::singlepart_text_plain(text))
-                            .singlepart(Self::singlepart_text_html(html)),
-                    )
-                    .singlepart(Self::singlepart_attachment(attachment)?),
-            ),
-            (Some(text), Some(html), None) => message_builder.multipart(
-                MultiPart::alternative()
-                    .singlepart(Self::singlepart_text_plain(text))
-                    .singlepart(Self::singlepart_text_html(html)),
-            ),
-            (Some(text), None, Some(attachment)) => message_builder.multipart(
-                MultiPart::mixed()
-                    .singlepart(Self::singlepart_text_plain(text))
-                    .singlepart(Self::singlepart_attachment(attachment)?),
-            ),
-            (None, Some(html), Some(attachment)) => message_builder.multipart(
-                MultiPart::mixed()
-                    .singlepart(Self::singlepart_text_html(html))
-                    .singlepart(Self::singlepart_attachment(attachment)?),
-            ),
-            (Some(text), None, None) => {
-                message_builder.singlepart(Self::singlepart_text_plain(text))
-            }
-            (None, Some(html), None) => {
-                message_builder.singlepart(Self::singlepart_text_html(html))
-            }
-            (None, None, Some(attachment)) => {
-                message_builder.singlepart(Self::singlepart_attachment(attachment)?)
+        let message = Self::build(message_builder, message, calendar, attachments)?;
+
+        Ok(Self::from_message(message))
+    }
+
+    /// Create a single email for multiple receivers.
+    ///
+    /// Like a blind carbon copy, the receivers are only part of the envelope
+    /// and don't show up in the headers. The email is addressed to the sender
+    /// instead. For the same reason, invitations to an event don't list the
+    /// receivers as attendees.
+    pub fn new_batch(
+        sender: Sender,
+        receivers: &[Receiver],
+        message: &email_builder::Message,
+        attachments: &[Attachment],
+        now: SystemTime,
+    ) -> Result<Self, anyhow::Error> {
+        let calendar = match &message.event {
+            Some(event) => Some(event.to_ical(&message.subject, sender, &[], now)?),
+            None => None,
+        };
+        let sender: Mailbox = sender.0.parse().context("Can't parse sender")?;
+        let receivers = receivers
+            .iter()
+            .map(|receiver| {
+                receiver
+                    .0
+                    .parse::<Mailbox>()
+                    .map(|mailbox| mailbox.email)
+                    .context(format!("Can't parse receiver '{}'", receiver.0))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let envelope = Envelope::new(Some(sender.email.clone()), receivers)
+            .context("Can't create envelope")?;
+        let message_builder = LettreMessage::builder()
+            .message_id(Some(message_id(&sender)))
+            .from(sender.clone())
+            .to(sender)
+            .subject(&message.subject)
+            .date(now)
+            .envelope(envelope);
+        let message = Self::build(message_builder, message, calendar, attachments)?;
+
+        Ok(Self::from_message(message))
+    }
+
+    /// Use an already formatted email, e.g. an archived email, as is.
+    pub fn from_raw(envelope: Envelope, formatted: Vec<u8>) -> Result<Self, anyhow::Error> {
+        let (headers, _) =
+            mailparse::parse_headers(&formatted).context("Can't parse MIME formatted email")?;
+        let message_id = headers.get_first_value("Message-ID");
+
+        Ok(Self {
+            envelope,
+            formatted,
+            message_id,
+        })
+    }
+
+    fn from_message(message: LettreMessage) -> Self {
+        Self {
+            envelope: message.envelope().clone(),
+            formatted: message.formatted(),
+            message_id: message.headers().get_raw("Message-ID").map(str::to_owned),
+        }
+    }
+
+    pub fn envelope(&self) -> &Envelope {
+        &self.envelope
+    }
+
+    /// The email in MIME format as sent over the wire.
+    pub fn formatted(&self) -> &[u8] {
+        &self.formatted
+    }
+
+    /// The value of the Message-ID header.
+    pub fn message_id(&self) -> Option<&str> {
+        self.message_id.as_deref()
+    }
+
+    /// Split the email into the headers of the email, e.g. From and Subject,
+    /// and the MIME entity consisting of the content headers and the body.
+    pub fn split_entity(&self) -> Result<(Vec<u8>, Vec<u8>), anyhow::Error> {
+        split_entity(&self.formatted)
+    }
+
+    /// Replace the MIME entity of the email, e.g. by a signed or encrypted
+    /// entity.
+    pub fn with_entity(&self, headers: &[u8], entity: &[u8]) -> Result<Self, anyhow::Error> {
+        let mut formatted = headers.to_vec();
+        formatted.extend_from_slice(b"MIME-Version: 1.0\r\n");
+        formatted.extend_from_slice(entity);
+
+        Self::from_raw(self.envelope.clone(), formatted)
+    }
+
+    fn build(
+        message_builder: MessageBuilder,
+        message: &email_builder::Message,
+        calendar: Option<String>,
+        attachments: &[Attachment],
+    ) -> Result<LettreMessage, anyhow::Error> {
+        let mut alternatives = vec![];
+
+        if let Some(text) = &message.text {
+            alternatives.push(Self::singlepart_text_plain(text));
+        }
+
+        if let Some(html) = &message.html {
+            alternatives.push(Self::singlepart_text_html(html));
+        }
+
+        if let Some(calendar) = calendar {
+            alternatives.push(Self::singlepart_text_calendar(calendar));
+        }
+
+        let mut attachments = attachments.iter().map(Attachment::singlepart);
+        let body = match Self::alternative(alternatives) {
+            Some(body) => body,
+            None => Body::Single(attachments.next().ok_or(anyhow!("Missing email body"))?),
+        };
+
+        let message = if attachments.len() == 0 {
+            match body {
+                Body::Single(singlepart) => message_builder.singlepart(singlepart),
+                Body::Multi(multipart) => message_builder.multipart(multipart),
             }
-            (None, None, None) => return Err(anyhow!("Missing email body")),
+        } else {
+            let multipart = match body {
+                Body::Single(singlepart) => MultiPart::mixed().singlepart(singlepart),
+                Body::Multi(multipart) => MultiPart::mixed().multipart(multipart),
+            };
+            message_builder.multipart(attachments.fold(multipart, |multipart, attachment| {
+                multipart.singlepart(attachment)
+            }))
         }
         .context("Can't create MIME formatted email")?;
 
-        Ok(Self { message })
+        Ok(message)
+    }
+
+    /// Combine alternative representations of the message, e.g. plaintext and
+    /// HTML.
+    fn alternative(alternatives: Vec<SinglePart>) -> Option<Body> {
+        let mut alternatives = alternatives.into_iter();
+        let first = alternatives.next()?;
+
+        if alternatives.len() == 0 {
+            return Some(Body::Single(first));
+        }
+
+        let multipart = alternatives.fold(
+            MultiPart::alternative().singlepart(first),
+            |multipart, alternative| multipart.singlepart(alternative),
+        );
+
+        Some(Body::Multi(multipart))
     }
 
     fn singlepart_text_plain(text: &str) -> SinglePart {
@@ -80,33 +221,79 @@ impl MimeFormat {
             .body(text.to_string())
     }
 
-    fn singlepart_attachment(path: &Path) -> Result<SinglePart, anyhow::Error> {
-        let file_name = match path.file_name() {
-            Some(file_name) => match file_name.to_str() {
-                Some(file_name) => file_name,
-                None => {
-                    return Err(anyhow!(
-                        "Email attachment error: Invalid characters in file name"
-                    ))
-                }
-            },
-            None => return Err(anyhow!("Can't find attachment")),
-        };
-        let bytes = fs::read(path).context("Can't read attachment")?;
-        let content_type = match infer::get(&bytes) {
-            Some(file_type) => file_type.mime_type(),
-            // Handle 'None': Compare internet standard RFC-2046, RFC-7231, and https://stackoverflow.com/questions/1176022/unknown-file-type-mime
-            None => "application/octet-stream",
-        };
+    fn singlepart_text_calendar(calendar: String) -> SinglePart {
+        SinglePart::builder()
+            .header(
+                header::ContentType::parse("text/calendar; charset=utf-8; method=REQUEST")
+                    .expect("Can't parse content type"),
+            )
+            .body(calendar)
+    }
+}
+
+/// The body of an email, or of the message in an email with attachments.
+enum Body {
+    Single(SinglePart),
+    Multi(MultiPart),
+}
+
+/// Split a formatted email into the headers of the email, e.g. From and
+/// Subject, and the MIME entity consisting of the content headers and the
+/// body. The MIME-Version header is dropped.
+fn split_entity(formatted: &[u8]) -> Result<(Vec<u8>, Vec<u8>), anyhow::Error> {
+    let header_end = formatted
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or(anyhow!("Can't find end of headers"))?;
+    let (header_section, body) = formatted.split_at(header_end + 2);
+    let mut headers = vec![];
+    let mut content_headers = vec![];
+
+    for field in header_fields(header_section) {
+        let name = field
+            .split(|&byte| byte == b':')
+            .next()
+            .map(|name| String::from_utf8_lossy(name).trim().to_lowercase())
+            .unwrap_or_default();
+
+        if name == "mime-version" {
+            continue;
+        } else if CONTENT_HEADERS.contains(&name.as_str()) {
+            content_headers.extend_from_slice(field);
+        } else {
+            headers.extend_from_slice(field);
+        }
+    }
+
+    content_headers.extend_from_slice(body);
+
+    Ok((headers, content_headers))
+}
+
+/// Split the header section into header fields including their folded
+/// continuation lines and line breaks.
+fn header_fields(header_section: &[u8]) -> Vec<&[u8]> {
+    let mut fields = vec![];
+    let mut start = 0;
+
+    for (index, window) in header_section.windows(3).enumerate() {
+        // A line break not followed by whitespace ends a field
+        if &window[..2] == b"\r\n" && window[2] != b' ' && window[2] != b'\t' {
+            fields.push(&header_section[start..index + 2]);
+            start = index + 2;
+        }
+    }
 
-        Ok(SinglePart::builder()
-            .header(header::ContentType::parse(content_type).context(format!(
-                "File type '{}' not supported: {}",
-                content_type, file_name
-            ))?)
-            .header(header::ContentDisposition::attachment(file_name))
-            .body(bytes))
+    if start < header_section.len() {
+        fields.push(&header_section[start..]);
     }
+
+    fields
+}
+
+/// Generate a unique Message-ID in the domain of the sender.
+fn message_id(sender: &Mailbox) -> String {
+    format!("<{}@{}>", Uuid::new_v4(), sender.email.domain())
 }
 
 impl fmt::Debug for MimeFormat {
@@ -114,7 +301,7 @@ impl fmt::Debug for MimeFormat {
         write!(
             f,
             "{}",
-            str::from_utf8(&self.message.formatted()).expect("Can't convert from utf8")
+            str::from_utf8(&self.formatted).expect("Can't convert from utf8")
         )
     }
 }
@@ -123,14 +310,14 @@ impl fmt::Debug for MimeFormat {
 mod tests {
     use self::email_builder::Message;
     use super::*;
-    use std::{fs::File, io::Read, time::UNIX_EPOCH};
+    use std::{fs::File, io::Read, path::Path, time::UNIX_EPOCH};
 
     impl MimeFormat {
         pub fn new_with_boundaries(
             sender: &str,
             receiver: &str,
             message: &email_builder::Message,
-            attachment: Option<&Path>,
+            attachments: &[Attachment],
             now: SystemTime,
             boundaries: Vec<&str>,
         ) -> Result<Self, anyhow::Error> {
@@ -141,7 +328,7 @@ mod tests {
                 .to(receiver)
                 .subject(&message.subject)
                 .date(now);
-            let message = match (&message.text, &message.html, attachment) {
+            let message = match (&message.text, &message.html, attachments.first()) {
                 (Some(text), Some(html), Some(attachment)) => message_builder.multipart(
                     MultiPart::mixed()
                         .boundary(boundaries[0])
@@ -151,7 +338,7 @@ mod tests {
                                 .singlepart(Self::singlepart_text_plain(text))
                                 .singlepart(Self::singlepart_text_html(html)),
                         )
-                        .singlepart(Self::singlepart_attachment(attachment)?),
+                        .singlepart(attachment.singlepart()),
                 ),
                 (Some(text), Some(html), None) => message_builder.multipart(
                     MultiPart::alternative()
@@ -163,13 +350,13 @@ mod tests {
                     MultiPart::mixed()
                         .boundary(boundaries[0])
                         .singlepart(Self::singlepart_text_plain(text))
-                        .singlepart(Self::singlepart_attachment(attachment)?),
+                        .singlepart(attachment.singlepart()),
                 ),
                 (None, Some(html), Some(attachment)) => message_builder.multipart(
                     MultiPart::mixed()
                         .boundary(boundaries[0])
                         .singlepart(Self::singlepart_text_html(html))
-                        .singlepart(Self::singlepart_attachment(attachment)?),
+                        .singlepart(attachment.singlepart()),
                 ),
                 (Some(text), None, None) => {
                     message_builder.singlepart(Self::singlepart_text_plain(text))
@@ -178,16 +365,54 @@ mod tests {
                     message_builder.singlepart(Self::singlepart_text_html(html))
                 }
                 (None, None, Some(attachment)) => {
-                    message_builder.singlepart(Self::singlepart_attachment(attachment)?)
+                    message_builder.singlepart(attachment.singlepart())
                 }
                 (None, None, None) => return Err(anyhow!("Missing email body")),
             }
             .context("Can't create MIME formatted email")?;
 
-            Ok(Self { message })
+            Ok(Self::from_message(message))
         }
     }
 
+    /// Remove the randomly generated Message-ID header.
+    fn without_message_id(mime_format: &str) -> String {
+        mime_format
+            .split_inclusive('\n')
+            .filter(|line| !line.starts_with("Message-ID: "))
+            .collect()
+    }
+
+    #[test]
+    fn test_split_entity() {
+        let formatted = b"From: albert@einstein.com\r\nSubject: Test\r\n subject\r\nMIME-Version: 1.0\r\nContent-Type: text/plain;\r\n charset=utf-8\r\nContent-Transfer-Encoding: 7bit\r\n\r\nDear Marie\r\n";
+
+        let (headers, entity) = split_entity(formatted).unwrap();
+        assert_eq!(
+            String::from_utf8(headers).unwrap(),
+            "From: albert@einstein.com\r\nSubject: Test\r\n subject\r\n"
+        );
+        assert_eq!(
+            String::from_utf8(entity).unwrap(),
+            "Content-Type: text/plain;\r\n charset=utf-8\r\nContent-Transfer-Encoding: 7bit\r\n\r\nDear Marie\r\n"
+        );
+    }
+
+    #[test]
+    fn test_mime_format_message_id() {
+        let sender = Sender("albert@einstein.com");
+        let receiver = Receiver("marie@curie.com");
+        let message = Message::new("Test Subject", Some("This is a test message."), None);
+
+        let first = MimeFormat::new(sender, receiver, &message, &[], SystemTime::now()).unwrap();
+        let second = MimeFormat::new(sender, receiver, &message, &[], SystemTime::now()).unwrap();
+
+        let message_id = first.message_id().unwrap();
+        assert!(message_id.starts_with('<'));
+        assert!(message_id.ends_with("@einstein.com>"));
+        assert_ne!(message_id, second.message_id().unwrap());
+    }
+
     #[test]
     fn test_mime_format_singlepart_plaintext() {
         let date_time = chrono::DateTime::parse_from_rfc3339("2024-01-01T14:00:00Z")
@@ -200,16 +425,94 @@ mod tests {
         let text = "This is a test message (plaintext).";
         let message = Message::new(subject, Some(text), None);
 
-        let res = MimeFormat::new(sender, receiver, &message, None, system_time);
+        let res = MimeFormat::new(sender, receiver, &message, &[], system_time);
         assert!(res.is_ok());
 
-        let mime_format = format!("{:?}", res.unwrap());
+        let mime_format = without_message_id(&format!("{:?}", res.unwrap()));
         let mut expected_file = File::open("./test_data/email_singlepart_plaintext.txt").unwrap();
         let mut expected_format = String::new();
         expected_file.read_to_string(&mut expected_format).unwrap();
         assert_eq!(mime_format.replace('\r', ""), expected_format);
     }
 
+    #[test]
+    fn test_mime_format_batch() {
+        let sender = Sender("albert@einstein.com");
+        let receivers = [Receiver("marie@curie.com"), Receiver("emmy@noether.com")];
+        let subject = "Test Subject";
+        let text = "This is a test message (plaintext).";
+        let message = Message::new(subject, Some(text), None);
+
+        let res = MimeFormat::new_batch(sender, &receivers, &message, &[], SystemTime::now());
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let mime_format = res.unwrap();
+        let envelope = mime_format.envelope();
+        assert_eq!(envelope.from().unwrap().to_string(), "albert@einstein.com");
+        assert_eq!(
+            envelope
+                .to()
+                .iter()
+                .map(|address| address.to_string())
+                .collect::<Vec<_>>(),
+            vec!["marie@curie.com", "emmy@noether.com"]
+        );
+
+        let formatted = format!("{:?}", mime_format);
+        assert!(formatted.contains("To: albert@einstein.com"));
+        assert!(!formatted.contains("marie@curie.com"));
+        assert!(!formatted.contains("emmy@noether.com"));
+    }
+
+    #[test]
+    fn test_mime_format_event() {
+        let sender = Sender("albert@einstein.com");
+        let receiver = Receiver("marie@curie.com");
+        let mut message = Message::new("Test Subject", Some("This is a test message."), None);
+        message.event = Some(email_builder::Event {
+            summary: None,
+            start: "2024-06-01T14:00:00Z".to_owned(),
+            end: "2024-06-01T15:00:00Z".to_owned(),
+            timezone: None,
+            location: None,
+            organizer: None,
+            description: None,
+            uid: None,
+        });
+        let attachment = Attachment::new(Path::new("./test_data/test.pdf")).unwrap();
+
+        let res = MimeFormat::new(
+            sender,
+            receiver,
+            &message,
+            std::slice::from_ref(&attachment),
+            SystemTime::now(),
+        );
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let formatted = format!("{:?}", res.unwrap());
+        assert!(formatted.contains("Content-Type: multipart/mixed;"));
+        assert!(formatted.contains("Content-Type: multipart/alternative;"));
+        assert!(formatted.contains("Content-Type: text/plain; charset=utf-8"));
+        assert!(formatted.contains("Content-Type: text/calendar; charset=utf-8; method=REQUEST"));
+        assert!(formatted.contains("SUMMARY:Test Subject"));
+        assert!(formatted.contains("ATTENDEE;"));
+        assert!(formatted.contains("Content-Type: application/pdf"));
+
+        let res = MimeFormat::new_batch(
+            sender,
+            &[receiver, Receiver("emmy@noether.com")],
+            &message,
+            &[],
+            SystemTime::now(),
+        );
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let formatted = format!("{:?}", res.unwrap());
+        assert!(formatted.contains("METHOD:REQUEST"));
+        assert!(!formatted.contains("ATTENDEE"));
+    }
+
     #[test]
     fn test_mime_format_singlepart_html() {
         let date_time = chrono::DateTime::parse_from_rfc3339("2024-01-01T14:00:00Z")
@@ -222,10 +525,10 @@ mod tests {
         let html = "<p>This is a test message (html).</p>";
         let message = Message::new(subject, None, Some(html));
 
-        let res = MimeFormat::new(sender, receiver, &message, None, system_time);
+        let res = MimeFormat::new(sender, receiver, &message, &[], system_time);
         assert!(res.is_ok());
 
-        let mime_format = format!("{:?}", res.unwrap());
+        let mime_format = without_message_id(&format!("{:?}", res.unwrap()));
         let mut expected_file = File::open("./test_data/email_singlepart_html.txt").unwrap();
         let mut expected_format = String::new();
         expected_file.read_to_string(&mut expected_format).unwrap();
@@ -242,12 +545,18 @@ mod tests {
         let receiver = Receiver("marie@curie.com");
         let subject = "Test Subject";
         let message = Message::new(subject, None, None);
-        let attachment = Path::new("./test_data/test.txt");
+        let attachment = Attachment::new(Path::new("./test_data/test.txt")).unwrap();
 
-        let res = MimeFormat::new(sender, receiver, &message, Some(attachment), system_time);
+        let res = MimeFormat::new(
+            sender,
+            receiver,
+            &message,
+            std::slice::from_ref(&attachment),
+            system_time,
+        );
         assert!(res.is_ok());
 
-        let mime_format = format!("{:?}", res.unwrap());
+        let mime_format = without_message_id(&format!("{:?}", res.unwrap()));
         let mut expected_file = File::open("./test_data/email_singlepart_attachment.txt").unwrap();
         let mut expected_format = String::new();
         expected_file.read_to_string(&mut expected_format).unwrap();
@@ -272,7 +581,7 @@ mod tests {
             sender,
             receiver,
             &message,
-            None,
+            &[],
             system_time,
             boundaries,
         );
@@ -297,13 +606,13 @@ mod tests {
         let text = "This is a test message (plaintext).";
         let message = Message::new(subject, Some(text), None);
         let boundaries = vec!["RZcCpBhV4GEzm8ETTVblOuzZ8bwGzGVyjkQfGTMt"];
-        let attachment = Path::new("./test_data/test.txt");
+        let attachment = Attachment::new(Path::new("./test_data/test.txt")).unwrap();
 
         let res = MimeFormat::new_with_boundaries(
             sender,
             receiver,
             &message,
-            Some(attachment),
+            std::slice::from_ref(&attachment),
             system_time,
             boundaries,
         );
@@ -332,13 +641,13 @@ mod tests {
             "OTi56O3hPypBNfzLsCk053S1timfKY03AexmLpxU",
             "HDKQ1fKhhPf7wLdMpdLSlteF05Rxv6VCIqIQf82I",
         ];
-        let attachment = Path::new("./test_data/test.txt");
+        let attachment = Attachment::new(Path::new("./test_data/test.txt")).unwrap();
 
         let res = MimeFormat::new_with_boundaries(
             sender,
             receiver,
             &message,
-            Some(attachment),
+            std::slice::from_ref(&attachment),
             system_time,
             boundaries,
         );
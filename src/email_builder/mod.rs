@@ -1,14 +1,48 @@
+mod attachment;
+mod duplicates;
 mod email;
+mod event;
+mod locale;
 mod message;
+mod message_columns;
 mod mime;
+mod pgp;
 mod receiver;
+mod receiver_keys;
 mod sender;
+mod smime;
+mod split;
 
+pub use attachment::{Attachment, AttachmentFile};
+pub use duplicates::{Deduplication, Duplicate, DuplicatePolicy};
 pub use email::{BulkEmail, Email};
+pub use event::Event;
+pub use locale::Locale;
 pub use message::Message;
+pub use message_columns::MessageColumns;
 pub use mime::MimeFormat;
-pub use receiver::{BulkReceiver, Receiver};
+pub use pgp::Pgp;
+pub use receiver::{BulkReceiver, Receiver, ReceiverRow, ReceiverStream, StreamedRow};
 pub use sender::Sender;
+pub use smime::Smime;
+pub use split::SplitTest;
+use std::collections::HashMap;
+
+/// Sign and encrypt emails before they are sent and archived.
+pub trait SecureEmail {
+    /// The receiver column with the paths of the keys of the receivers, if
+    /// the keys are looked up by column.
+    fn key_column(&self) -> Option<&str>;
+
+    /// Sign and then encrypt the email, and replace its MIME format. If the
+    /// keys of the receivers are looked up by column, their paths are given
+    /// by receiver.
+    fn secure(
+        &self,
+        email: &mut Email,
+        key_paths: &HashMap<String, String>,
+    ) -> Result<(), anyhow::Error>;
+}
 
 pub enum Confirmed {
     Yes,
@@ -0,0 +1,493 @@
+use super::{receiver_keys::ReceiverKeys, Email, SecureEmail};
+use crate::arg;
+use anyhow::{anyhow, Context};
+use clap::ArgMatches;
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+};
+use uuid::Uuid;
+
+// File extensions of the public keys in `--pgp-key-dir`
+const PUBLIC_KEY_EXTENSIONS: [&str; 2] = ["asc", "gpg"];
+
+/// Sign and encrypt emails according to PGP/MIME (RFC 3156).
+///
+/// The keys are processed by GnuPG in a temporary home directory, so that the
+/// keyring of the user is left untouched.
+pub struct Pgp {
+    home_dir: PathBuf,
+    signer: Option<Signer>,
+    public_keys: Option<ReceiverKeys>,
+}
+
+/// The secret key of the sender, imported into the temporary home directory.
+struct Signer {
+    fingerprint: String,
+    /// Path of the file with the passphrase of the secret key, if any.
+    passphrase_file: Option<PathBuf>,
+}
+
+impl Pgp {
+    /// Returns `None` if neither signing nor encryption is requested.
+    pub fn from_args(matches: &ArgMatches) -> Result<Option<Self>, anyhow::Error> {
+        let secret_key = matches.get_one::<String>(arg::PGP_KEY).map(Path::new);
+        let public_keys = ReceiverKeys::from_args(matches, arg::PGP_KEY_DIR, arg::PGP_KEY_COLUMN)?;
+
+        if secret_key.is_none() && public_keys.is_none() {
+            return Ok(None);
+        }
+
+        // The passphrase is not passed as argument to keep it out of the shell
+        // history
+        let passphrase = env::var("PGP_PASSPHRASE").ok();
+
+        Self::new(secret_key, passphrase.as_deref(), public_keys).map(Some)
+    }
+
+    fn new(
+        secret_key: Option<&Path>,
+        passphrase: Option<&str>,
+        public_keys: Option<ReceiverKeys>,
+    ) -> Result<Self, anyhow::Error> {
+        // Fail before sending instead of at the first email
+        check_gpg()?;
+
+        let home_dir = env::temp_dir().join(format!("pigeon-gnupg-{}", Uuid::new_v4().simple()));
+        let mut dir_builder = fs::DirBuilder::new();
+
+        // GnuPG refuses home directories which are accessible by other users
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut dir_builder, 0o700);
+
+        dir_builder.create(&home_dir).context(format!(
+            "Can't create GnuPG home directory '{}'",
+            home_dir.display()
+        ))?;
+
+        let mut pgp = Self {
+            home_dir,
+            signer: None,
+            public_keys,
+        };
+
+        if let Some(secret_key) = secret_key {
+            pgp.signer = Some(pgp.import_secret_key(secret_key, passphrase)?);
+        }
+
+        Ok(pgp)
+    }
+
+    fn import_secret_key(
+        &self,
+        path: &Path,
+        passphrase: Option<&str>,
+    ) -> Result<Signer, anyhow::Error> {
+        let passphrase_file = match passphrase {
+            Some(passphrase) => {
+                let passphrase_file = self.home_dir.join("passphrase");
+                let mut options = fs::OpenOptions::new();
+                options.write(true).create_new(true);
+
+                // Only readable by the user, like the home directory
+                #[cfg(unix)]
+                std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+                options
+                    .open(&passphrase_file)
+                    .and_then(|mut file| file.write_all(passphrase.as_bytes()))
+                    .context("Can't write passphrase of secret key")?;
+                Some(passphrase_file)
+            }
+            None => None,
+        };
+        let mut signer = Signer {
+            fingerprint: String::new(),
+            passphrase_file,
+        };
+
+        let mut args = signer.passphrase_args();
+        args.extend(["--import".to_owned(), path.display().to_string()]);
+        self.gpg(&args, &[])
+            .context(format!("Can't import secret key '{}'", path.display()))?;
+
+        let secret_keys = self.gpg(&["--with-colons", "--list-secret-keys"], &[])?;
+        // The first fingerprint belongs to the primary key
+        signer.fingerprint = String::from_utf8_lossy(&secret_keys)
+            .lines()
+            .find(|line| line.starts_with("fpr:"))
+            .and_then(|line| line.split(':').nth(9))
+            .map(str::to_owned)
+            .ok_or(anyhow!("Missing secret key in '{}'", path.display()))?;
+
+        Ok(signer)
+    }
+
+    /// Run gpg in the temporary home directory, and return its output.
+    fn gpg<S>(&self, args: &[S], input: &[u8]) -> Result<Vec<u8>, anyhow::Error>
+    where
+        S: AsRef<str>,
+    {
+        let mut child = Command::new("gpg")
+            .arg("--homedir")
+            .arg(&self.home_dir)
+            .args(["--batch", "--no-tty", "--quiet"])
+            .args(args.iter().map(AsRef::as_ref))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Can't run gpg. Is GnuPG installed?")?;
+
+        // Write the input in a separate thread, so that gpg doesn't block on a
+        // full output pipe
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or(anyhow!("Can't open stdin of gpg"))?;
+        let input = input.to_vec();
+        let writer = thread::spawn(move || stdin.write_all(&input));
+
+        let output = child.wait_with_output().context("Can't run gpg")?;
+        writer
+            .join()
+            .map_err(|_| anyhow!("Can't write to gpg"))?
+            .context("Can't write to gpg")?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "gpg failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(output.stdout)
+    }
+
+    /// Create a `multipart/signed` entity with a detached signature.
+    fn sign(&self, signer: &Signer, entity: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        let mut args = signer.passphrase_args();
+        args.extend(
+            [
+                "--local-user",
+                &signer.fingerprint,
+                "--digest-algo",
+                "SHA256",
+                "--armor",
+                "--detach-sign",
+            ]
+            .map(str::to_owned),
+        );
+        let signature = self.gpg(&args, entity).context("Can't sign email")?;
+        let boundary = Uuid::new_v4().simple().to_string();
+
+        let mut signed = format!(
+            "Content-Type: multipart/signed; micalg=pgp-sha256;\r\n protocol=\"application/pgp-signature\"; boundary=\"{boundary}\"\r\n\r\n--{boundary}\r\n"
+        )
+        .into_bytes();
+        signed.extend_from_slice(entity);
+        signed.extend_from_slice(
+            format!(
+                "\r\n--{boundary}\r\n\
+                Content-Type: application/pgp-signature; name=\"signature.asc\"\r\n\
+                Content-Description: OpenPGP digital signature\r\n\
+                Content-Disposition: attachment; filename=\"signature.asc\"\r\n\r\n\
+                {}\r\n--{boundary}--\r\n",
+                crlf(&signature).trim_end()
+            )
+            .as_bytes(),
+        );
+
+        Ok(signed)
+    }
+
+    /// Create a `multipart/encrypted` entity which can only be read by the
+    /// owners of the public keys. If the email is signed as well, the
+    /// signature is part of the encrypted data.
+    fn encrypt(&self, public_keys: &[PathBuf], entity: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        let mut args = vec![];
+
+        for public_key in public_keys {
+            args.extend([
+                "--recipient-file".to_owned(),
+                public_key.display().to_string(),
+            ]);
+        }
+
+        if let Some(signer) = &self.signer {
+            args.extend(signer.passphrase_args());
+            // Encrypt to the sender as well, so that archived emails stay
+            // readable
+            args.extend(
+                [
+                    "--trust-model",
+                    "always",
+                    "--recipient",
+                    &signer.fingerprint,
+                    "--local-user",
+                    &signer.fingerprint,
+                    "--digest-algo",
+                    "SHA256",
+                    "--sign",
+                ]
+                .map(str::to_owned),
+            );
+        }
+
+        args.extend(["--armor".to_owned(), "--encrypt".to_owned()]);
+        let encrypted = self.gpg(&args, entity).context("Can't encrypt email")?;
+        let boundary = Uuid::new_v4().simple().to_string();
+
+        Ok(format!(
+            "Content-Type: multipart/encrypted; protocol=\"application/pgp-encrypted\";\r\n boundary=\"{boundary}\"\r\n\r\n\
+            --{boundary}\r\n\
+            Content-Type: application/pgp-encrypted\r\n\
+            Content-Description: PGP/MIME version identification\r\n\r\n\
+            Version: 1\r\n\r\n\
+            --{boundary}\r\n\
+            Content-Type: application/octet-stream; name=\"encrypted.asc\"\r\n\
+            Content-Description: OpenPGP encrypted message\r\n\
+            Content-Disposition: inline; filename=\"encrypted.asc\"\r\n\r\n\
+            {}\r\n--{boundary}--\r\n",
+            crlf(&encrypted).trim_end()
+        )
+        .into_bytes())
+    }
+}
+
+impl SecureEmail for Pgp {
+    fn key_column(&self) -> Option<&str> {
+        self.public_keys.as_ref().and_then(ReceiverKeys::column)
+    }
+
+    fn secure(
+        &self,
+        email: &mut Email,
+        key_paths: &HashMap<String, String>,
+    ) -> Result<(), anyhow::Error> {
+        let (headers, entity) = email.mime_format.split_entity()?;
+
+        let entity = match (&self.signer, &self.public_keys) {
+            (_, Some(public_keys)) => {
+                let public_keys = email
+                    .receivers()
+                    .iter()
+                    .map(|receiver| lookup_public_key(public_keys, receiver.0, key_paths))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.encrypt(&public_keys, &entity)?
+            }
+            (Some(signer), None) => self.sign(signer, &entity)?,
+            (None, None) => entity,
+        };
+
+        email.mime_format = email.mime_format.with_entity(&headers, &entity)?;
+
+        Ok(())
+    }
+}
+
+impl Drop for Pgp {
+    fn drop(&mut self) {
+        // Stop the agent which gpg started for the temporary home directory
+        let _ = Command::new("gpgconf")
+            .arg("--homedir")
+            .arg(&self.home_dir)
+            .args(["--kill", "gpg-agent"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        let _ = fs::remove_dir_all(&self.home_dir);
+    }
+}
+
+impl Signer {
+    fn passphrase_args(&self) -> Vec<String> {
+        match &self.passphrase_file {
+            Some(passphrase_file) => vec![
+                "--pinentry-mode".to_owned(),
+                "loopback".to_owned(),
+                "--passphrase-file".to_owned(),
+                passphrase_file.display().to_string(),
+            ],
+            None => vec![],
+        }
+    }
+}
+
+/// Check that the gpg binary of GnuPG can be run.
+fn check_gpg() -> Result<(), anyhow::Error> {
+    let status = Command::new("gpg")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(anyhow!("Can't run gpg: {}", status)),
+        Err(err) => Err(anyhow!(
+            "Can't run gpg, which is required for PGP/MIME. Please install GnuPG: {}",
+            err
+        )),
+    }
+}
+
+fn lookup_public_key(
+    public_keys: &ReceiverKeys,
+    receiver: &str,
+    key_paths: &HashMap<String, String>,
+) -> Result<PathBuf, anyhow::Error> {
+    let path = public_keys.lookup(receiver, key_paths, "public key", &PUBLIC_KEY_EXTENSIONS)?;
+
+    if !path.is_file() {
+        return Err(anyhow!("Can't find public key '{}'", path.display()));
+    }
+
+    Ok(path)
+}
+
+/// Convert the line breaks of the ASCII armored output of gpg to CRLF.
+fn crlf(armored: &[u8]) -> String {
+    String::from_utf8_lossy(armored)
+        .lines()
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email_builder::{Message, MimeFormat, Receiver, Sender};
+    use std::time::SystemTime;
+    use tempfile::tempdir;
+
+    fn email<'a>(sender: Sender<'a>, receiver: Receiver<'a>) -> Email<'a> {
+        let message = Message::new("Test subject", Some("This is a test message."), None);
+        let mime_format =
+            MimeFormat::new(sender, receiver, &message, &[], SystemTime::now()).unwrap();
+        Email::new(sender, receiver, &message, &mime_format).unwrap()
+    }
+
+    /// Extract the ASCII armored block of the given type, e.g. `PGP MESSAGE`.
+    fn armored<'a>(formatted: &'a str, block_type: &str) -> &'a str {
+        let start = formatted
+            .find(&format!("-----BEGIN {}-----", block_type))
+            .unwrap();
+        let end_line = format!("-----END {}-----", block_type);
+        let end = formatted.find(&end_line).unwrap() + end_line.len();
+        &formatted[start..end]
+    }
+
+    #[test]
+    fn test_sign() {
+        let temp_dir = tempdir().unwrap();
+        let pgp = Pgp::new(
+            Some(Path::new("./test_data/pgp/albert@einstein.com.key")),
+            None,
+            None,
+        )
+        .unwrap();
+        let mut email = email(Sender("albert@einstein.com"), Receiver("marie@curie.com"));
+        let (_, entity) = email.mime_format.split_entity().unwrap();
+
+        let res = pgp.secure(&mut email, &HashMap::new());
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let formatted = String::from_utf8(email.mime_format.formatted().to_vec()).unwrap();
+        assert!(formatted.contains("Subject: Test subject\r\n"));
+        assert!(formatted.contains("Content-Type: multipart/signed; micalg=pgp-sha256;"));
+        assert_eq!(formatted.matches("MIME-Version: 1.0").count(), 1);
+
+        // The signed entity is embedded unchanged
+        assert!(formatted.contains(&String::from_utf8(entity.clone()).unwrap()));
+
+        assert!(formatted.contains("Content-Type: application/pgp-signature"));
+        let signature = armored(&formatted, "PGP SIGNATURE");
+
+        // Verify the signature with the public key of the sender
+        let verifier = Pgp::new(None, None, None).unwrap();
+        verifier
+            .gpg(
+                &["--import", "./test_data/pgp/albert@einstein.com.asc"],
+                &[],
+            )
+            .unwrap();
+        let signature_path = temp_dir.path().join("signature.asc");
+        fs::write(&signature_path, signature).unwrap();
+        let res = verifier.gpg(
+            &["--verify", &signature_path.display().to_string(), "-"],
+            &entity,
+        );
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+    }
+
+    #[test]
+    fn test_sign_and_encrypt() {
+        let keys_dir = Path::new("./test_data/pgp");
+        let pgp = Pgp::new(
+            Some(&keys_dir.join("albert@einstein.com.key")),
+            None,
+            Some(ReceiverKeys::Dir(keys_dir.to_owned())),
+        )
+        .unwrap();
+        let mut email = email(
+            Sender("albert@einstein.com"),
+            Receiver("Marie Curie <marie@curie.com>"),
+        );
+
+        let res = pgp.secure(&mut email, &HashMap::new());
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let formatted = String::from_utf8(email.mime_format.formatted().to_vec()).unwrap();
+        assert!(formatted.contains("Content-Type: multipart/encrypted;"));
+        assert!(formatted.contains("Version: 1\r\n"));
+        assert!(!formatted.contains("This is a test message."));
+
+        // Decrypt with the secret key of the receiver
+        let encrypted = armored(&formatted, "PGP MESSAGE");
+        let receiver = Pgp::new(Some(&keys_dir.join("marie@curie.com.key")), None, None).unwrap();
+        let sender_key = keys_dir.join("albert@einstein.com.asc");
+        receiver
+            .gpg(&["--import", &sender_key.display().to_string()], &[])
+            .unwrap();
+        let res = receiver.gpg(&["--decrypt"], encrypted.as_bytes());
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let decrypted = String::from_utf8(res.unwrap()).unwrap();
+        assert!(decrypted.starts_with("Content-Type: text/plain"));
+        assert!(decrypted.contains("This is a test message."));
+
+        // The sender can decrypt the email as well
+        assert!(pgp.gpg(&["--decrypt"], encrypted.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_encrypt_missing_public_key() {
+        let pgp = Pgp::new(
+            None,
+            None,
+            Some(ReceiverKeys::Column("public_key".to_owned())),
+        )
+        .unwrap();
+        let home_dir = pgp.home_dir.clone();
+        let mut email = email(Sender("albert@einstein.com"), Receiver("marie@curie.com"));
+
+        let res = pgp.secure(&mut email, &HashMap::new());
+        assert!(res.is_err());
+
+        let key_paths = HashMap::from([(
+            "marie@curie.com".to_owned(),
+            "./test_data/pgp/marie@curie.com.asc".to_owned(),
+        )]);
+        let res = pgp.secure(&mut email, &key_paths);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        // The temporary home directory is removed
+        drop(pgp);
+        assert!(!home_dir.exists());
+    }
+}
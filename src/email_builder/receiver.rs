@@ -1,26 +1,42 @@
+use super::{Deduplication, Duplicate};
 use crate::{
     arg, cmd,
-    sources::{self, ConnVars, DbConnection},
+    sources::{self, ConnVars, DbConnection, PostgresCursor, Row},
 };
 use anyhow::{anyhow, Context};
 use clap::ArgMatches;
 use polars::{
     chunked_array::{ops::TakeRandom, ChunkedArray},
-    datatypes::Utf8Type,
+    datatypes::{BooleanChunked, Utf8Type},
     frame::DataFrame,
 };
-use std::path::Path;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Receiver<'a>(pub &'a str);
 
+impl Receiver<'_> {
+    /// The domain of the email address.
+    pub fn domain(&self) -> Result<&str, anyhow::Error> {
+        match self.0.rsplit_once('@') {
+            Some((_, domain)) if !domain.trim_end_matches('>').is_empty() => {
+                Ok(domain.trim_end_matches('>'))
+            }
+            _ => Err(anyhow!("Missing domain in email address '{}'", self.0)),
+        }
+    }
+}
+
 impl AsRef<str> for Receiver<'_> {
     fn as_ref(&self) -> &str {
         self.0
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct BulkReceiver {
     pub column_name: String,
     pub df_receiver: DataFrame,
@@ -56,7 +72,7 @@ impl BulkReceiver {
                 ))
             },
             (None, Some(path)) => {
-                let df_receiver = sources::read_csv(path)?;
+                let df_receiver = sources::read_csv_as_text(path)?;
 
                 if matches.get_flag(arg::DISPLAY) {
                     println!("Display csv file: {}", df_receiver);
@@ -88,6 +104,75 @@ impl BulkReceiver {
         }
     }
 
+    /// Remove all rows whose receiver doesn't satisfy the predicate. Returns
+    /// the number of removed rows.
+    pub fn retain(&mut self, predicate: impl Fn(&str) -> bool) -> Result<usize, anyhow::Error> {
+        let height = self.height();
+        let mask = self
+            .receiver_column()?
+            .into_iter()
+            .map(|receiver| receiver.map_or(true, &predicate))
+            .collect::<BooleanChunked>();
+        self.df_receiver = self
+            .df_receiver
+            .filter(&mask)
+            .context("Can't filter receivers")?;
+
+        Ok(height - self.height())
+    }
+
+    /// Remove the rows of receivers which occur in several rows, except the
+    /// row kept by the policy. Returns the duplicates.
+    pub fn dedup(
+        &mut self,
+        deduplication: &Deduplication,
+    ) -> Result<Vec<Duplicate>, anyhow::Error> {
+        let duplicates = deduplication.find(self.receiver_column()?)?;
+        let skipped_rows = duplicates
+            .iter()
+            .flat_map(Duplicate::skipped_rows)
+            .collect::<HashSet<_>>();
+        let mask = (0..self.height())
+            .map(|row| !skipped_rows.contains(&row))
+            .collect::<BooleanChunked>();
+        self.df_receiver = self
+            .df_receiver
+            .filter(&mask)
+            .context("Can't filter receivers")?;
+
+        Ok(duplicates)
+    }
+
+    /// Keep only the given rows, which start at zero.
+    pub fn select_rows(&mut self, rows: &[usize]) -> Result<(), anyhow::Error> {
+        let height = self.height();
+
+        if let Some(row) = rows.iter().find(|&&row| row >= height) {
+            return Err(anyhow!(
+                "Row {} doesn't exist, the receivers have {} rows",
+                row + 1,
+                height
+            ));
+        }
+
+        let mask = (0..height)
+            .map(|row| rows.contains(&row))
+            .collect::<BooleanChunked>();
+        self.df_receiver = self
+            .df_receiver
+            .filter(&mask)
+            .context("Can't filter receivers")?;
+
+        Ok(())
+    }
+
+    /// The receivers which satisfy the predicate.
+    pub fn filter(&self, predicate: impl Fn(&str) -> bool) -> Result<Self, anyhow::Error> {
+        let mut bulk_receiver = self.clone();
+        bulk_receiver.retain(predicate)?;
+        Ok(bulk_receiver)
+    }
+
     pub fn height(&self) -> usize {
         self.df_receiver.height()
     }
@@ -119,6 +204,35 @@ impl BulkReceiver {
         }
     }
 
+    /// The value of the given column in the given row, or `None` if the row
+    /// has no value.
+    pub fn optional_row<'a>(
+        &'a self,
+        index: usize,
+        column_name: &str,
+    ) -> Result<Option<&'a str>, anyhow::Error> {
+        Ok(self.column(column_name)?.get(index))
+    }
+
+    /// The values of the given column by receiver. Rows without value are
+    /// skipped.
+    pub fn values_by_receiver(
+        &self,
+        column_name: &str,
+    ) -> Result<HashMap<String, String>, anyhow::Error> {
+        let values = self
+            .receiver_column()?
+            .into_iter()
+            .zip(self.column(column_name)?)
+            .filter_map(|(receiver, value)| match (receiver, value) {
+                (Some(receiver), Some(value)) => Some((receiver.to_owned(), value.to_owned())),
+                _ => None,
+            })
+            .collect();
+
+        Ok(values)
+    }
+
     pub fn column<'a>(
         &'a self,
         column_name: &str,
@@ -134,12 +248,203 @@ impl BulkReceiver {
     }
 }
 
+/// A single row of a receiver file or query.
+#[derive(Debug, PartialEq)]
+pub struct ReceiverRow {
+    /// The position of the row, starting at zero.
+    pub index: usize,
+    pub receiver: String,
+    values: Row,
+}
+
+impl ReceiverRow {
+    pub fn value(&self, column_name: &str) -> Result<&str, anyhow::Error> {
+        match self.values.get(column_name) {
+            Some(Some(value)) => Ok(value),
+            Some(None) => Err(anyhow!(
+                "Missing value for column '{}' in row {}",
+                column_name,
+                self.index + 1
+            )),
+            None => Err(anyhow!("Missing column '{}'", column_name)),
+        }
+    }
+
+    /// The value of the given column, or `None` if the row has no value.
+    pub fn optional_value(&self, column_name: &str) -> Result<Option<&str>, anyhow::Error> {
+        match self.values.get(column_name) {
+            Some(value) => Ok(value.as_deref()),
+            None => Err(anyhow!("Missing column '{}'", column_name)),
+        }
+    }
+}
+
+/// A row of a receiver stream.
+#[derive(Debug, PartialEq)]
+pub enum StreamedRow {
+    Receiver(ReceiverRow),
+    /// A row without value in the receiver column, with its position starting
+    /// at zero.
+    MissingReceiver(usize),
+}
+
+/// Receivers which are read row by row from a csv file or a query, so that
+/// memory usage doesn't depend on the number of receivers.
+pub struct ReceiverStream {
+    pub column_name: String,
+    /// The receiver file or query.
+    pub source: String,
+    rows: Box<dyn Iterator<Item = Result<Row, anyhow::Error>>>,
+    index: usize,
+}
+
+impl ReceiverStream {
+    pub fn new(
+        column_name: String,
+        source: String,
+        rows: Box<dyn Iterator<Item = Result<Row, anyhow::Error>>>,
+    ) -> Self {
+        Self {
+            column_name,
+            source,
+            rows,
+            index: 0,
+        }
+    }
+
+    pub fn from_args(matches: &ArgMatches) -> Result<Self, anyhow::Error> {
+        let column_name = arg::value(arg::RECEIVER_COLUMN, matches)?;
+        let receiver_query = matches.get_one::<String>(arg::RECEIVER_QUERY);
+        let receiver_path = matches.get_one::<String>(arg::RECEIVER_FILE).map(Path::new);
+
+        match (receiver_query, receiver_path) {
+            (Some(query), None) => {
+                let conn_vars = ConnVars::from_env()?;
+                let ssh_tunnel = matches.get_one::<String>(arg::SSH_TUNNEL).map(|arg| arg.as_ref());
+                let connection = DbConnection::new(&conn_vars, ssh_tunnel)?;
+                let cursor = PostgresCursor::new(connection, query)?;
+
+                Ok(Self::new(column_name.to_owned(), query.to_owned(), Box::new(cursor)))
+            },
+            (None, Some(path)) => {
+                let rows = sources::stream_csv(path)?;
+
+                Ok(Self::new(column_name.to_owned(), path.display().to_string(), Box::new(rows)))
+            },
+            (Some(_), Some(_)) => {
+                Err(anyhow!(
+                    "Argument conflict: arguments {} and {} are not allowed at the same time. Check usage via '{} help {}'",
+                    arg::RECEIVER_QUERY,
+                    arg::RECEIVER_FILE,
+                    cmd::BIN,
+                    cmd::SEND_BULK,
+                ))
+            },
+            (None, None) => {
+                Err(anyhow!(
+                    "Missing arguments: please specify argument {} or {}. Check usage via '{} help {}'",
+                    arg::RECEIVER_QUERY,
+                    arg::RECEIVER_FILE,
+                    cmd::BIN,
+                    cmd::SEND_BULK,
+                ))
+            },
+        }
+    }
+}
+
+impl Iterator for ReceiverStream {
+    type Item = Result<StreamedRow, anyhow::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let values = match self.rows.next()? {
+            Ok(values) => values,
+            Err(err) => return Some(Err(err)),
+        };
+        let index = self.index;
+        self.index += 1;
+
+        let receiver = match values.get(&self.column_name) {
+            Some(Some(receiver)) => receiver.to_owned(),
+            Some(None) => return Some(Ok(StreamedRow::MissingReceiver(index))),
+            None => return Some(Err(anyhow!("Missing column '{}'", self.column_name))),
+        };
+
+        Some(Ok(StreamedRow::Receiver(ReceiverRow {
+            index,
+            receiver,
+            values,
+        })))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::app;
+    use crate::{app, email_builder::DuplicatePolicy};
     use polars::{prelude::NamedFrom, series::Series};
 
+    #[test]
+    fn test_receiver_domain() {
+        assert_eq!(Receiver("marie@curie.com").domain().unwrap(), "curie.com");
+        assert_eq!(
+            Receiver("Marie Curie <marie@curie.com>").domain().unwrap(),
+            "curie.com"
+        );
+        assert!(Receiver("marie").domain().is_err());
+        assert!(Receiver("marie@").domain().is_err());
+    }
+
+    #[test]
+    fn test_bulk_receiver_retain() {
+        let email_column = Series::new(
+            "email",
+            &["marie@curie.com", "pierre@curie.com", "emmy@noether.com"],
+        );
+        let df_receiver = DataFrame::new(vec![email_column]).unwrap();
+        let mut receivers = BulkReceiver::new("email".to_owned(), df_receiver);
+
+        let res = receivers.retain(|receiver| !receiver.ends_with("@curie.com"));
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        assert_eq!(res.unwrap(), 2);
+        assert_eq!(receivers.height(), 1);
+        assert_eq!(receivers.receiver_row(0).unwrap(), "emmy@noether.com");
+    }
+
+    #[test]
+    fn test_bulk_receiver_dedup() {
+        let email_column = Series::new(
+            "email",
+            &[
+                "marie@curie.com",
+                "emmy@noether.com",
+                "Marie@Curie.com",
+                "lise@meitner.com",
+            ],
+        );
+        let name_column = Series::new("first_name", &["Marie", "Emmy", "Maria", "Lise"]);
+        let df_receiver = DataFrame::new(vec![email_column, name_column]).unwrap();
+        let receivers = BulkReceiver::new("email".to_owned(), df_receiver);
+
+        let mut deduplicated = receivers.clone();
+        let res = deduplicated.dedup(&Deduplication::default());
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        assert_eq!(res.unwrap().len(), 1);
+        assert_eq!(deduplicated.height(), 3);
+        assert_eq!(deduplicated.row(0, "first_name").unwrap(), "Marie");
+
+        let mut deduplicated = receivers.clone();
+        let res = deduplicated.dedup(&Deduplication::new(DuplicatePolicy::KeepLast, false));
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        assert_eq!(deduplicated.height(), 3);
+        assert_eq!(deduplicated.row(1, "first_name").unwrap(), "Maria");
+
+        let mut deduplicated = receivers.clone();
+        let res = deduplicated.dedup(&Deduplication::new(DuplicatePolicy::Fail, false));
+        assert!(res.is_err());
+        assert_eq!(deduplicated, receivers);
+    }
+
     #[test]
     fn test_bulk_receiver_from_args_receiver_file() {
         let args = vec![
@@ -210,4 +515,70 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_receiver_stream_from_args() {
+        let args = vec![
+            "pigeon",
+            "send-bulk",
+            "albert@einstein.com",
+            "--receiver-file",
+            "./test_data/contacts.csv",
+            "--message-file",
+            "./test_data/message.yaml",
+            "--receiver-column",
+            "contact",
+            "--stream",
+        ];
+        let app = app();
+        let matches = app.get_matches_from(args);
+        let subcommand_matches = matches.subcommand_matches("send-bulk").unwrap();
+
+        let receiver_stream = ReceiverStream::from_args(subcommand_matches).unwrap();
+        assert_eq!(receiver_stream.source, "./test_data/contacts.csv");
+
+        let rows = receiver_stream
+            .map(|row| match row.unwrap() {
+                StreamedRow::Receiver(row) => row,
+                StreamedRow::MissingReceiver(index) => panic!("Missing receiver in row {}", index),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].index, 1);
+        assert_eq!(rows[1].receiver, "alexandre@grothendieck.com");
+        assert_eq!(rows[1].value("first_name").unwrap(), "Alexandre");
+        assert!(rows[1].value("middle_name").is_err());
+    }
+
+    #[test]
+    fn test_receiver_stream_missing_receiver() {
+        let rows = vec![
+            Ok(Row::from([(
+                "email".to_owned(),
+                Some("marie@curie.com".to_owned()),
+            )])),
+            Ok(Row::from([("email".to_owned(), None)])),
+            Ok(Row::from([(
+                "contact".to_owned(),
+                Some("emmy@noether.com".to_owned()),
+            )])),
+        ];
+        let mut receiver_stream = ReceiverStream::new(
+            "email".to_owned(),
+            "test".to_owned(),
+            Box::new(rows.into_iter()),
+        );
+
+        assert!(matches!(
+            receiver_stream.next().unwrap().unwrap(),
+            StreamedRow::Receiver(row) if row.receiver == "marie@curie.com"
+        ));
+        assert_eq!(
+            receiver_stream.next().unwrap().unwrap(),
+            StreamedRow::MissingReceiver(1)
+        );
+        // A missing column can't be skipped
+        assert!(receiver_stream.next().unwrap().is_err());
+        assert!(receiver_stream.next().is_none());
+    }
 }
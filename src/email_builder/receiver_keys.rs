@@ -0,0 +1,135 @@
+use anyhow::{anyhow, Context};
+use clap::ArgMatches;
+use lettre::message::Mailbox;
+use std::{collections::HashMap, path::PathBuf};
+
+/// Where to look up the keys of the receivers, e.g. their S/MIME certificates
+/// or PGP public keys.
+pub enum ReceiverKeys {
+    /// Directory with a key `<email>.<extension>` per receiver.
+    Dir(PathBuf),
+    /// Receiver column with the path of the key.
+    Column(String),
+}
+
+impl ReceiverKeys {
+    /// Returns `None` if neither the directory nor the column is given.
+    pub fn from_args(
+        matches: &ArgMatches,
+        dir_arg: &str,
+        column_arg: &str,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        match (
+            matches.get_one::<String>(dir_arg),
+            matches.try_get_one::<String>(column_arg).ok().flatten(),
+        ) {
+            (Some(dir), None) => Ok(Some(Self::Dir(PathBuf::from(dir)))),
+            (None, Some(column)) => Ok(Some(Self::Column(column.to_owned()))),
+            (None, None) => Ok(None),
+            (Some(_), Some(_)) => Err(anyhow!(
+                "Argument conflict: arguments {} and {} are not allowed at the same time",
+                dir_arg,
+                column_arg
+            )),
+        }
+    }
+
+    /// The receiver column with the paths of the keys, if the keys are looked
+    /// up by column.
+    pub fn column(&self) -> Option<&str> {
+        match self {
+            Self::Dir(_) => None,
+            Self::Column(column) => Some(column),
+        }
+    }
+
+    /// Look up the path of the key of the receiver. The key is called `name`
+    /// in error messages, and has one of the `extensions` in a directory.
+    pub fn lookup(
+        &self,
+        receiver: &str,
+        key_paths: &HashMap<String, String>,
+        name: &str,
+        extensions: &[&str],
+    ) -> Result<PathBuf, anyhow::Error> {
+        match self {
+            Self::Dir(dir) => {
+                let email = receiver
+                    .parse::<Mailbox>()
+                    .context(format!("Can't parse receiver '{}'", receiver))?
+                    .email;
+                extensions
+                    .iter()
+                    .map(|extension| dir.join(format!("{}.{}", email, extension)))
+                    .find(|path| path.is_file())
+                    .ok_or(anyhow!(
+                        "Missing {} of receiver '{}' in '{}'",
+                        name,
+                        receiver,
+                        dir.display()
+                    ))
+            }
+            Self::Column(column) => key_paths
+                .get(receiver)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+                .ok_or(anyhow!(
+                    "Missing {} of receiver '{}' in column '{}'",
+                    name,
+                    receiver,
+                    column
+                )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_dir() {
+        let keys = ReceiverKeys::Dir(PathBuf::from("./test_data/smime"));
+        let key_paths = HashMap::new();
+
+        assert_eq!(
+            keys.lookup(
+                "Marie Curie <marie@curie.com>",
+                &key_paths,
+                "certificate",
+                &["crt", "pem"]
+            )
+            .unwrap(),
+            PathBuf::from("./test_data/smime/marie@curie.com.pem")
+        );
+        let err = keys
+            .lookup("emmy@noether.com", &key_paths, "certificate", &["pem"])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Missing certificate of receiver 'emmy@noether.com' in './test_data/smime'"
+        );
+    }
+
+    #[test]
+    fn test_lookup_column() {
+        let keys = ReceiverKeys::Column("public_key".to_owned());
+        let key_paths = HashMap::from([
+            ("marie@curie.com".to_owned(), "marie.asc".to_owned()),
+            ("emmy@noether.com".to_owned(), String::new()),
+        ]);
+
+        assert_eq!(
+            keys.lookup("marie@curie.com", &key_paths, "public key", &["asc"])
+                .unwrap(),
+            PathBuf::from("marie.asc")
+        );
+        let err = keys
+            .lookup("emmy@noether.com", &key_paths, "public key", &["asc"])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Missing public key of receiver 'emmy@noether.com' in column 'public_key'"
+        );
+    }
+}
@@ -0,0 +1,421 @@
+use super::{receiver_keys::ReceiverKeys, Email, SecureEmail};
+use crate::arg;
+use anyhow::{anyhow, Context};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::ArgMatches;
+use openssl::{
+    pkcs12::Pkcs12,
+    pkcs7::{Pkcs7, Pkcs7Flags},
+    pkey::{PKey, Private},
+    stack::Stack,
+    symm::Cipher,
+    x509::X509,
+};
+use std::{collections::HashMap, env, fs, path::Path};
+use uuid::Uuid;
+
+const BASE64_LINE_LEN: usize = 76;
+
+// File extensions of the certificates in `--smime-cert-dir`
+const CERTIFICATE_EXTENSIONS: [&str; 2] = ["pem", "crt"];
+
+/// Sign and encrypt emails according to S/MIME (RFC 8551).
+pub struct Smime {
+    signer: Option<Signer>,
+    certificates: Option<ReceiverKeys>,
+}
+
+/// The certificate and private key of the sender.
+struct Signer {
+    certificate: X509,
+    private_key: PKey<Private>,
+    /// Intermediate certificates which are included in the signature.
+    chain: Stack<X509>,
+}
+
+impl Smime {
+    /// Returns `None` if neither signing nor encryption is requested.
+    pub fn from_args(matches: &ArgMatches) -> Result<Option<Self>, anyhow::Error> {
+        let signer = match (
+            matches.get_one::<String>(arg::SMIME_CERT),
+            matches.get_one::<String>(arg::SMIME_KEY),
+            matches.get_one::<String>(arg::SMIME_PKCS12),
+        ) {
+            (Some(certificate), Some(private_key), None) => Some(Signer::from_pem(
+                Path::new(certificate),
+                Path::new(private_key),
+            )?),
+            (None, None, Some(pkcs12)) => {
+                // The password is not passed as argument to keep it out of the
+                // shell history
+                let password = env::var("SMIME_PASSWORD").unwrap_or_default();
+                Some(Signer::from_pkcs12(Path::new(pkcs12), &password)?)
+            }
+            (None, None, None) => None,
+            _ => {
+                return Err(anyhow!(
+                    "Missing arguments: please specify {} and {}, or {}",
+                    arg::SMIME_CERT,
+                    arg::SMIME_KEY,
+                    arg::SMIME_PKCS12
+                ))
+            }
+        };
+        let certificates =
+            ReceiverKeys::from_args(matches, arg::SMIME_CERT_DIR, arg::SMIME_CERT_COLUMN)?;
+
+        if signer.is_none() && certificates.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            signer,
+            certificates,
+        }))
+    }
+}
+
+impl SecureEmail for Smime {
+    fn key_column(&self) -> Option<&str> {
+        self.certificates.as_ref().and_then(ReceiverKeys::column)
+    }
+
+    fn secure(
+        &self,
+        email: &mut Email,
+        certificate_paths: &HashMap<String, String>,
+    ) -> Result<(), anyhow::Error> {
+        let (headers, mut entity) = email.mime_format.split_entity()?;
+
+        if let Some(signer) = &self.signer {
+            entity = signer.sign(&entity)?;
+        }
+
+        if let Some(certificates) = &self.certificates {
+            let mut receiver_certificates = Stack::new()?;
+
+            for receiver in email.receivers() {
+                let certificate = read_certificate(&certificates.lookup(
+                    receiver.0,
+                    certificate_paths,
+                    "certificate",
+                    &CERTIFICATE_EXTENSIONS,
+                )?)?;
+                receiver_certificates.push(certificate)?;
+            }
+
+            // Encrypt to the sender as well, so that archived emails stay
+            // readable
+            if let Some(signer) = &self.signer {
+                receiver_certificates.push(signer.certificate.clone())?;
+            }
+
+            entity = encrypt(&entity, &receiver_certificates)?;
+        }
+
+        email.mime_format = email.mime_format.with_entity(&headers, &entity)?;
+
+        Ok(())
+    }
+}
+
+impl Signer {
+    fn from_pem(certificate_path: &Path, private_key_path: &Path) -> Result<Self, anyhow::Error> {
+        let certificates = fs::read(certificate_path).context(format!(
+            "Can't read certificate '{}'",
+            certificate_path.display()
+        ))?;
+        let mut certificates = X509::stack_from_pem(&certificates)
+            .context(format!(
+                "Can't parse certificate '{}'",
+                certificate_path.display()
+            ))?
+            .into_iter();
+        let certificate = certificates.next().ok_or(anyhow!(
+            "Missing certificate in '{}'",
+            certificate_path.display()
+        ))?;
+        let mut chain = Stack::new()?;
+
+        for certificate in certificates {
+            chain.push(certificate)?;
+        }
+
+        let private_key = fs::read(private_key_path).context(format!(
+            "Can't read private key '{}'",
+            private_key_path.display()
+        ))?;
+        let private_key = PKey::private_key_from_pem(&private_key).context(format!(
+            "Can't parse private key '{}'",
+            private_key_path.display()
+        ))?;
+
+        Ok(Self {
+            certificate,
+            private_key,
+            chain,
+        })
+    }
+
+    fn from_pkcs12(path: &Path, password: &str) -> Result<Self, anyhow::Error> {
+        let pkcs12 = fs::read(path).context(format!("Can't read '{}'", path.display()))?;
+        let pkcs12 = Pkcs12::from_der(&pkcs12)
+            .and_then(|pkcs12| pkcs12.parse2(password))
+            .context(format!(
+                "Can't parse '{}'. Is the environment variable 'SMIME_PASSWORD' set?",
+                path.display()
+            ))?;
+
+        match (pkcs12.cert, pkcs12.pkey) {
+            (Some(certificate), Some(private_key)) => Ok(Self {
+                certificate,
+                private_key,
+                chain: match pkcs12.ca {
+                    Some(chain) => chain,
+                    None => Stack::new()?,
+                },
+            }),
+            _ => Err(anyhow!(
+                "Missing certificate or private key in '{}'",
+                path.display()
+            )),
+        }
+    }
+
+    /// Create a `multipart/signed` entity with a detached signature.
+    fn sign(&self, entity: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        // The entity is already in canonical form, i.e. with CRLF line breaks
+        let signature = Pkcs7::sign(
+            &self.certificate,
+            &self.private_key,
+            &self.chain,
+            entity,
+            Pkcs7Flags::DETACHED | Pkcs7Flags::BINARY,
+        )
+        .and_then(|signature| signature.to_der())
+        .context("Can't sign email")?;
+        let micalg = micalg(&signature)?;
+        let boundary = Uuid::new_v4().simple().to_string();
+
+        let mut signed = format!(
+            "Content-Type: multipart/signed; protocol=\"application/pkcs7-signature\";\r\n micalg=\"{micalg}\"; boundary=\"{boundary}\"\r\n\r\n--{boundary}\r\n"
+        )
+        .into_bytes();
+        signed.extend_from_slice(entity);
+        signed.extend_from_slice(
+            format!(
+                "\r\n--{boundary}\r\n\
+                Content-Type: application/pkcs7-signature; name=\"smime.p7s\"\r\n\
+                Content-Transfer-Encoding: base64\r\n\
+                Content-Disposition: attachment; filename=\"smime.p7s\"\r\n\r\n\
+                {}\r\n--{boundary}--\r\n",
+                encode_base64(&signature)
+            )
+            .as_bytes(),
+        );
+
+        Ok(signed)
+    }
+}
+
+/// The `micalg` parameter of the `multipart/signed` entity. The digest
+/// algorithm of the signature depends on the private key, e.g. its type and
+/// size, so the parameter is taken from the S/MIME output of OpenSSL.
+///
+/// The signature is parsed from DER, as writing S/MIME finalizes the
+/// signature again.
+fn micalg(signature: &[u8]) -> Result<String, anyhow::Error> {
+    let smime = Pkcs7::from_der(signature)
+        .and_then(|signature| signature.to_smime(&[], Pkcs7Flags::DETACHED | Pkcs7Flags::BINARY))
+        .context("Can't determine digest algorithm of signature")?;
+
+    String::from_utf8_lossy(&smime)
+        .split_once("micalg=\"")
+        .and_then(|(_, micalg)| micalg.split_once('"'))
+        .map(|(micalg, _)| micalg.to_owned())
+        .ok_or(anyhow!("Missing digest algorithm of signature"))
+}
+
+fn read_certificate(path: &Path) -> Result<X509, anyhow::Error> {
+    let certificate =
+        fs::read(path).context(format!("Can't read certificate '{}'", path.display()))?;
+
+    X509::from_pem(&certificate)
+        .or_else(|_| X509::from_der(&certificate))
+        .context(format!("Can't parse certificate '{}'", path.display()))
+}
+
+/// Create an `application/pkcs7-mime` entity which can only be read by the
+/// owners of the certificates.
+fn encrypt(entity: &[u8], certificates: &Stack<X509>) -> Result<Vec<u8>, anyhow::Error> {
+    let encrypted = Pkcs7::encrypt(
+        certificates,
+        entity,
+        Cipher::aes_256_cbc(),
+        Pkcs7Flags::BINARY,
+    )
+    .and_then(|encrypted| encrypted.to_der())
+    .context("Can't encrypt email")?;
+
+    Ok(format!(
+        "Content-Type: application/pkcs7-mime; smime-type=enveloped-data;\r\n name=\"smime.p7m\"\r\n\
+        Content-Transfer-Encoding: base64\r\n\
+        Content-Disposition: attachment; filename=\"smime.p7m\"\r\n\r\n\
+        {}\r\n",
+        encode_base64(&encrypted)
+    )
+    .into_bytes())
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    STANDARD
+        .encode(bytes)
+        .as_bytes()
+        .chunks(BASE64_LINE_LEN)
+        .map(|line| String::from_utf8_lossy(line))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email_builder::{Message, MimeFormat, Receiver, Sender};
+    use openssl::x509::store::X509StoreBuilder;
+    use std::{path::PathBuf, time::SystemTime};
+    use tempfile::tempdir;
+
+    /// Load the self-signed test certificate and the private key of the
+    /// email address.
+    fn certificate(email: &str) -> (X509, PKey<Private>) {
+        let dir = Path::new("./test_data/smime");
+        let certificate = fs::read(dir.join(format!("{}.pem", email))).unwrap();
+        let private_key = fs::read(dir.join(format!("{}.key", email))).unwrap();
+
+        (
+            X509::from_pem(&certificate).unwrap(),
+            PKey::private_key_from_pem(&private_key).unwrap(),
+        )
+    }
+
+    fn email<'a>(sender: Sender<'a>, receiver: Receiver<'a>) -> Email<'a> {
+        let message = Message::new("Test subject", Some("This is a test message."), None);
+        let mime_format =
+            MimeFormat::new(sender, receiver, &message, &[], SystemTime::now()).unwrap();
+        Email::new(sender, receiver, &message, &mime_format).unwrap()
+    }
+
+    fn signer(email: &str) -> Signer {
+        let (certificate, private_key) = certificate(email);
+        Signer {
+            certificate,
+            private_key,
+            chain: Stack::new().unwrap(),
+        }
+    }
+
+    /// Extract and decode the base64 encoded body of a single part.
+    fn decode_base64(formatted: &str, content_type: &str) -> Vec<u8> {
+        let part = &formatted[formatted.find(content_type).unwrap()..];
+        let body = &part[part.find("\r\n\r\n").unwrap() + 4..];
+        let body = body.split("\r\n--").next().unwrap();
+        STANDARD.decode(body.replace("\r\n", "")).unwrap()
+    }
+
+    #[test]
+    fn test_sign() {
+        let smime = Smime {
+            signer: Some(signer("albert@einstein.com")),
+            certificates: None,
+        };
+        let mut email = email(Sender("albert@einstein.com"), Receiver("marie@curie.com"));
+        let (_, entity) = email.mime_format.split_entity().unwrap();
+
+        let res = smime.secure(&mut email, &HashMap::new());
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let formatted = String::from_utf8(email.mime_format.formatted().to_vec()).unwrap();
+        assert!(formatted.contains("Subject: Test subject\r\n"));
+        assert!(formatted.contains("Content-Type: multipart/signed"));
+        assert!(formatted.contains(" micalg=\"sha-256\";"));
+        assert_eq!(formatted.matches("MIME-Version: 1.0").count(), 1);
+        assert!(email.mime_format.message_id().is_some());
+
+        // The signed entity is embedded unchanged
+        let entity = String::from_utf8(entity).unwrap();
+        assert!(formatted.contains(&entity));
+
+        let signer = smime.signer.unwrap();
+        let mut certificates = Stack::new().unwrap();
+        certificates.push(signer.certificate.clone()).unwrap();
+        let signature = decode_base64(&formatted, "Content-Type: application/pkcs7-signature");
+        let signature = Pkcs7::from_der(&signature).unwrap();
+        let res = signature.verify(
+            &certificates,
+            &X509StoreBuilder::new().unwrap().build(),
+            Some(entity.as_bytes()),
+            None,
+            Pkcs7Flags::NOVERIFY | Pkcs7Flags::BINARY,
+        );
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+    }
+
+    #[test]
+    fn test_sign_and_encrypt() {
+        let (certificate, private_key) = certificate("marie@curie.com");
+        let smime = Smime {
+            signer: Some(signer("albert@einstein.com")),
+            certificates: Some(ReceiverKeys::Dir(PathBuf::from("./test_data/smime"))),
+        };
+        let mut email = email(
+            Sender("albert@einstein.com"),
+            Receiver("Marie Curie <marie@curie.com>"),
+        );
+
+        let res = smime.secure(&mut email, &HashMap::new());
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let formatted = String::from_utf8(email.mime_format.formatted().to_vec()).unwrap();
+        assert!(formatted.contains("To: \"Marie Curie\" <marie@curie.com>\r\n"));
+        assert!(!formatted.contains("This is a test message."));
+
+        let encrypted = decode_base64(&formatted, "Content-Type: application/pkcs7-mime");
+        let encrypted = Pkcs7::from_der(&encrypted).unwrap();
+        let decrypted = encrypted
+            .decrypt(&private_key, &certificate, Pkcs7Flags::BINARY)
+            .unwrap();
+        let decrypted = String::from_utf8(decrypted).unwrap();
+        assert!(decrypted.starts_with("Content-Type: multipart/signed"));
+        assert!(decrypted.contains("This is a test message."));
+
+        // The sender can decrypt the email as well
+        let signer = smime.signer.unwrap();
+        assert!(encrypted
+            .decrypt(&signer.private_key, &signer.certificate, Pkcs7Flags::BINARY)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_encrypt_missing_certificate() {
+        let temp_dir = tempdir().unwrap();
+        let smime = Smime {
+            signer: None,
+            certificates: Some(ReceiverKeys::Column("certificate".to_owned())),
+        };
+        let mut email = email(Sender("albert@einstein.com"), Receiver("marie@curie.com"));
+
+        let res = smime.secure(&mut email, &HashMap::new());
+        assert!(res.is_err());
+
+        let path = temp_dir.path().join("marie.der");
+        let (certificate, _) = certificate("marie@curie.com");
+        fs::write(&path, certificate.to_der().unwrap()).unwrap();
+        let certificate_paths = HashMap::from([(
+            "marie@curie.com".to_owned(),
+            path.to_string_lossy().into_owned(),
+        )]);
+
+        let res = smime.secure(&mut email, &certificate_paths);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+    }
+}
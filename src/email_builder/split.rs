@@ -0,0 +1,291 @@
+use super::{BulkReceiver, Deduplication, Message};
+use crate::arg;
+use anyhow::{anyhow, Context};
+use clap::ArgMatches;
+use sha2::{Digest, Sha256};
+
+/// Basis points of all receivers, i.e. 100 %.
+const TOTAL_SHARE: u32 = 10_000;
+
+/// The group a receiver is assigned to by the split.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Assignment {
+    /// The index of the variant of the message.
+    Variant(usize),
+    /// The receivers which don't get any variant, e.g. to send them the
+    /// winner later.
+    Holdout,
+}
+
+/// Split of the receivers into the variants of the message for A/B testing,
+/// e.g. `50/50`, or `10/10` with a holdout of 80 %.
+///
+/// Each receiver is assigned by the hash of its address and the seed, so that
+/// the assignment is reproducible for the same split and seed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Split {
+    /// The shares of the variants in basis points.
+    shares: Vec<u32>,
+    seed: String,
+}
+
+impl Split {
+    pub fn new(split: &str, seed: &str) -> Result<Self, anyhow::Error> {
+        let shares = split
+            .split('/')
+            .map(|share| {
+                let percent = share
+                    .trim()
+                    .trim_end_matches('%')
+                    .parse::<f64>()
+                    .context(format!("Invalid share '{}' in split '{}'", share, split))?;
+
+                if !(percent > 0. && percent <= 100.) {
+                    return Err(anyhow!(
+                        "Invalid share '{}' in split '{}': must be greater than 0 and at most 100",
+                        share,
+                        split
+                    ));
+                }
+
+                Ok((percent * 100.).round() as u32)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if shares.iter().sum::<u32>() > TOTAL_SHARE {
+            return Err(anyhow!("Shares of split '{}' exceed 100 %", split));
+        }
+
+        Ok(Self {
+            shares,
+            seed: seed.to_owned(),
+        })
+    }
+
+    pub fn from_args(matches: &ArgMatches) -> Result<Option<Self>, anyhow::Error> {
+        match matches.get_one::<String>(arg::SPLIT) {
+            Some(split) => {
+                let seed = matches
+                    .get_one::<String>(arg::SPLIT_SEED)
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                Ok(Some(Self::new(split, seed)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The number of variants.
+    pub fn variant_count(&self) -> usize {
+        self.shares.len()
+    }
+
+    /// Assign the receiver to a variant or to the holdout. Receivers are
+    /// normalized like duplicates, i.e. the display name is ignored and email
+    /// addresses are compared case-insensitively.
+    pub fn assign(&self, receiver: &str) -> Assignment {
+        let mut hasher = Sha256::new();
+        hasher.update(self.seed.as_bytes());
+        hasher.update([0]);
+        hasher.update(Deduplication::default().normalize(receiver).as_bytes());
+        let hash = hasher.finalize();
+
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&hash[..8]);
+        let bucket = (u64::from_be_bytes(bytes) % u64::from(TOTAL_SHARE)) as u32;
+
+        let mut upper_bound = 0;
+
+        for (i, share) in self.shares.iter().enumerate() {
+            upper_bound += share;
+
+            if bucket < upper_bound {
+                return Assignment::Variant(i);
+            }
+        }
+
+        Assignment::Holdout
+    }
+}
+
+/// A/B test which sends the variants of the message to the receivers
+/// assigned by the split, or the winning variant to the holdout.
+#[derive(Debug)]
+pub struct SplitTest {
+    split: Split,
+    /// The messages of the variants by name.
+    variants: Vec<(String, Message)>,
+    /// The index of the variant which is sent to the holdout.
+    winner: Option<usize>,
+}
+
+/// The receivers which get the same variant of the message.
+#[derive(Debug)]
+pub struct SplitGroup<'a> {
+    pub variant: &'a str,
+    pub message: &'a Message,
+    pub receivers: BulkReceiver,
+}
+
+impl SplitTest {
+    pub fn new(
+        split: Split,
+        message: &Message,
+        winner: Option<&str>,
+    ) -> Result<Self, anyhow::Error> {
+        let variants = message.variants();
+
+        if variants.len() != split.variant_count() {
+            return Err(anyhow!(
+                "Split into {} variants, but the message has {} variants",
+                split.variant_count(),
+                variants.len()
+            ));
+        }
+
+        let winner = match winner {
+            Some(winner) => Some(
+                variants
+                    .iter()
+                    .position(|(name, _)| name == winner)
+                    .ok_or(anyhow!("Missing variant '{}' in message", winner))?,
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            split,
+            variants,
+            winner,
+        })
+    }
+
+    pub fn from_args(
+        matches: &ArgMatches,
+        message: &Message,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        match Split::from_args(matches)? {
+            Some(split) => Ok(Some(Self::new(
+                split,
+                message,
+                matches.get_one::<String>(arg::VARIANT).map(String::as_str),
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The name and message of the variant which is sent to the receiver, or
+    /// `None` if the receiver doesn't get an email in this run.
+    pub fn select(&self, receiver: &str) -> Option<(&str, &Message)> {
+        let index = match (self.split.assign(receiver), self.winner) {
+            (Assignment::Variant(index), None) => index,
+            (Assignment::Holdout, Some(winner)) => winner,
+            _ => return None,
+        };
+        let (name, message) = &self.variants[index];
+
+        Some((name, message))
+    }
+
+    /// Group the receivers by the variant they get. Receivers who don't get
+    /// an email in this run are skipped.
+    pub fn groups(&self, bulk_receiver: &BulkReceiver) -> Result<Vec<SplitGroup>, anyhow::Error> {
+        let mut groups = vec![];
+
+        for (name, message) in self.variants.iter() {
+            let receivers = bulk_receiver.filter(|receiver| {
+                self.select(receiver)
+                    .is_some_and(|(variant, _)| variant == name)
+            })?;
+
+            if receivers.height() > 0 {
+                groups.push(SplitGroup {
+                    variant: name,
+                    message,
+                    receivers,
+                });
+            }
+        }
+
+        Ok(groups)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_new() {
+        let split = Split::new("50/50", "").unwrap();
+        assert_eq!(split.variant_count(), 2);
+        assert_eq!(split.shares, vec![5000, 5000]);
+
+        let split = Split::new("10/10/5.5%", "").unwrap();
+        assert_eq!(split.variant_count(), 3);
+        assert_eq!(split.shares, vec![1000, 1000, 550]);
+
+        assert!(Split::new("60/50", "").is_err());
+        assert!(Split::new("50/0", "").is_err());
+        assert!(Split::new("50/", "").is_err());
+        assert!(Split::new("a/b", "").is_err());
+    }
+
+    #[test]
+    fn test_split_assign() {
+        let split = Split::new("10/10", "").unwrap();
+        let receivers = (0..10_000)
+            .map(|i| format!("receiver{}@example.com", i))
+            .collect::<Vec<_>>();
+        let assignments = receivers
+            .iter()
+            .map(|receiver| split.assign(receiver))
+            .collect::<Vec<_>>();
+
+        // The assignment is reproducible, case-insensitive, and ignores the
+        // display name
+        assert_eq!(
+            split.assign("Marie@Curie.com"),
+            split.assign("marie@curie.com")
+        );
+        assert_eq!(
+            split.assign("Marie Curie <marie@curie.com>"),
+            split.assign("marie@curie.com")
+        );
+        assert_eq!(
+            receivers
+                .iter()
+                .map(|receiver| split.assign(receiver))
+                .collect::<Vec<_>>(),
+            assignments
+        );
+
+        let count = |assignment| {
+            assignments
+                .iter()
+                .filter(|&&assigned| assigned == assignment)
+                .count()
+        };
+        assert!((900..1100).contains(&count(Assignment::Variant(0))));
+        assert!((900..1100).contains(&count(Assignment::Variant(1))));
+        assert!((7800..8200).contains(&count(Assignment::Holdout)));
+
+        // Another seed shuffles the assignment
+        let reseeded = Split::new("10/10", "newsletter-2").unwrap();
+        assert_ne!(
+            receivers
+                .iter()
+                .map(|receiver| reseeded.assign(receiver))
+                .collect::<Vec<_>>(),
+            assignments
+        );
+    }
+
+    #[test]
+    fn test_split_assign_all() {
+        let split = Split::new("50/50", "").unwrap();
+        assert!((0..1000)
+            .map(|i| split.assign(&format!("receiver{}@example.com", i)))
+            .all(|assignment| assignment != Assignment::Holdout));
+    }
+}
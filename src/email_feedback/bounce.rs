@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Classification of a bounce or complaint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BounceType {
+    /// The receiver doesn't exist or rejects emails permanently.
+    Hard,
+    /// The delivery failed temporarily, e.g. because of a full mailbox.
+    Soft,
+    /// The receiver marked the email as spam.
+    Complaint,
+}
+
+impl BounceType {
+    /// Hard bounces and complaints must not be mailed again.
+    pub fn is_suppressed(&self) -> bool {
+        matches!(self, BounceType::Hard | BounceType::Complaint)
+    }
+}
+
+impl fmt::Display for BounceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BounceType::Hard => write!(f, "hard"),
+            BounceType::Soft => write!(f, "soft"),
+            BounceType::Complaint => write!(f, "complaint"),
+        }
+    }
+}
+
+/// A bounced or complaining receiver.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bounce {
+    /// The original receiver of the email.
+    pub email: String,
+    pub bounce_type: BounceType,
+    /// The enhanced status code of a bounce, or the feedback type of a
+    /// complaint.
+    pub status: String,
+    /// The diagnostic message of the receiving mail server.
+    pub diagnostic: String,
+    pub date: String,
+    /// The Message-ID of the bounced email, if known.
+    pub message_id: String,
+    /// The file the bounce was imported from.
+    pub source: String,
+}
@@ -0,0 +1,297 @@
+use super::{Bounce, BounceType};
+use anyhow::Context;
+use mailparse::{MailHeaderMap, ParsedMail};
+use std::{collections::HashMap, path::Path};
+
+/// Parse a delivery status notification (RFC 3464) or an abuse feedback
+/// report (RFC 5965). Other messages, like auto-replies, don't contain any
+/// bounces.
+pub fn parse(message: &[u8], source: &Path) -> Result<Vec<Bounce>, anyhow::Error> {
+    let mail = mailparse::parse_mail(message)
+        .context(format!("Can't parse email in '{}'", source.display()))?;
+    let date = mail.headers.get_first_value("Date").unwrap_or_default();
+    let message_id = original_message_id(&mail).unwrap_or_default();
+
+    if let Some(part) = find_part(&mail, "message/delivery-status") {
+        let body = part.get_body().context("Can't read delivery status")?;
+        return Ok(delivery_status(&body, &date, &message_id, source));
+    }
+
+    if let Some(part) = find_part(&mail, "message/feedback-report") {
+        let body = part.get_body().context("Can't read feedback report")?;
+        let original_receiver = original_receiver(&mail);
+        return Ok(feedback_report(
+            &body,
+            original_receiver.as_deref(),
+            &date,
+            &message_id,
+            source,
+        ));
+    }
+
+    Ok(vec![])
+}
+
+fn delivery_status(body: &str, date: &str, message_id: &str, source: &Path) -> Vec<Bounce> {
+    let groups = fields(body);
+    let mut bounces = vec![];
+
+    let Some((per_message, per_receiver)) = groups.split_first() else {
+        return bounces;
+    };
+    let date = per_message
+        .get("arrival-date")
+        .map_or(date, |arrival_date| arrival_date.as_str());
+
+    for fields in per_receiver {
+        let receiver = fields
+            .get("final-recipient")
+            .or_else(|| fields.get("original-recipient"))
+            .map(|receiver| address(receiver));
+        let status = fields.get("status").cloned().unwrap_or_default();
+        let action = fields
+            .get("action")
+            .map(|action| action.to_lowercase())
+            .unwrap_or_default();
+
+        let bounce_type = match (status.chars().next(), action.as_str()) {
+            (Some('5'), _) => BounceType::Hard,
+            (Some('4'), _) => BounceType::Soft,
+            (_, "failed") => BounceType::Hard,
+            (_, "delayed") => BounceType::Soft,
+            // Successful delivery, relay, or expansion
+            _ => continue,
+        };
+
+        if let Some(receiver) = receiver {
+            bounces.push(Bounce {
+                email: receiver,
+                bounce_type,
+                status,
+                diagnostic: fields
+                    .get("diagnostic-code")
+                    .map(|diagnostic| remove_type(diagnostic).to_owned())
+                    .unwrap_or_default(),
+                date: date.to_owned(),
+                message_id: message_id.to_owned(),
+                source: source.display().to_string(),
+            });
+        }
+    }
+
+    bounces
+}
+
+fn feedback_report(
+    body: &str,
+    original_receiver: Option<&str>,
+    date: &str,
+    message_id: &str,
+    source: &Path,
+) -> Vec<Bounce> {
+    let groups = fields(body);
+    let Some(fields) = groups.first() else {
+        return vec![];
+    };
+    let receiver = fields
+        .get("original-rcpt-to")
+        .map(|receiver| address(receiver))
+        .or_else(|| original_receiver.map(address));
+
+    match receiver {
+        Some(receiver) => vec![Bounce {
+            email: receiver,
+            bounce_type: BounceType::Complaint,
+            status: fields.get("feedback-type").cloned().unwrap_or_default(),
+            diagnostic: String::new(),
+            date: fields
+                .get("arrival-date")
+                .map_or(date, |arrival_date| arrival_date.as_str())
+                .to_owned(),
+            message_id: message_id.to_owned(),
+            source: source.display().to_string(),
+        }],
+        None => vec![],
+    }
+}
+
+fn find_part<'a>(mail: &'a ParsedMail<'a>, mimetype: &str) -> Option<&'a ParsedMail<'a>> {
+    if mail.ctype.mimetype.eq_ignore_ascii_case(mimetype) {
+        return Some(mail);
+    }
+
+    mail.subparts
+        .iter()
+        .find_map(|part| find_part(part, mimetype))
+}
+
+/// The headers of the original email which are attached to the report.
+fn original_headers(mail: &ParsedMail) -> Option<Vec<(String, String)>> {
+    let part =
+        find_part(mail, "message/rfc822").or_else(|| find_part(mail, "text/rfc822-headers"))?;
+    let raw = part.get_body_raw().ok()?;
+    let (headers, _) = mailparse::parse_headers(&raw).ok()?;
+
+    Some(
+        headers
+            .iter()
+            .map(|header| (header.get_key(), header.get_value()))
+            .collect(),
+    )
+}
+
+fn original_header(mail: &ParsedMail, name: &str) -> Option<String> {
+    original_headers(mail)?
+        .into_iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+}
+
+fn original_message_id(mail: &ParsedMail) -> Option<String> {
+    original_header(mail, "Message-ID")
+}
+
+fn original_receiver(mail: &ParsedMail) -> Option<String> {
+    original_header(mail, "To")
+}
+
+/// Split the body of a report into groups of fields. Groups are separated by
+/// blank lines, and field names are lowercased.
+fn fields(body: &str) -> Vec<HashMap<String, String>> {
+    let mut groups = vec![];
+    let mut group = HashMap::new();
+    let mut last_key: Option<String> = None;
+
+    for line in body.lines() {
+        let line = line.trim_end_matches('\r');
+
+        if line.trim().is_empty() {
+            if !group.is_empty() {
+                groups.push(group);
+                group = HashMap::new();
+            }
+            last_key = None;
+        } else if line.starts_with([' ', '\t']) {
+            // Folded line
+            if let Some(value) = last_key.as_ref().and_then(|key| group.get_mut(key)) {
+                let value: &mut String = value;
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            let key = key.trim().to_lowercase();
+            group.insert(key.clone(), value.trim().to_owned());
+            last_key = Some(key);
+        }
+    }
+
+    if !group.is_empty() {
+        groups.push(group);
+    }
+
+    groups
+}
+
+/// Remove the address or diagnostic type, like `rfc822;` or `smtp;`.
+pub fn remove_type(value: &str) -> &str {
+    value
+        .split_once(';')
+        .map_or(value, |(_, value)| value)
+        .trim()
+}
+
+/// Extract the email address of a field like `rfc822; <marie@curie.com>` or
+/// `Marie Curie <marie@curie.com>`.
+fn address(value: &str) -> String {
+    let value = remove_type(value);
+
+    match (value.find('<'), value.find('>')) {
+        (Some(start), Some(end)) if start < end => value[start + 1..end].trim().to_owned(),
+        _ => value.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_address() {
+        assert_eq!(address("rfc822; marie@curie.com"), "marie@curie.com");
+        assert_eq!(address("rfc822;<marie@curie.com>"), "marie@curie.com");
+        assert_eq!(address("Marie Curie <marie@curie.com>"), "marie@curie.com");
+        assert_eq!(address("marie@curie.com"), "marie@curie.com");
+    }
+
+    #[test]
+    fn test_fields() {
+        let body = "Reporting-MTA: dns; mx.curie.com\r\n\r\nFinal-Recipient: rfc822; marie@curie.com\r\nDiagnostic-Code: smtp; 550 5.1.1\r\n  user unknown\r\n";
+        let groups = fields(body);
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0]["reporting-mta"], "dns; mx.curie.com");
+        assert_eq!(groups[1]["final-recipient"], "rfc822; marie@curie.com");
+        assert_eq!(groups[1]["diagnostic-code"], "smtp; 550 5.1.1 user unknown");
+    }
+
+    #[test]
+    fn test_parse_dsn() {
+        let path = Path::new("./test_data/bounces/dsn.eml");
+        let message = fs::read(path).unwrap();
+
+        let res = parse(&message, path);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let bounces = res.unwrap();
+        assert_eq!(
+            bounces,
+            vec![
+                Bounce {
+                    email: "marie@curie.com".to_owned(),
+                    bounce_type: BounceType::Hard,
+                    status: "5.1.1".to_owned(),
+                    diagnostic:
+                        "550 5.1.1 The email account that you tried to reach does not exist"
+                            .to_owned(),
+                    date: "Mon, 1 Jan 2024 14:00:05 +0000".to_owned(),
+                    message_id: "<1704117600.1234@einstein.com>".to_owned(),
+                    source: "./test_data/bounces/dsn.eml".to_owned(),
+                },
+                Bounce {
+                    email: "alexandre@grothendieck.com".to_owned(),
+                    bounce_type: BounceType::Soft,
+                    status: "4.2.2".to_owned(),
+                    diagnostic: "452 4.2.2 Mailbox full".to_owned(),
+                    date: "Mon, 1 Jan 2024 14:00:05 +0000".to_owned(),
+                    message_id: "<1704117600.1234@einstein.com>".to_owned(),
+                    source: "./test_data/bounces/dsn.eml".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_feedback_report() {
+        let path = Path::new("./test_data/bounces/arf.eml");
+        let message = fs::read(path).unwrap();
+
+        let res = parse(&message, path);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let bounces = res.unwrap();
+        assert_eq!(bounces.len(), 1);
+        assert_eq!(bounces[0].email, "emmy@noether.com");
+        assert_eq!(bounces[0].bounce_type, BounceType::Complaint);
+        assert_eq!(bounces[0].status, "abuse");
+    }
+
+    #[test]
+    fn test_parse_no_report() {
+        let message = b"From: marie@curie.com\r\nTo: albert@einstein.com\r\nSubject: Out of office\r\n\r\nI'm on vacation.\r\n";
+
+        let res = parse(message, Path::new("auto_reply.eml"));
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        assert!(res.unwrap().is_empty());
+    }
+}
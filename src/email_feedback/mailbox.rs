@@ -0,0 +1,127 @@
+use anyhow::Context;
+use std::{fs, path::Path};
+
+/// Check if a file is in mbox format, i.e. starts with a `From ` line.
+pub fn is_mbox(content: &[u8]) -> bool {
+    content.starts_with(b"From ")
+}
+
+/// Check if a directory is a Maildir.
+pub fn is_maildir(dir: &Path) -> bool {
+    dir.join("cur").is_dir() || dir.join("new").is_dir()
+}
+
+/// Split a mbox file into messages. Lines starting with `>From ` are
+/// unescaped as in the mboxrd format.
+pub fn split_mbox(content: &[u8]) -> Vec<Vec<u8>> {
+    let mut messages = vec![];
+    let mut message: Option<Vec<u8>> = None;
+
+    for line in content.split_inclusive(|byte| *byte == b'\n') {
+        if line.starts_with(b"From ") {
+            if let Some(message) = message.take() {
+                messages.push(message);
+            }
+
+            // The `From_` line is not part of the message
+            message = Some(vec![]);
+            continue;
+        }
+
+        if let Some(message) = message.as_mut() {
+            let quotes = line.iter().take_while(|byte| **byte == b'>').count();
+
+            if quotes > 0 && line[quotes..].starts_with(b"From ") {
+                message.extend_from_slice(&line[1..]);
+            } else {
+                message.extend_from_slice(line);
+            }
+        }
+    }
+
+    if let Some(message) = message {
+        messages.push(message);
+    }
+
+    messages
+}
+
+/// Read all messages in the `new` and `cur` directories of a Maildir.
+pub fn read_maildir(dir: &Path) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+    let mut messages = vec![];
+
+    for sub_dir in ["new", "cur"] {
+        let sub_dir = dir.join(sub_dir);
+
+        if !sub_dir.is_dir() {
+            continue;
+        }
+
+        let mut paths = fs::read_dir(&sub_dir)
+            .context(format!("Can't read directory '{}'", sub_dir.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .context(format!("Can't read directory '{}'", sub_dir.display()))?;
+        paths.sort();
+
+        for path in paths.iter().filter(|path| path.is_file()) {
+            let message =
+                fs::read(path).context(format!("Can't read file '{}'", path.display()))?;
+            messages.push(message);
+        }
+    }
+
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_split_mbox() {
+        let mbox = b"From MAILER-DAEMON Mon Jan  1 14:00:05 2024\nSubject: First\n\n>From the start\n>>From here\n\nFrom MAILER-DAEMON Mon Jan  1 14:00:06 2024\nSubject: Second\n\nBody\n";
+
+        assert!(is_mbox(mbox));
+
+        let messages = split_mbox(mbox);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0],
+            b"Subject: First\n\nFrom the start\n>From here\n\n".to_vec()
+        );
+        assert_eq!(messages[1], b"Subject: Second\n\nBody\n".to_vec());
+    }
+
+    #[test]
+    fn test_read_maildir() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path();
+        assert!(!is_maildir(temp_path));
+
+        fs::create_dir(temp_path.join("new")).unwrap();
+        fs::create_dir(temp_path.join("cur")).unwrap();
+        fs::create_dir(temp_path.join("tmp")).unwrap();
+        fs::write(temp_path.join("new").join("1.host"), "Subject: New\r\n\r\n").unwrap();
+        fs::write(
+            temp_path.join("cur").join("2.host:2,S"),
+            "Subject: Seen\r\n\r\n",
+        )
+        .unwrap();
+        fs::write(temp_path.join("tmp").join("3.host"), "Subject: Tmp\r\n\r\n").unwrap();
+        assert!(is_maildir(temp_path));
+
+        let res = read_maildir(temp_path);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let messages = res.unwrap();
+        assert_eq!(
+            messages,
+            vec![
+                b"Subject: New\r\n\r\n".to_vec(),
+                b"Subject: Seen\r\n\r\n".to_vec()
+            ]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+mod bounce;
+mod dsn;
+pub mod mailbox;
+mod ses;
+mod suppression;
+
+use anyhow::Context;
+pub use bounce::{Bounce, BounceType};
+use std::{fs, path::Path};
+pub use suppression::{write_bounces, SuppressionList};
+
+/// Import bounces and complaints from a path.
+///
+/// A file is read as SES notification if it has a `.json` extension, as mbox
+/// if it starts with a `From ` line, and as single message otherwise. A
+/// directory is read as Maildir if it contains a `cur` or `new` directory,
+/// otherwise all files in the directory are imported.
+pub fn import(path: &Path) -> Result<Vec<Bounce>, anyhow::Error> {
+    if path.is_dir() {
+        if mailbox::is_maildir(path) {
+            let mut bounces = vec![];
+
+            for message in mailbox::read_maildir(path)? {
+                bounces.extend(dsn::parse(&message, path)?);
+            }
+
+            return Ok(bounces);
+        }
+
+        let mut entries = fs::read_dir(path)
+            .context(format!("Can't read directory '{}'", path.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .context(format!("Can't read directory '{}'", path.display()))?;
+        entries.sort();
+
+        let mut bounces = vec![];
+
+        for entry in entries.iter().filter(|entry| entry.is_file()) {
+            bounces.extend(import_file(entry)?);
+        }
+
+        Ok(bounces)
+    } else {
+        import_file(path)
+    }
+}
+
+fn import_file(path: &Path) -> Result<Vec<Bounce>, anyhow::Error> {
+    let content = fs::read(path).context(format!("Can't read file '{}'", path.display()))?;
+
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        return ses::parse(&content, path);
+    }
+
+    if mailbox::is_mbox(&content) {
+        let mut bounces = vec![];
+
+        for message in mailbox::split_mbox(&content) {
+            bounces.extend(dsn::parse(&message, path)?);
+        }
+
+        return Ok(bounces);
+    }
+
+    dsn::parse(&content, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_dir() {
+        let res = import(Path::new("./test_data/bounces"));
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let bounces = res.unwrap();
+        let emails = bounces
+            .iter()
+            .map(|bounce| (bounce.email.as_str(), bounce.bounce_type))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            emails,
+            vec![
+                ("emmy@noether.com", BounceType::Complaint),
+                ("marie@curie.com", BounceType::Hard),
+                ("alexandre@grothendieck.com", BounceType::Soft),
+                ("pierre@curie.com", BounceType::Hard),
+                ("henri@poincare.com", BounceType::Complaint),
+            ]
+        );
+    }
+}
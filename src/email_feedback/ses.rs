@@ -0,0 +1,176 @@
+use super::{dsn, Bounce, BounceType};
+use anyhow::{anyhow, Context};
+use serde_json::Value;
+use std::path::Path;
+
+/// Parse SES bounce and complaint notifications. A file may contain a single
+/// notification, an array of notifications, or one notification per line.
+/// Notifications can be wrapped in an SNS message. Other notifications, like
+/// deliveries, are skipped.
+pub fn parse(content: &[u8], source: &Path) -> Result<Vec<Bounce>, anyhow::Error> {
+    let mut bounces = vec![];
+
+    for value in serde_json::Deserializer::from_slice(content).into_iter::<Value>() {
+        let value = value.context(format!(
+            "Can't parse SES notification in '{}'",
+            source.display()
+        ))?;
+
+        match value {
+            Value::Array(values) => {
+                for value in values {
+                    bounces.extend(notification(value, source)?);
+                }
+            }
+            value => bounces.extend(notification(value, source)?),
+        }
+    }
+
+    Ok(bounces)
+}
+
+fn notification(value: Value, source: &Path) -> Result<Vec<Bounce>, anyhow::Error> {
+    // Unwrap SNS message
+    let value = match value.get("Message").and_then(Value::as_str) {
+        Some(message) => serde_json::from_str::<Value>(message)
+            .context(format!("Can't parse SNS message in '{}'", source.display()))?,
+        None => value,
+    };
+
+    // Notifications use `notificationType`, event publishing uses `eventType`
+    let notification_type = value
+        .get("notificationType")
+        .or_else(|| value.get("eventType"))
+        .and_then(Value::as_str)
+        .ok_or(anyhow!(
+            "Missing notification type in SES notification in '{}'",
+            source.display()
+        ))?;
+    let message_id = string(&value["mail"], "messageId");
+
+    let bounces = match notification_type {
+        "Bounce" => {
+            let bounce = &value["bounce"];
+            let bounce_type = match bounce["bounceType"].as_str() {
+                Some("Permanent") => BounceType::Hard,
+                _ => BounceType::Soft,
+            };
+
+            receivers(&bounce["bouncedRecipients"])
+                .map(|receiver| Bounce {
+                    email: string(receiver, "emailAddress"),
+                    bounce_type,
+                    status: receiver["status"]
+                        .as_str()
+                        .or(bounce["bounceSubType"].as_str())
+                        .unwrap_or_default()
+                        .to_owned(),
+                    diagnostic: dsn::remove_type(&string(receiver, "diagnosticCode")).to_owned(),
+                    date: string(bounce, "timestamp"),
+                    message_id: message_id.clone(),
+                    source: source.display().to_string(),
+                })
+                .collect()
+        }
+        "Complaint" => {
+            let complaint = &value["complaint"];
+
+            receivers(&complaint["complainedRecipients"])
+                .map(|receiver| Bounce {
+                    email: string(receiver, "emailAddress"),
+                    bounce_type: BounceType::Complaint,
+                    status: string(complaint, "complaintFeedbackType"),
+                    diagnostic: String::new(),
+                    date: string(complaint, "timestamp"),
+                    message_id: message_id.clone(),
+                    source: source.display().to_string(),
+                })
+                .collect()
+        }
+        _ => vec![],
+    };
+
+    Ok(bounces)
+}
+
+fn receivers(value: &Value) -> impl Iterator<Item = &Value> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|receiver| receiver["emailAddress"].is_string())
+}
+
+fn string(value: &Value, key: &str) -> String {
+    value[key].as_str().unwrap_or_default().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_parse_bounce() {
+        let path = Path::new("./test_data/bounces/ses_bounce.json");
+        let content = fs::read(path).unwrap();
+
+        let res = parse(&content, path);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let bounces = res.unwrap();
+        assert_eq!(
+            bounces,
+            vec![Bounce {
+                email: "pierre@curie.com".to_owned(),
+                bounce_type: BounceType::Hard,
+                status: "5.1.1".to_owned(),
+                diagnostic: "550 5.1.1 user unknown".to_owned(),
+                date: "2024-01-01T14:00:05.000Z".to_owned(),
+                message_id: "0100018cc5d7b0a1-1234".to_owned(),
+                source: "./test_data/bounces/ses_bounce.json".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_complaint() {
+        let path = Path::new("./test_data/bounces/ses_complaint.json");
+        let content = fs::read(path).unwrap();
+
+        let res = parse(&content, path);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let bounces = res.unwrap();
+        assert_eq!(bounces.len(), 1);
+        assert_eq!(bounces[0].email, "henri@poincare.com");
+        assert_eq!(bounces[0].bounce_type, BounceType::Complaint);
+        assert_eq!(bounces[0].status, "abuse");
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        let content = br#"{"notificationType":"Bounce","bounce":{"bounceType":"Transient","bounceSubType":"MailboxFull","bouncedRecipients":[{"emailAddress":"marie@curie.com"}],"timestamp":"2024-01-01T14:00:05.000Z"},"mail":{"messageId":"1"}}
+{"notificationType":"Delivery","mail":{"messageId":"2"}}
+[{"eventType":"Complaint","complaint":{"complainedRecipients":[{"emailAddress":"emmy@noether.com"}]},"mail":{"messageId":"3"}}]
+"#;
+
+        let res = parse(content, Path::new("notifications.json"));
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let bounces = res.unwrap();
+        assert_eq!(bounces.len(), 2);
+        assert_eq!(bounces[0].email, "marie@curie.com");
+        assert_eq!(bounces[0].bounce_type, BounceType::Soft);
+        assert_eq!(bounces[0].status, "MailboxFull");
+        assert_eq!(bounces[1].email, "emmy@noether.com");
+        assert_eq!(bounces[1].bounce_type, BounceType::Complaint);
+        assert_eq!(bounces[1].message_id, "3");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let res = parse(b"{\"mail\": {}}", Path::new("invalid.json"));
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use super::Bounce;
+use anyhow::{anyhow, Context};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Receivers which must not be mailed again, stored as csv file.
+///
+/// Only hard bounces and complaints are suppressed. Email addresses are
+/// compared case-insensitively, and the first entry of a receiver is kept.
+#[derive(Debug)]
+pub struct SuppressionList {
+    path: PathBuf,
+    entries: Vec<Bounce>,
+    emails: HashSet<String>,
+}
+
+impl SuppressionList {
+    /// Load the suppression list. Fails if the file doesn't exist, so that a
+    /// mistyped path doesn't suppress nobody.
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        if !path.exists() {
+            return Err(anyhow!("Missing suppression file '{}'", path.display()));
+        }
+
+        Ok(Self::new(path, read_bounces(path)?))
+    }
+
+    /// Load the suppression list, or create an empty one if the file doesn't
+    /// exist yet.
+    pub fn load_or_create(path: &Path) -> Result<Self, anyhow::Error> {
+        let entries = if path.exists() {
+            read_bounces(path)?
+        } else {
+            vec![]
+        };
+
+        Ok(Self::new(path, entries))
+    }
+
+    fn new(path: &Path, entries: Vec<Bounce>) -> Self {
+        let emails = entries
+            .iter()
+            .map(|entry| entry.email.to_lowercase())
+            .collect();
+
+        Self {
+            path: path.to_owned(),
+            entries,
+            emails,
+        }
+    }
+
+    pub fn contains(&self, email: &str) -> bool {
+        self.emails.contains(&email.to_lowercase())
+    }
+
+    /// Add a bounce to the suppression list. Returns `false` if the bounce is
+    /// not suppressed or the receiver is already suppressed.
+    pub fn insert(&mut self, bounce: &Bounce) -> bool {
+        if !bounce.bounce_type.is_suppressed() || !self.emails.insert(bounce.email.to_lowercase()) {
+            return false;
+        }
+
+        self.entries.push(bounce.clone());
+        true
+    }
+
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        write_bounces(&self.path, &self.entries)
+    }
+}
+
+fn read_bounces(path: &Path) -> Result<Vec<Bounce>, anyhow::Error> {
+    let mut reader = csv::Reader::from_path(path)
+        .context(format!("Can't read csv file '{}'", path.display()))?;
+
+    reader
+        .deserialize()
+        .collect::<Result<Vec<Bounce>, _>>()
+        .context(format!("Can't read csv file '{}'", path.display()))
+}
+
+/// Write bounces to a csv file.
+pub fn write_bounces(path: &Path, bounces: &[Bounce]) -> Result<(), anyhow::Error> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).context(format!("Can't create directory '{}'", dir.display()))?;
+    }
+
+    let mut writer = csv::Writer::from_path(path)
+        .context(format!("Can't write csv file '{}'", path.display()))?;
+
+    for bounce in bounces {
+        writer
+            .serialize(bounce)
+            .context(format!("Can't write csv file '{}'", path.display()))?;
+    }
+
+    writer
+        .flush()
+        .context(format!("Can't write csv file '{}'", path.display()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email_feedback::BounceType;
+    use tempfile::tempdir;
+
+    fn bounce(email: &str, bounce_type: BounceType) -> Bounce {
+        Bounce {
+            email: email.to_owned(),
+            bounce_type,
+            status: "5.1.1".to_owned(),
+            diagnostic: "550 5.1.1 user unknown".to_owned(),
+            date: "2024-01-01T14:00:05.000Z".to_owned(),
+            message_id: "1234".to_owned(),
+            source: "bounces.json".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_suppression_list() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("suppressions.csv");
+
+        assert!(SuppressionList::load(&path).is_err());
+
+        let mut suppression_list = SuppressionList::load_or_create(&path).unwrap();
+        assert_eq!(suppression_list.entries.len(), 0);
+
+        assert!(suppression_list.insert(&bounce("marie@curie.com", BounceType::Hard)));
+        assert!(!suppression_list.insert(&bounce("Marie@Curie.com", BounceType::Complaint)));
+        assert!(!suppression_list.insert(&bounce("pierre@curie.com", BounceType::Soft)));
+        assert!(suppression_list.insert(&bounce("emmy@noether.com", BounceType::Complaint)));
+
+        let res = suppression_list.save();
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let suppression_list = SuppressionList::load(&path).unwrap();
+        assert_eq!(suppression_list.entries.len(), 2);
+        assert!(suppression_list.contains("MARIE@curie.com"));
+        assert!(suppression_list.contains("emmy@noether.com"));
+        assert!(!suppression_list.contains("pierre@curie.com"));
+        assert_eq!(
+            suppression_list.entries[0],
+            bounce("marie@curie.com", BounceType::Hard)
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use crate::arg::{self, val};
+use anyhow::{anyhow, Context};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::{
+    fmt,
+    io::{Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveCompression {
+    Gzip,
+    Zstd,
+}
+
+impl ArchiveCompression {
+    /// Detect the compression of an archived file by its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => Some(Self::Gzip),
+            Some("zst") => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &str {
+        match self {
+            Self::Gzip => "gz",
+            Self::Zstd => "zst",
+        }
+    }
+
+    pub fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(vec![], Compression::default());
+                encoder.write_all(bytes).context("Can't compress email")?;
+                encoder.finish().context("Can't compress email")
+            }
+            Self::Zstd => zstd::encode_all(bytes, 0).context("Can't compress email"),
+        }
+    }
+
+    pub fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        match self {
+            Self::Gzip => {
+                let mut decompressed = vec![];
+                GzDecoder::new(bytes)
+                    .read_to_end(&mut decompressed)
+                    .context("Can't decompress email")?;
+                Ok(decompressed)
+            }
+            Self::Zstd => zstd::decode_all(bytes).context("Can't decompress email"),
+        }
+    }
+}
+
+impl FromStr for ArchiveCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            val::GZIP => Ok(Self::Gzip),
+            val::ZSTD => Ok(Self::Zstd),
+            other => Err(anyhow!(
+                "Value '{}' for argument '{}' not supported",
+                other,
+                arg::ARCHIVE_COMPRESSION
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ArchiveCompression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let archive_compression = match self {
+            Self::Gzip => val::GZIP,
+            Self::Zstd => val::ZSTD,
+        };
+
+        write!(f, "{}", archive_compression)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_compression_from_str() {
+        assert_eq!(
+            "gzip".parse::<ArchiveCompression>().unwrap(),
+            ArchiveCompression::Gzip
+        );
+        assert_eq!(
+            "zstd".parse::<ArchiveCompression>().unwrap(),
+            ArchiveCompression::Zstd
+        );
+        assert!("bzip2".parse::<ArchiveCompression>().is_err());
+    }
+
+    #[test]
+    fn test_archive_compression_roundtrip() {
+        let message = b"Subject: Test subject\r\n\r\nDear Marie\r\n".repeat(100);
+
+        for compression in [ArchiveCompression::Gzip, ArchiveCompression::Zstd] {
+            let compressed = compression.compress(&message).unwrap();
+            assert!(compressed.len() < message.len());
+            assert_eq!(compression.decompress(&compressed).unwrap(), message);
+
+            let path =
+                Path::new("test.eml").with_extension(format!("eml.{}", compression.extension()));
+            assert_eq!(ArchiveCompression::from_path(&path), Some(compression));
+        }
+
+        assert_eq!(ArchiveCompression::from_path(Path::new("test.eml")), None);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::arg::{self, val};
+use anyhow::anyhow;
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Eml,
+    Mbox,
+    Maildir,
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            val::EML => Ok(Self::Eml),
+            val::MBOX => Ok(Self::Mbox),
+            val::MAILDIR => Ok(Self::Maildir),
+            other => Err(anyhow!(
+                "Value '{}' for argument '{}' not supported",
+                other,
+                arg::ARCHIVE_FORMAT
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let archive_format = match self {
+            Self::Eml => val::EML,
+            Self::Mbox => val::MBOX,
+            Self::Maildir => val::MAILDIR,
+        };
+
+        write!(f, "{}", archive_format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_format_from_str() {
+        assert_eq!("eml".parse::<ArchiveFormat>().unwrap(), ArchiveFormat::Eml);
+        assert_eq!(
+            "mbox".parse::<ArchiveFormat>().unwrap(),
+            ArchiveFormat::Mbox
+        );
+        assert_eq!(
+            "Maildir".parse::<ArchiveFormat>().unwrap(),
+            ArchiveFormat::Maildir
+        );
+        assert!("pst".parse::<ArchiveFormat>().is_err());
+    }
+}
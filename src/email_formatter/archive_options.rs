@@ -0,0 +1,29 @@
+use super::{ArchiveCompression, ArchiveFormat};
+use crate::arg;
+use clap::ArgMatches;
+
+/// Options how to archive the emails of a campaign.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArchiveOptions {
+    pub format: ArchiveFormat,
+    /// Compression of archived `.eml` files.
+    pub compression: Option<ArchiveCompression>,
+    /// Store identical attachments only once per campaign.
+    pub dedup_attachments: bool,
+}
+
+impl ArchiveOptions {
+    pub fn from_args(matches: &ArgMatches) -> Result<Self, anyhow::Error> {
+        let format = arg::value(arg::ARCHIVE_FORMAT, matches)?.parse::<ArchiveFormat>()?;
+        let compression = match matches.get_one::<String>(arg::ARCHIVE_COMPRESSION) {
+            Some(compression) => Some(compression.parse::<ArchiveCompression>()?),
+            None => None,
+        };
+
+        Ok(Self {
+            format,
+            compression,
+            dedup_attachments: matches.get_flag(arg::DEDUP_ATTACHMENTS),
+        })
+    }
+}
@@ -0,0 +1,218 @@
+use anyhow::{anyhow, Context};
+use mailparse::{DispositionType, ParsedMail};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// Directory for de-duplicated attachments in the campaign directory.
+pub const ATTACHMENT_DIR: &str = "attachments";
+/// Prefix of the reference which replaces the body of a de-duplicated
+/// attachment in an archived email.
+const ATTACHMENT_REFERENCE: &str = "pigeon-attachment:sha256:";
+/// Length of a hex encoded SHA-256 hash.
+const HASH_LEN: usize = 64;
+
+/// Store for attachments shared by the emails of a campaign.
+///
+/// The encoded body of each attachment is stored once in a file named after
+/// its SHA-256 hash, and replaced by a reference in the archived email. The
+/// headers of the attachment are kept, so that the archived email can be
+/// restored byte by byte.
+pub struct AttachmentStore {
+    dir: PathBuf,
+}
+
+impl AttachmentStore {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_owned(),
+        }
+    }
+
+    /// Store the attachments of a formatted message, and return the message
+    /// with references to the stored attachments.
+    pub fn dedup(&self, message: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        let mail = mailparse::parse_mail(message).context("Can't parse archived email")?;
+        let mut bodies = vec![];
+        collect_attachment_bodies(message, &mail, &mut bodies)?;
+        bodies.sort_by_key(|body| body.start);
+
+        let mut deduped = Vec::with_capacity(message.len());
+        let mut position = 0;
+
+        for body in bodies {
+            let bytes = &message[body.clone()];
+            let hash = format!("{:x}", Sha256::digest(bytes));
+            let path = self.dir.join(&hash);
+
+            if !path.exists() {
+                fs::create_dir_all(&self.dir).context(format!(
+                    "Unable to create attachment directory '{}'",
+                    self.dir.display()
+                ))?;
+                fs::write(&path, bytes)
+                    .context(format!("Can't write attachment file '{}'", path.display()))?;
+            }
+
+            deduped.extend_from_slice(&message[position..body.start]);
+            deduped.extend_from_slice(ATTACHMENT_REFERENCE.as_bytes());
+            deduped.extend_from_slice(hash.as_bytes());
+            position = body.end;
+        }
+
+        deduped.extend_from_slice(&message[position..]);
+
+        Ok(deduped)
+    }
+
+    /// Replace the references in an archived email by the stored attachments.
+    /// Only the bodies of attachments are replaced, so that a reference in the
+    /// text of the message is kept as is.
+    pub fn restore(&self, message: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        let mail = mailparse::parse_mail(message).context("Can't parse archived email")?;
+        let mut bodies = vec![];
+        collect_attachment_bodies(message, &mail, &mut bodies)?;
+        bodies.sort_by_key(|body| body.start);
+
+        let mut restored = Vec::with_capacity(message.len());
+        let mut position = 0;
+
+        for body in bodies {
+            let hash = match message[body.clone()].strip_prefix(ATTACHMENT_REFERENCE.as_bytes()) {
+                Some(hash) => hash,
+                None => continue,
+            };
+            let hash = std::str::from_utf8(hash)
+                .ok()
+                .filter(|hash| {
+                    hash.len() == HASH_LEN && hash.chars().all(|char| char.is_ascii_hexdigit())
+                })
+                .ok_or(anyhow!("Invalid attachment reference in archived email"))?;
+            let path = self.dir.join(hash);
+            let attachment = fs::read(&path)
+                .context(format!("Can't read attachment file '{}'", path.display()))?;
+
+            restored.extend_from_slice(&message[position..body.start]);
+            restored.extend_from_slice(&attachment);
+            position = body.end;
+        }
+
+        restored.extend_from_slice(&message[position..]);
+
+        Ok(restored)
+    }
+}
+
+/// Collect the ranges of the encoded bodies of all attachments in the
+/// message. The line break before the next boundary is not part of the body.
+fn collect_attachment_bodies(
+    message: &[u8],
+    mail: &ParsedMail,
+    bodies: &mut Vec<Range<usize>>,
+) -> Result<(), anyhow::Error> {
+    if !mail.subparts.is_empty() {
+        for subpart in &mail.subparts {
+            collect_attachment_bodies(message, subpart, bodies)?;
+        }
+
+        return Ok(());
+    }
+
+    let disposition = mail.get_content_disposition();
+    let is_attachment = disposition.disposition == DispositionType::Attachment
+        || disposition.params.contains_key("filename")
+        || mail.ctype.params.contains_key("name");
+
+    if !is_attachment {
+        return Ok(());
+    }
+
+    // The parsed part borrows from the message
+    let offset = mail.raw_bytes.as_ptr() as usize - message.as_ptr() as usize;
+    let (_, body_start) =
+        mailparse::parse_headers(mail.raw_bytes).context("Can't parse archived email")?;
+    let body = &mail.raw_bytes[body_start..];
+    let body = body.strip_suffix(b"\r\n").unwrap_or(body);
+
+    if !body.is_empty() {
+        bodies.push(offset + body_start..offset + body_start + body.len());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn message(receiver: &str) -> Vec<u8> {
+        format!("From: albert@einstein.com\r\nTo: {}\r\nMIME-Version: 1.0\r\nContent-Type: multipart/mixed; boundary=\"b1\"\r\n\r\n--b1\r\nContent-Type: text/plain; charset=utf-8\r\n\r\nDear {}\r\n--b1\r\nContent-Type: application/pdf\r\nContent-Disposition: attachment; filename=\"test.pdf\"\r\nContent-Transfer-Encoding: base64\r\n\r\nJVBERi0xLjQKJcfsj6IK\r\nJVBERi0xLjQKJcfsj6IK\r\n--b1--\r\n", receiver, receiver).into_bytes()
+    }
+
+    #[test]
+    fn test_attachment_store() {
+        let temp_dir = tempdir().unwrap();
+        let store = AttachmentStore::new(&temp_dir.path().join(ATTACHMENT_DIR));
+
+        for receiver in ["marie@curie.com", "emmy@noether.com"] {
+            let message = message(receiver);
+            let res = store.dedup(&message);
+            assert!(res.is_ok(), "{}", res.unwrap_err());
+
+            let res = res.unwrap();
+            let text = String::from_utf8(res.clone()).unwrap();
+            assert!(!text.contains("JVBERi0xLjQKJcfsj6IK"));
+            assert!(text.contains(&format!("Dear {}", receiver)));
+            assert!(text.contains("filename=\"test.pdf\""));
+            assert!(text.contains("\r\n--b1--\r\n"));
+
+            let restored = store.restore(&res).unwrap();
+            assert_eq!(restored, message);
+        }
+
+        let files = fs::read_dir(temp_dir.path().join(ATTACHMENT_DIR))
+            .unwrap()
+            .flatten()
+            .collect::<Vec<_>>();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            fs::read(files[0].path()).unwrap(),
+            b"JVBERi0xLjQKJcfsj6IK\r\nJVBERi0xLjQKJcfsj6IK"
+        );
+    }
+
+    #[test]
+    fn test_attachment_store_missing() {
+        let temp_dir = tempdir().unwrap();
+        let store = AttachmentStore::new(temp_dir.path());
+        let message = format!(
+            "Subject: Test\r\nContent-Disposition: attachment; filename=\"test.pdf\"\r\n\r\n{}{}\r\n",
+            ATTACHMENT_REFERENCE,
+            "0".repeat(HASH_LEN)
+        );
+        assert!(store.restore(message.as_bytes()).is_err());
+
+        let message = b"Subject: Test\r\n\r\nDear Marie\r\n";
+        assert_eq!(store.restore(message).unwrap(), message);
+    }
+
+    #[test]
+    fn test_attachment_store_reference_in_text() {
+        let temp_dir = tempdir().unwrap();
+        let store = AttachmentStore::new(temp_dir.path());
+
+        // A reference in the text of the message is not an attachment
+        let message = format!(
+            "Subject: Test\r\n\r\nSee {}{}\r\n",
+            ATTACHMENT_REFERENCE,
+            "0".repeat(HASH_LEN)
+        );
+        let res = store.restore(message.as_bytes());
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        assert_eq!(res.unwrap(), message.as_bytes());
+    }
+}
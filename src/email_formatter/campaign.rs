@@ -0,0 +1,416 @@
+use super::{ArchiveFormat, ArchiveOptions, AttachmentStore, EmlFormatter, ATTACHMENT_DIR};
+use crate::{
+    arg::{self, val},
+    email_builder::{Email, Message, Sender},
+    email_transmission::{SentEmail, Status},
+};
+use anyhow::Context;
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::ArgMatches;
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::OnceCell,
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+/// File name of the campaign metadata in the campaign directory.
+pub const CAMPAIGN_FILE: &str = "campaign.json";
+/// File name of the index in the campaign directory.
+pub const INDEX_FILE: &str = "index.jsonl";
+/// Directory for emails which couldn't be sent in the campaign directory.
+pub const FAILED_DIR: &str = "failed";
+/// Maildir++ folder for emails which couldn't be sent.
+pub const FAILED_FOLDER: &str = ".Failed";
+
+/// The source of the receivers of a campaign.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// Path of the csv file.
+    File(String),
+    /// Text of the SQL query.
+    Query(String),
+    /// Receiver given as argument.
+    Argument,
+}
+
+impl Source {
+    pub fn from_args(matches: &ArgMatches) -> Self {
+        if let Some(path) = matches.get_one::<String>(arg::RECEIVER_FILE) {
+            Self::File(path.to_owned())
+        } else if let Some(query) = matches.get_one::<String>(arg::RECEIVER_QUERY) {
+            Self::Query(query.to_owned())
+        } else {
+            Self::Argument
+        }
+    }
+}
+
+/// Metadata of a campaign, i.e. a single run of `send` or `send-bulk`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Campaign {
+    pub id: String,
+    pub created_at: String,
+    pub dry_run: bool,
+    pub sender: String,
+    pub subject: String,
+    pub source: Source,
+    pub archive_format: String,
+    pub archive_compression: Option<String>,
+    #[serde(default)]
+    pub dedup_attachments: bool,
+    /// The split of the receivers for A/B testing, e.g. `10/10`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split: Option<String>,
+    /// The variant which is sent to the holdout of the split.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub winner: Option<String>,
+}
+
+impl Campaign {
+    pub fn new(
+        sender: Sender,
+        message: &Message,
+        source: Source,
+        archive_options: &ArchiveOptions,
+        dry_run: bool,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let created_at = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+        // The short uuid keeps the ids of runs within the same second apart
+        let id = format!(
+            "{}_{}",
+            now.format("%Y%m%dT%H%M%SZ"),
+            &Uuid::new_v4().simple().to_string()[..8]
+        );
+        let id = if dry_run {
+            format!("{}_dry-run", id)
+        } else {
+            id
+        };
+
+        Self {
+            id,
+            created_at,
+            dry_run,
+            sender: sender.0.to_owned(),
+            subject: message.subject.to_owned(),
+            source,
+            archive_format: archive_options.format.to_string(),
+            archive_compression: archive_options
+                .compression
+                .map(|compression| compression.to_string()),
+            dedup_attachments: archive_options.dedup_attachments,
+            split: None,
+            winner: None,
+        }
+    }
+}
+
+/// An entry of the index, i.e. a single archived email.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub receivers: Vec<String>,
+    pub message_id: Option<String>,
+    /// The id returned by the provider, e.g. the SES message id or the queue
+    /// id of the SMTP server.
+    pub provider_message_id: Option<String>,
+    /// One of `ok`, `dry-run`, or `failed`.
+    pub status: String,
+    pub error: Option<String>,
+    pub personalization: BTreeMap<String, String>,
+    /// The variant of the message if the receivers are split for A/B testing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    /// The transport the email was sent via, e.g. `smtp` or `aws`.
+    #[serde(default)]
+    pub transport: Option<String>,
+    /// Path of the archived email relative to the campaign directory.
+    pub file: String,
+    pub archived_at: String,
+}
+
+impl IndexEntry {
+    pub fn new(email: &Email, sent_email: &SentEmail, file: String, now: DateTime<Utc>) -> Self {
+        let (status, provider_message_id, error) = match &sent_email.status {
+            Status::DryRun => (val::DRY_RUN, None, None),
+            Status::SentOk(message_id) if message_id.is_empty() => (val::OK, None, None),
+            Status::SentOk(message_id) => (val::OK, Some(message_id.clone()), None),
+            Status::SentError(err) | Status::TransientError(err) => {
+                (val::FAILED, None, Some(err.clone()))
+            }
+        };
+
+        Self {
+            receivers: email
+                .receivers()
+                .iter()
+                .map(|receiver| receiver.0.to_owned())
+                .collect(),
+            message_id: email.mime_format.message_id().map(str::to_owned),
+            provider_message_id,
+            status: status.to_owned(),
+            error,
+            personalization: email.personalization.clone(),
+            variant: email.variant.clone(),
+            transport: sent_email.transport.map(|transport| transport.to_string()),
+            file,
+            archived_at: now.to_rfc3339_opts(SecondsFormat::Secs, true),
+        }
+    }
+}
+
+/// Archive of a campaign.
+///
+/// Each campaign is stored in a separate directory in the archive directory.
+/// The campaign directory contains the archived emails, the campaign metadata
+/// in `campaign.json`, and an index in `index.jsonl` with one line per email.
+/// Emails which couldn't be sent are stored separately in the directory
+/// `failed`, or in the Maildir subfolder `.Failed`. Identical attachments
+/// can be stored once in the directory `attachments`.
+pub struct CampaignArchive {
+    dir: PathBuf,
+    dry_run: bool,
+    archive_options: ArchiveOptions,
+    eml_formatter: EmlFormatter,
+    attachment_store: Option<AttachmentStore>,
+    /// Created on the first failed email to avoid empty directories.
+    failed_formatter: OnceCell<EmlFormatter>,
+}
+
+impl CampaignArchive {
+    pub fn new(
+        archive_dir: &Path,
+        archive_options: &ArchiveOptions,
+        campaign: &Campaign,
+    ) -> Result<Self, anyhow::Error> {
+        fs::create_dir_all(archive_dir).context(format!(
+            "Unable to create archive directory '{}'",
+            archive_dir.display()
+        ))?;
+
+        // Fails if the directory exists, so that campaigns are never mixed
+        let dir = archive_dir.join(&campaign.id);
+        fs::create_dir(&dir).context(format!(
+            "Unable to create campaign directory '{}'",
+            dir.display()
+        ))?;
+
+        let path = dir.join(CAMPAIGN_FILE);
+        let json =
+            serde_json::to_string_pretty(campaign).context("Can't serialize campaign metadata")?;
+        fs::write(&path, json).context(format!("Can't write file '{}'", path.display()))?;
+
+        let eml_formatter =
+            EmlFormatter::new(&dir, archive_options.format, archive_options.compression)?;
+        let attachment_store = archive_options
+            .dedup_attachments
+            .then(|| AttachmentStore::new(&dir.join(ATTACHMENT_DIR)));
+
+        Ok(Self {
+            dir,
+            dry_run: campaign.dry_run,
+            archive_options: *archive_options,
+            eml_formatter,
+            attachment_store,
+            failed_formatter: OnceCell::new(),
+        })
+    }
+
+    /// Archive an email depending on the send outcome, and add it to the
+    /// index.
+    pub fn archive(
+        &self,
+        email: &Email,
+        sent_email: &SentEmail,
+        now: DateTime<Utc>,
+    ) -> Result<(), anyhow::Error> {
+        let eml_formatter = match sent_email.status {
+            Status::SentError(_) | Status::TransientError(_) => self.failed_formatter()?,
+            Status::DryRun | Status::SentOk(_) => &self.eml_formatter,
+        };
+        let path = match &self.attachment_store {
+            Some(attachment_store) => {
+                let message = attachment_store.dedup(email.mime_format.formatted())?;
+                eml_formatter.archive_message(
+                    email.mime_format.envelope(),
+                    &message,
+                    now,
+                    self.dry_run,
+                )?
+            }
+            None => eml_formatter.archive(email, now, self.dry_run)?,
+        };
+        let file = path
+            .strip_prefix(&self.dir)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        let entry = IndexEntry::new(email, sent_email, file, now);
+
+        let mut line = serde_json::to_string(&entry).context("Can't serialize index entry")?;
+        line.push('\n');
+
+        let path = self.dir.join(INDEX_FILE);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .context(format!("Can't open index file '{}'", path.display()))?;
+        file.write_all(line.as_bytes())
+            .context(format!("Can't write index file '{}'", path.display()))?;
+
+        Ok(())
+    }
+
+    fn failed_formatter(&self) -> Result<&EmlFormatter, anyhow::Error> {
+        if let Some(eml_formatter) = self.failed_formatter.get() {
+            return Ok(eml_formatter);
+        }
+
+        let dir = match self.archive_options.format {
+            ArchiveFormat::Maildir => self.dir.join(FAILED_FOLDER),
+            ArchiveFormat::Eml | ArchiveFormat::Mbox => self.dir.join(FAILED_DIR),
+        };
+        let eml_formatter = EmlFormatter::new(
+            &dir,
+            self.archive_options.format,
+            self.archive_options.compression,
+        )?;
+
+        Ok(self.failed_formatter.get_or_init(|| eml_formatter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        email_builder::{MimeFormat, Receiver},
+        email_transmission::TransmissionType,
+    };
+    use std::time::SystemTime;
+    use tempfile::tempdir;
+
+    const ARCHIVE_OPTIONS: ArchiveOptions = ArchiveOptions {
+        format: ArchiveFormat::Eml,
+        compression: None,
+        dedup_attachments: false,
+    };
+
+    fn create_email<'a>(message: &Message) -> Email<'a> {
+        let sender = Sender("albert@einstein.com");
+        let receiver = Receiver("marie@curie.com");
+        let mime_format =
+            MimeFormat::new(sender, receiver, message, &[], SystemTime::now()).unwrap();
+        let mut email = Email::new(sender, receiver, message, &mime_format).unwrap();
+        email
+            .personalization
+            .insert("first_name".to_owned(), "Marie".to_owned());
+        email
+    }
+
+    #[test]
+    fn test_campaign_archive() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path();
+
+        let now = DateTime::parse_from_rfc3339("2024-01-01T14:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let message = Message::new("Test subject", Some("Dear Marie"), None);
+        let source = Source::File("./receiver.csv".to_owned());
+        let campaign = Campaign::new(
+            Sender("albert@einstein.com"),
+            &message,
+            source,
+            &ARCHIVE_OPTIONS,
+            true,
+            now,
+        );
+        assert!(campaign.id.starts_with("20240101T140000Z_"));
+        assert!(campaign.id.ends_with("_dry-run"));
+        assert!(!campaign.id.contains(':'));
+        assert_ne!(
+            Campaign::new(
+                Sender("albert@einstein.com"),
+                &message,
+                Source::Argument,
+                &ARCHIVE_OPTIONS,
+                true,
+                now,
+            )
+            .id,
+            campaign.id
+        );
+
+        let res = CampaignArchive::new(temp_path, &ARCHIVE_OPTIONS, &campaign);
+        assert!(res.is_ok(), "{}", res.err().unwrap());
+
+        let campaign_archive = res.unwrap();
+
+        // The same campaign can't be archived twice
+        assert!(CampaignArchive::new(temp_path, &ARCHIVE_OPTIONS, &campaign).is_err());
+
+        let email = create_email(&message);
+        let sent_email = SentEmail::new(&email, Status::DryRun);
+        let res = campaign_archive.archive(&email, &sent_email, now);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        let status = Status::SentError("550 mailbox unavailable".to_owned());
+        let sent_email = SentEmail::new(&email, status);
+        let res = campaign_archive.archive(&email, &sent_email, now);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        let mut sent_email = SentEmail::new(&email, Status::SentOk("4F1A2B3C".to_owned()));
+        sent_email.transport = Some(TransmissionType::Aws);
+        let res = campaign_archive.archive(&email, &sent_email, now);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let campaign_dir = temp_path.join(&campaign.id);
+        let json = fs::read_to_string(campaign_dir.join(CAMPAIGN_FILE)).unwrap();
+        assert_eq!(serde_json::from_str::<Campaign>(&json).unwrap(), campaign);
+        assert!(json.contains(r#""file": "./receiver.csv""#));
+
+        let index = fs::read_to_string(campaign_dir.join(INDEX_FILE)).unwrap();
+        let entries = index
+            .lines()
+            .map(|line| serde_json::from_str::<IndexEntry>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].receivers, vec!["marie@curie.com"]);
+        assert_eq!(
+            entries[0].message_id.as_deref(),
+            email.mime_format.message_id()
+        );
+        assert_eq!(entries[0].status, "dry-run");
+        assert_eq!(entries[0].error, None);
+        assert_eq!(entries[0].personalization["first_name"], "Marie");
+        assert!(campaign_dir.join(&entries[0].file).is_file());
+        assert_eq!(entries[0].provider_message_id, None);
+        assert_eq!(entries[1].status, "failed");
+        assert_eq!(entries[1].error.as_deref(), Some("550 mailbox unavailable"));
+        assert!(entries[1].file.starts_with(FAILED_DIR));
+        assert!(campaign_dir.join(&entries[1].file).is_file());
+        assert_eq!(entries[2].status, "ok");
+        assert_eq!(entries[2].provider_message_id.as_deref(), Some("4F1A2B3C"));
+        assert_eq!(entries[2].transport.as_deref(), Some("aws"));
+        assert_eq!(entries[0].transport, None);
+        assert!(!entries[2].file.starts_with(FAILED_DIR));
+    }
+
+    #[test]
+    fn test_source_serialization() {
+        let source = Source::Query("select email from account".to_owned());
+        assert_eq!(
+            serde_json::to_string(&source).unwrap(),
+            r#"{"query":"select email from account"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Source::Argument).unwrap(),
+            r#""argument""#
+        );
+    }
+}
@@ -1,71 +1,225 @@
+use super::{ArchiveCompression, ArchiveFormat};
 use crate::email_builder::Email;
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use chrono::{DateTime, Utc};
-use lettre::{FileTransport, Transport};
+use lettre::address::Envelope;
 use std::{
-    fs,
+    fs::{self, OpenOptions},
+    io::Write,
     path::{Path, PathBuf},
 };
+use uuid::Uuid;
 
-/// Structure to store emails in EML format.
+/// Maildir++ folder for emails of dry runs
+pub const DRY_RUN_FOLDER: &str = ".DryRun";
+
+/// Structure to store emails in EML, mbox, or Maildir format.
+///
+/// In EML format, each email is stored as separate file. In mbox format, all
+/// emails of a campaign are appended to a single file. In Maildir format, the
+/// target directory is used as Maildir, and emails of dry runs are stored in
+/// the subfolder `.DryRun`. Emails in EML format can be compressed.
 pub struct EmlFormatter {
     target_dir: PathBuf,
-    transport: FileTransport,
+    archive_format: ArchiveFormat,
+    compression: Option<ArchiveCompression>,
+    created_at: DateTime<Utc>,
 }
 
 impl EmlFormatter {
-    pub fn new(target_dir: &Path) -> Result<Self, anyhow::Error> {
+    pub fn new(
+        target_dir: &Path,
+        archive_format: ArchiveFormat,
+        compression: Option<ArchiveCompression>,
+    ) -> Result<Self, anyhow::Error> {
+        if compression.is_some() && archive_format != ArchiveFormat::Eml {
+            return Err(anyhow!(
+                "Compression is only supported for archive format '{}'",
+                ArchiveFormat::Eml
+            ));
+        }
+
         if !target_dir.exists() {
             fs::create_dir(target_dir).context("Unable to create directory for archived emails")?;
         }
 
-        let transport = FileTransport::new(target_dir);
         let formatter = Self {
             target_dir: target_dir.to_owned(),
-            transport,
+            archive_format,
+            compression,
+            created_at: Utc::now(),
         };
 
         Ok(formatter)
     }
 
+    /// Archive an email, and return the path of the file it is stored in.
     pub fn archive(
         &self,
         email: &Email,
         now: DateTime<Utc>,
         dry_run: bool,
-    ) -> Result<(), anyhow::Error> {
-        let message_id = self
-            .transport
-            .send(&email.mime_format.message)
-            .context("Can't save email in .eml format")?;
+    ) -> Result<PathBuf, anyhow::Error> {
+        self.archive_message(
+            email.mime_format.envelope(),
+            email.mime_format.formatted(),
+            now,
+            dry_run,
+        )
+    }
+
+    /// Archive a formatted message, and return the path of the file it is
+    /// stored in.
+    pub fn archive_message(
+        &self,
+        envelope: &Envelope,
+        message: &[u8],
+        now: DateTime<Utc>,
+        dry_run: bool,
+    ) -> Result<PathBuf, anyhow::Error> {
+        match self.archive_format {
+            ArchiveFormat::Eml => self.archive_eml(message, now, dry_run),
+            ArchiveFormat::Mbox => self.archive_mbox(envelope, message, now, dry_run),
+            ArchiveFormat::Maildir => self.archive_maildir(message, now, dry_run),
+        }
+    }
+
+    fn archive_eml(
+        &self,
+        message: &[u8],
+        now: DateTime<Utc>,
+        dry_run: bool,
+    ) -> Result<PathBuf, anyhow::Error> {
+        let file_id = Uuid::new_v4().to_string();
+        let path = eml_path(&file_id, &self.target_dir, dry_run, now, self.compression);
+
+        println!("Archiving '{}' ...", path.display());
+
+        match self.compression {
+            Some(compression) => fs::write(&path, compression.compress(message)?),
+            None => fs::write(&path, message),
+        }
+        .context("Can't save email in .eml format")?;
+
+        Ok(path)
+    }
+
+    fn archive_mbox(
+        &self,
+        envelope: &Envelope,
+        message: &[u8],
+        now: DateTime<Utc>,
+        dry_run: bool,
+    ) -> Result<PathBuf, anyhow::Error> {
+        let path = mbox_path(&self.target_dir, dry_run, self.created_at);
+        let sender = envelope
+            .from()
+            .map(|address| address.to_string())
+            .unwrap_or_else(|| "MAILER-DAEMON".to_owned());
+        let entry = mbox_entry(&sender, message, now);
+
+        println!("Archiving '{}' ...", path.display());
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .context(format!("Can't open mbox file '{}'", path.display()))?;
+        file.write_all(&entry)
+            .context("Can't save email in mbox format")?;
+
+        Ok(path)
+    }
+
+    fn archive_maildir(
+        &self,
+        message: &[u8],
+        now: DateTime<Utc>,
+        dry_run: bool,
+    ) -> Result<PathBuf, anyhow::Error> {
+        let maildir = if dry_run {
+            self.target_dir.join(DRY_RUN_FOLDER)
+        } else {
+            self.target_dir.clone()
+        };
 
-        let old_path = old_path(message_id.as_str(), &self.target_dir);
-        let new_path = new_path(message_id.as_str(), &self.target_dir, dry_run, now);
+        for sub_dir in ["tmp", "new", "cur"] {
+            fs::create_dir_all(maildir.join(sub_dir))
+                .context("Unable to create Maildir for archived emails")?;
+        }
 
-        println!("Archiving '{}' ...", new_path.display());
+        // Unique file name as recommended in https://cr.yp.to/proto/maildir.html
+        let file_name = format!("{}.{}.pigeon", now.timestamp(), Uuid::new_v4().simple());
+        let tmp_path = maildir.join("tmp").join(&file_name);
+        // Sent emails are marked as seen
+        let cur_path = maildir.join("cur").join(format!("{}:2,S", file_name));
 
-        // TODO: renaming file is required because of issue/discussion https://github.com/lettre/lettre/discussions/711
-        fs::rename(old_path, new_path).context("Can't rename archived email")?;
+        println!("Archiving '{}' ...", cur_path.display());
 
-        Ok(())
+        fs::write(&tmp_path, message).context("Can't save email in Maildir format")?;
+        fs::rename(tmp_path, &cur_path).context("Can't move archived email to Maildir")?;
+
+        Ok(cur_path)
     }
 }
 
-fn old_path(message_id: &str, target_dir: &Path) -> PathBuf {
-    let old_file_name = format!("{}.eml", message_id);
-    target_dir.join(old_file_name)
+fn eml_path(
+    file_id: &str,
+    target_dir: &Path,
+    dry_run: bool,
+    now: DateTime<Utc>,
+    compression: Option<ArchiveCompression>,
+) -> PathBuf {
+    // Colons are not allowed in file names on Windows
+    let timestamp = now.format("%Y%m%dT%H%M%SZ");
+
+    let mut file_name = if dry_run {
+        format!("{}_{}_dry-run.eml", timestamp, file_id)
+    } else {
+        format!("{}_{}.eml", timestamp, file_id)
+    };
+
+    if let Some(compression) = compression {
+        file_name = format!("{}.{}", file_name, compression.extension());
+    }
+
+    target_dir.join(file_name)
 }
 
-fn new_path(message_id: &str, target_dir: &Path, dry_run: bool, now: DateTime<Utc>) -> PathBuf {
-    let timestamp = now.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+fn mbox_path(target_dir: &Path, dry_run: bool, created_at: DateTime<Utc>) -> PathBuf {
+    let timestamp = created_at.format("%Y%m%dT%H%M%SZ");
 
-    let new_file_name = if dry_run {
-        format!("{}_{}_dry-run.eml", timestamp, message_id)
+    let file_name = if dry_run {
+        format!("{}_dry-run.mbox", timestamp)
     } else {
-        format!("{}_{}.eml", timestamp, message_id)
+        format!("{}.mbox", timestamp)
     };
 
-    target_dir.join(new_file_name)
+    target_dir.join(file_name)
+}
+
+/// Format an email as mbox entry in mboxrd format: the entry starts with a
+/// `From_` line, lines starting with any number of `>` followed by `From ` are
+/// escaped by an additional `>`, and the entry ends with an empty line.
+fn mbox_entry(sender: &str, message: &[u8], now: DateTime<Utc>) -> Vec<u8> {
+    let mut entry =
+        format!("From {} {}\n", sender, now.format("%a %b %e %H:%M:%S %Y")).into_bytes();
+
+    for line in message.split_inclusive(|byte| *byte == b'\n') {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let quotes = line.iter().take_while(|byte| **byte == b'>').count();
+
+        if line[quotes..].starts_with(b"From ") {
+            entry.push(b'>');
+        }
+
+        entry.extend_from_slice(line);
+        entry.push(b'\n');
+    }
+
+    entry.push(b'\n');
+    entry
 }
 
 #[cfg(test)]
@@ -82,7 +236,7 @@ mod tests {
         let text = "This is a test message (plaintext).";
         let html = "<p>This is a test message (html).</p>";
         let message = Message::new(subject, Some(text), Some(html));
-        let mime_format = MimeFormat::new(sender, receiver, &message, None, now).unwrap();
+        let mime_format = MimeFormat::new(sender, receiver, &message, &[], now).unwrap();
         Email::new(sender, receiver, &message, &mime_format).unwrap()
     }
 
@@ -97,7 +251,7 @@ mod tests {
         let dry_run = false;
         let email = create_email(now_system_time);
 
-        let email_formatter = EmlFormatter::new(temp_path).unwrap();
+        let email_formatter = EmlFormatter::new(temp_path, ArchiveFormat::Eml, None).unwrap();
         let res = email_formatter.archive(&email, now, dry_run);
         assert!(res.is_ok(), "{}", res.unwrap_err());
 
@@ -113,6 +267,126 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_archive_compressed() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path();
+
+        let now = Utc::now();
+        let now_system_time = SystemTime::UNIX_EPOCH + Duration::from_secs(now.timestamp() as u64);
+        let email = create_email(now_system_time);
+
+        let compression = Some(ArchiveCompression::Gzip);
+        let res = EmlFormatter::new(temp_path, ArchiveFormat::Mbox, compression);
+        assert!(res.is_err());
+
+        let email_formatter =
+            EmlFormatter::new(temp_path, ArchiveFormat::Eml, compression).unwrap();
+        let res = email_formatter.archive(&email, now, false);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let path = res.unwrap();
+        assert!(path.to_str().unwrap().ends_with(".eml.gz"));
+
+        let eml = ArchiveCompression::Gzip
+            .decompress(&fs::read(path).unwrap())
+            .unwrap();
+        assert_eq!(eml, email.mime_format.formatted());
+    }
+
+    #[test]
+    fn test_archive_path() {
+        let now = DateTime::parse_from_rfc3339("2024-01-01T14:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let target_dir = Path::new("sent_emails");
+
+        assert_eq!(
+            eml_path("1234", target_dir, true, now, None),
+            target_dir.join("20240101T140000Z_1234_dry-run.eml")
+        );
+        assert_eq!(
+            mbox_path(target_dir, false, now),
+            target_dir.join("20240101T140000Z.mbox")
+        );
+    }
+
+    #[test]
+    fn test_mbox_entry() {
+        let now = DateTime::parse_from_rfc3339("2024-01-01T14:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let message = b"Subject: Test\r\n\r\nFrom the start\r\n>From here\r\nno From\r\n";
+
+        let entry = mbox_entry("albert@einstein.com", message, now);
+        assert_eq!(
+            String::from_utf8(entry).unwrap(),
+            "From albert@einstein.com Mon Jan  1 14:00:00 2024\nSubject: Test\n\n>From the start\n>>From here\nno From\n\n"
+        );
+    }
+
+    #[test]
+    fn test_archive_mbox() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path();
+
+        let now = Utc::now();
+        let now_system_time = SystemTime::UNIX_EPOCH + Duration::from_secs(now.timestamp() as u64);
+        let email = create_email(now_system_time);
+
+        let email_formatter = EmlFormatter::new(temp_path, ArchiveFormat::Mbox, None).unwrap();
+
+        for _ in 0..2 {
+            let res = email_formatter.archive(&email, now, false);
+            assert!(res.is_ok(), "{}", res.unwrap_err());
+        }
+
+        let files = fs::read_dir(temp_path)
+            .unwrap()
+            .flatten()
+            .collect::<Vec<_>>();
+        assert_eq!(files.len(), 1);
+
+        let path = &files[0].path();
+        assert_eq!(path.extension().unwrap().to_str().unwrap(), "mbox");
+
+        let mbox = fs::read_to_string(path).unwrap();
+        assert_eq!(mbox.matches("From albert@einstein.com ").count(), 2);
+        assert_eq!(mbox.matches("Subject: Test subject\n").count(), 2);
+        assert!(!mbox.contains('\r'));
+    }
+
+    #[test]
+    fn test_archive_maildir() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path();
+
+        let now = Utc::now();
+        let now_system_time = SystemTime::UNIX_EPOCH + Duration::from_secs(now.timestamp() as u64);
+        let email = create_email(now_system_time);
+
+        let email_formatter = EmlFormatter::new(temp_path, ArchiveFormat::Maildir, None).unwrap();
+        let res = email_formatter.archive(&email, now, false);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        let res = email_formatter.archive(&email, now, true);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        for maildir in [temp_path.to_owned(), temp_path.join(DRY_RUN_FOLDER)] {
+            assert_eq!(fs::read_dir(maildir.join("tmp")).unwrap().count(), 0);
+            assert_eq!(fs::read_dir(maildir.join("new")).unwrap().count(), 0);
+
+            let files = fs::read_dir(maildir.join("cur"))
+                .unwrap()
+                .flatten()
+                .collect::<Vec<_>>();
+            assert_eq!(files.len(), 1);
+            assert!(files[0].file_name().to_str().unwrap().ends_with(":2,S"));
+
+            let eml = fs::read_to_string(files[0].path()).unwrap();
+            assert_eq!(eml, format!("{:?}", email.mime_format));
+        }
+    }
+
     #[test]
     fn test_archive_dry() {
         let temp_dir = tempdir().unwrap();
@@ -124,7 +398,7 @@ mod tests {
         let dry_run = true;
         let email = create_email(now_system_time);
 
-        let email_formatter = EmlFormatter::new(temp_path).unwrap();
+        let email_formatter = EmlFormatter::new(temp_path, ArchiveFormat::Eml, None).unwrap();
         let res = email_formatter.archive(&email, now, dry_run);
         assert!(res.is_ok(), "{}", res.unwrap_err());
 
@@ -1,2 +1,18 @@
+mod archive_compression;
+mod archive_format;
+mod archive_options;
+mod attachment_store;
+mod campaign;
 mod eml;
-pub use eml::EmlFormatter;
+mod preview;
+
+pub use archive_compression::ArchiveCompression;
+pub use archive_format::ArchiveFormat;
+pub use archive_options::ArchiveOptions;
+pub use attachment_store::{AttachmentStore, ATTACHMENT_DIR};
+pub use campaign::{
+    Campaign, CampaignArchive, IndexEntry, Source, CAMPAIGN_FILE, FAILED_DIR, FAILED_FOLDER,
+    INDEX_FILE,
+};
+pub use eml::{EmlFormatter, DRY_RUN_FOLDER};
+pub use preview::PreviewFormatter;
@@ -0,0 +1,223 @@
+use crate::{
+    email_archive::{self, Body},
+    email_builder::Email,
+};
+use anyhow::Context;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Structure to render emails as standalone HTML files for review in a
+/// browser.
+///
+/// Each preview shows a header panel with sender, receivers, subject, and
+/// attachments above the HTML message. The HTML message is embedded in an
+/// iframe, so that its styles don't interfere with the header panel. Emails
+/// without HTML message show the text message instead.
+pub struct PreviewFormatter {
+    target_dir: PathBuf,
+}
+
+impl PreviewFormatter {
+    pub fn new(target_dir: &Path) -> Result<Self, anyhow::Error> {
+        fs::create_dir_all(target_dir).context(format!(
+            "Unable to create preview directory '{}'",
+            target_dir.display()
+        ))?;
+
+        Ok(Self {
+            target_dir: target_dir.to_owned(),
+        })
+    }
+
+    /// Render the preview of the email with the given position, and return
+    /// the path of the HTML file.
+    pub fn preview(&self, email: &Email, position: usize) -> Result<PathBuf, anyhow::Error> {
+        let receivers = email
+            .receivers()
+            .iter()
+            .map(|receiver| receiver.0)
+            .collect::<Vec<_>>();
+        let file_name = format!("{:04}_{}.html", position, file_name(receivers[0]));
+        let path = self.target_dir.join(file_name);
+
+        println!("Rendering preview '{}' ...", path.display());
+
+        let html = render(email)?;
+        fs::write(&path, html).context(format!("Can't write preview '{}'", path.display()))?;
+
+        Ok(path)
+    }
+}
+
+fn render(email: &Email) -> Result<String, anyhow::Error> {
+    let receivers = email
+        .receivers()
+        .iter()
+        .map(|receiver| receiver.0)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let attachments = email_archive::parse_parts(email.mime_format.formatted())?
+        .into_iter()
+        .filter_map(|part| {
+            let size = match &part.body {
+                Body::Text(text) => text.len(),
+                Body::Binary(bytes) => bytes.len(),
+            };
+            part.file_name.map(|file_name| {
+                format!(
+                    "{} ({}, {} bytes)",
+                    escape(&file_name),
+                    escape(&part.content_type),
+                    size
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut header = vec![
+        header_row("From", &escape(email.sender.0)),
+        header_row("To", &escape(&receivers)),
+        header_row("Subject", &escape(&email.message.subject)),
+    ];
+
+    if !attachments.is_empty() {
+        header.push(header_row("Attachments", &attachments.join("<br>")));
+    }
+
+    let body = match (&email.message.html, &email.message.text) {
+        (Some(html), _) => format!(
+            r#"<iframe class="message" sandbox srcdoc="{}"></iframe>"#,
+            escape(html)
+        ),
+        (None, Some(text)) => format!(r#"<pre class="message">{}</pre>"#, escape(text)),
+        (None, None) => r#"<p class="message">No message</p>"#.to_owned(),
+    };
+
+    Ok(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ margin: 0; font-family: sans-serif; }}
+.header {{ padding: 12px 16px; background: #f0f0f0; border-bottom: 1px solid #ccc; }}
+.header th {{ padding-right: 16px; text-align: left; vertical-align: top; color: #666; }}
+.message {{ display: block; box-sizing: border-box; width: 100%; margin: 0; padding: 16px; border: none; }}
+iframe.message {{ height: calc(100vh - 140px); padding: 0; }}
+pre.message {{ white-space: pre-wrap; }}
+</style>
+</head>
+<body>
+<table class="header">
+{header}
+</table>
+{body}
+</body>
+</html>
+"#,
+        title = escape(&email.message.subject),
+        header = header.join("\n"),
+        body = body,
+    ))
+}
+
+fn header_row(name: &str, value: &str) -> String {
+    format!("<tr><th>{}</th><td>{}</td></tr>", name, value)
+}
+
+/// Escape text for HTML content and attribute values.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for char in text.chars() {
+        match char {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(char),
+        }
+    }
+
+    escaped
+}
+
+/// Replace characters which are not safe in file names.
+fn file_name(receiver: &str) -> String {
+    receiver
+        .chars()
+        .map(|char| match char {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '@' | '.' | '-' | '_' => char,
+            _ => '_',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email_builder::{Attachment, Message, MimeFormat, Receiver, Sender};
+    use std::time::SystemTime;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_preview() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().join("previews");
+
+        let sender = Sender("albert@einstein.com");
+        let receiver = Receiver("marie@curie.com");
+        let message = Message::new(
+            "Test <subject>",
+            Some("Dear Marie"),
+            Some(r#"<p class="greeting">Dear Marie</p>"#),
+        );
+        let attachment = Attachment::new(Path::new("./test_data/test.pdf")).unwrap();
+        let mime_format =
+            MimeFormat::new(sender, receiver, &message, &[attachment], SystemTime::now()).unwrap();
+        let email = Email::new(sender, receiver, &message, &mime_format).unwrap();
+
+        let preview_formatter = PreviewFormatter::new(&temp_path).unwrap();
+        let res = preview_formatter.preview(&email, 1);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let path = res.unwrap();
+        assert_eq!(path, temp_path.join("0001_marie@curie.com.html"));
+
+        let html = fs::read_to_string(path).unwrap();
+        assert!(html.contains("<title>Test &lt;subject&gt;</title>"));
+        assert!(html.contains("<tr><th>From</th><td>albert@einstein.com</td></tr>"));
+        assert!(html.contains("<tr><th>To</th><td>marie@curie.com</td></tr>"));
+        assert!(html.contains("<th>Attachments</th><td>test.pdf (application/pdf, "));
+        assert!(
+            html.contains(r#"srcdoc="&lt;p class=&quot;greeting&quot;&gt;Dear Marie&lt;/p&gt;""#)
+        );
+    }
+
+    #[test]
+    fn test_preview_text() {
+        let sender = Sender("albert@einstein.com");
+        let receiver = Receiver("marie@curie.com");
+        let message = Message::new("Test subject", Some("1 < 2"), None);
+        let mime_format =
+            MimeFormat::new(sender, receiver, &message, &[], SystemTime::now()).unwrap();
+        let email = Email::new(sender, receiver, &message, &mime_format).unwrap();
+
+        let html = render(&email).unwrap();
+        assert!(html.contains(r#"<pre class="message">1 &lt; 2</pre>"#));
+        assert!(!html.contains("Attachments"));
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("marie@curie.com"), "marie@curie.com");
+        assert_eq!(
+            file_name("../marie curie@curie.com"),
+            ".._marie_curie@curie.com"
+        );
+    }
+}
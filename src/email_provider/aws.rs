@@ -1,26 +1,40 @@
 use crate::{
-    arg,
     email_builder::Email,
-    email_transmission::{SendEmail, SentEmail, Status},
-    utils::format_green,
+    email_transmission::{SendEmail, SendQuota, SentEmail, Status},
+    utils::{format_green, format_red},
 };
 use anyhow::{Context, Result};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use bytes::Bytes;
-use clap::ArgMatches;
-use rusoto_core::{HttpClient, Region};
+use rusoto_core::{HttpClient, Region, RusotoError};
 use rusoto_credential::{EnvironmentProvider, ProvideAwsCredentials};
-use rusoto_ses::{RawMessage, SendRawEmailRequest, Ses, SesClient};
+use rusoto_ses::{
+    GetIdentityVerificationAttributesRequest, ListIdentitiesRequest, RawMessage, SendRawEmailError,
+    SendRawEmailRequest, Ses, SesClient,
+};
 use std::{env, str::FromStr};
 
+// Accounts in the SES sandbox are limited to 200 emails per 24 hours and one
+// email per second. The SES v1 api doesn't expose the sandbox status directly,
+// so it is only estimated from the sending quota.
+const SANDBOX_MAX_24_HOUR_SEND: f64 = 200.;
+const SANDBOX_MAX_SEND_RATE: f64 = 1.;
+
+// Maximum number of identities per request in `GetIdentityVerificationAttributes`.
+const MAX_IDENTITIES_PER_REQUEST: usize = 100;
+
+// Maximum size of a raw message in the SES v1 api, including attachments.
+const MAX_MESSAGE_SIZE: u64 = 10 * 1024 * 1024;
+
 pub struct AwsSesClient {
     #[allow(dead_code)]
     pub region_name: String,
     pub client: SesClient,
+    provider: EnvironmentProvider,
 }
 
 impl AwsSesClient {
-    pub fn new(matches: &ArgMatches) -> Result<Self, anyhow::Error> {
+    pub fn new() -> Result<Self, anyhow::Error> {
         let http = HttpClient::new()?;
         let provider = EnvironmentProvider::default();
 
@@ -29,14 +43,7 @@ impl AwsSesClient {
         let region = Region::from_str(&aws_region).context("Unknown aws region")?;
         let region_name = region.name().to_string();
 
-        // Check if AWS access keys are set in environment
-        if matches.get_flag(arg::DRY_RUN) {
-            AwsSesClient::get_credentials(&provider).context(
-                "Missing environment variable 'AWS_ACCESS_KEY_ID' and/or 'AWS_SECRET_ACCESS_KEY'",
-            )?;
-        }
-
-        let client = SesClient::new_with(http, provider, region);
+        let client = SesClient::new_with(http, provider.clone(), region);
 
         println!(
             "Connecting to aws server in region '{}' ... {}",
@@ -47,33 +54,242 @@ impl AwsSesClient {
         Ok(AwsSesClient {
             region_name,
             client,
+            provider,
         })
     }
 
+    /// Check if AWS access keys are set in environment.
+    pub fn check_credentials(&self) -> Result<(), anyhow::Error> {
+        Self::get_credentials(&self.provider).context(format!(
+            "Checking aws credentials ... {}: Missing environment variable 'AWS_ACCESS_KEY_ID' and/or 'AWS_SECRET_ACCESS_KEY'",
+            format_red("FAILED")
+        ))?;
+
+        println!("Checking aws credentials ... {}", format_green("ok"));
+
+        Ok(())
+    }
+
+    /// Print sending quota, sandbox status, and verified identities of the
+    /// account. If `domain` is given, only identities of this domain are
+    /// listed.
+    pub fn display_account(&self, domain: Option<&str>) -> Result<(), anyhow::Error> {
+        let sending_enabled = self.sending_enabled()?;
+        let quota = self.quota()?;
+        let identities = self.verified_identities()?;
+        let identities = filter_identities(&identities, domain);
+
+        println!(
+            "Sending enabled: {}",
+            if sending_enabled {
+                format_green("yes")
+            } else {
+                format_red("no")
+            }
+        );
+        println!("{}", quota);
+        println!(
+            "Sandbox (estimated from sending quota): {}",
+            if has_sandbox_quota(&quota) {
+                format_red("likely")
+            } else {
+                format_green("unlikely")
+            }
+        );
+
+        match domain {
+            Some(domain) => println!("Verified identities for domain '{}':", domain),
+            None => println!("Verified identities:"),
+        }
+
+        if identities.is_empty() {
+            println!("  {}", format_red("none"));
+        }
+
+        for identity in identities {
+            println!("  {}", identity);
+        }
+
+        Ok(())
+    }
+
     #[tokio::main]
     async fn get_credentials(provider: &EnvironmentProvider) -> Result<(), anyhow::Error> {
         let _credentials = provider.credentials().await?;
         Ok(())
     }
+
+    #[tokio::main]
+    async fn sending_enabled(&self) -> Result<bool, anyhow::Error> {
+        let response = self
+            .client
+            .get_account_sending_enabled()
+            .await
+            .context("Can't get account status")?;
+        Ok(response.enabled.unwrap_or_default())
+    }
+
+    #[tokio::main]
+    async fn quota(&self) -> Result<SendQuota, anyhow::Error> {
+        let response = self
+            .client
+            .get_send_quota()
+            .await
+            .context("Can't get sending quota")?;
+
+        Ok(SendQuota::new(
+            response.max_24_hour_send.unwrap_or_default(),
+            response.max_send_rate.unwrap_or_default(),
+            response.sent_last_24_hours.unwrap_or_default(),
+        ))
+    }
+
+    #[tokio::main]
+    async fn verified_identities(&self) -> Result<Vec<String>, anyhow::Error> {
+        let mut identities = vec![];
+        let mut next_token = None;
+
+        loop {
+            let request = ListIdentitiesRequest {
+                next_token,
+                ..Default::default()
+            };
+            let response = self
+                .client
+                .list_identities(request)
+                .await
+                .context("Can't list identities")?;
+            identities.extend(response.identities);
+
+            match response.next_token {
+                Some(token) => next_token = Some(token),
+                None => break,
+            }
+        }
+
+        let mut verified_identities = vec![];
+
+        for chunk in identities.chunks(MAX_IDENTITIES_PER_REQUEST) {
+            let request = GetIdentityVerificationAttributesRequest {
+                identities: chunk.to_vec(),
+            };
+            let response = self
+                .client
+                .get_identity_verification_attributes(request)
+                .await
+                .context("Can't get verification status of identities")?;
+
+            for (identity, attributes) in response.verification_attributes {
+                if attributes.verification_status == "Success" {
+                    verified_identities.push(identity);
+                }
+            }
+        }
+
+        verified_identities.sort();
+
+        Ok(verified_identities)
+    }
 }
 
-impl<'a> SendEmail<'a> for AwsSesClient {
+impl SendEmail for AwsSesClient {
     #[tokio::main]
-    async fn send(&self, email: &'a Email<'a>) -> Result<SentEmail<'a>, anyhow::Error> {
+    async fn send<'a>(&self, email: &'a Email<'a>) -> Result<SentEmail<'a>, anyhow::Error> {
         let raw_message = RawMessage {
-            data: Bytes::from(BASE64.encode(email.mime_format.message.formatted())),
+            data: Bytes::from(BASE64.encode(email.mime_format.formatted())),
+        };
+        // Receivers of batched emails are not part of the headers
+        let destinations = if email.bcc.is_empty() {
+            None
+        } else {
+            Some(
+                email
+                    .mime_format
+                    .envelope()
+                    .to()
+                    .iter()
+                    .map(|address| address.to_string())
+                    .collect(),
+            )
         };
         let request = SendRawEmailRequest {
             raw_message,
+            destinations,
             ..Default::default()
         };
         let response = self.client.send_raw_email(request).await;
         let status = match response {
             Ok(response) => Status::SentOk(response.message_id),
-            Err(err) => Status::SentError(err.to_string()),
+            Err(RusotoError::Service(SendRawEmailError::MessageRejected(err))) => {
+                Status::SentError(err)
+            }
+            Err(err) => Status::TransientError(err.to_string()),
         };
         let sent_email = SentEmail::new(email, status);
 
         Ok(sent_email)
     }
+
+    fn send_quota(&self) -> Result<Option<SendQuota>, anyhow::Error> {
+        let quota = self.quota()?;
+        Ok(Some(quota))
+    }
+
+    fn max_message_size(&self) -> Option<u64> {
+        Some(MAX_MESSAGE_SIZE)
+    }
+}
+
+/// Whether the quota equals the limits of the SES sandbox. An account which
+/// was moved out of the sandbox may still have the same limits.
+fn has_sandbox_quota(quota: &SendQuota) -> bool {
+    quota.max_24_hour_send == SANDBOX_MAX_24_HOUR_SEND
+        && quota.max_send_rate == SANDBOX_MAX_SEND_RATE
+}
+
+/// Filter identities by domain. An identity belongs to a domain if it is the
+/// domain itself or an email address of this domain.
+fn filter_identities<'a>(identities: &'a [String], domain: Option<&str>) -> Vec<&'a str> {
+    identities
+        .iter()
+        .map(|identity| identity.as_str())
+        .filter(|identity| match domain {
+            Some(domain) => {
+                let identity_domain = identity.rsplit_once('@').map_or(*identity, |(_, d)| d);
+                identity_domain.eq_ignore_ascii_case(domain)
+            }
+            None => true,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_sandbox_quota() {
+        assert!(has_sandbox_quota(&SendQuota::new(200., 1., 0.)));
+        assert!(!has_sandbox_quota(&SendQuota::new(50000., 14., 0.)));
+    }
+
+    #[test]
+    fn test_filter_identities() {
+        let identities = vec![
+            "einstein.com".to_owned(),
+            "albert@einstein.com".to_owned(),
+            "marie@curie.com".to_owned(),
+        ];
+
+        assert_eq!(
+            filter_identities(&identities, Some("einstein.com")),
+            vec!["einstein.com", "albert@einstein.com"]
+        );
+        assert_eq!(
+            filter_identities(&identities, Some("Curie.com")),
+            vec!["marie@curie.com"]
+        );
+        assert!(filter_identities(&identities, Some("noether.com")).is_empty());
+        assert_eq!(filter_identities(&identities, None).len(), 3);
+    }
 }
@@ -1,17 +1,26 @@
-use super::{MockClient, SendEmail, SentEmail, SmtpClient};
+use super::{
+    DnsResolver, ImapClient, MockClient, MxClient, SendEmail, SentEmail, SmtpClient, Status,
+    MX_PORT,
+};
 use crate::{
     arg::{self, val},
-    email_builder::Email,
+    email_builder::{Attachment, Email},
     email_provider::AwsSesClient,
+    utils::format_red,
 };
 use anyhow::anyhow;
 use clap::ArgMatches;
-use std::fmt;
+use std::{cell::Cell, fmt};
+
+/// The default number of consecutive transient failures before falling back
+/// to the next transport.
+pub const DEFAULT_FAILOVER_AFTER: usize = 3;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TransmissionType {
     Smtp,
     Aws,
+    Mx,
     Dry,
 }
 
@@ -20,6 +29,7 @@ impl fmt::Display for TransmissionType {
         let request_method = match self {
             Self::Smtp => "smtp",
             Self::Aws => "aws",
+            Self::Mx => "mx",
             Self::Dry => "dry",
         };
 
@@ -27,54 +37,303 @@ impl fmt::Display for TransmissionType {
     }
 }
 
-pub struct Client<'a> {
-    #[allow(dead_code)]
+struct Transport {
     transmission_type: TransmissionType,
-    client: Box<dyn SendEmail<'a>>,
+    client: Box<dyn SendEmail>,
+}
+
+/// Client to send emails via an ordered list of transports.
+///
+/// The first transport is used until it fails `failover_after` times in a
+/// row with a transient error. Then the client falls back to the next
+/// transport. Each email which fails with a transient error is retried via
+/// the next transports.
+///
+/// If a sent folder is configured, successfully sent emails are stored in
+/// this IMAP folder.
+pub struct Client {
+    transports: Vec<Transport>,
+    failover_after: usize,
+    current: Cell<usize>,
+    consecutive_failures: Cell<usize>,
+    sent_folder: Option<ImapClient>,
 }
 
-impl<'a> Client<'a> {
-    pub fn new(transmission_type: TransmissionType, client: Box<dyn SendEmail<'a>>) -> Self {
+impl Client {
+    pub fn new(transmission_type: TransmissionType, client: Box<dyn SendEmail>) -> Self {
+        Self::with_failover(vec![(transmission_type, client)], DEFAULT_FAILOVER_AFTER)
+    }
+
+    pub fn with_failover(
+        transports: Vec<(TransmissionType, Box<dyn SendEmail>)>,
+        failover_after: usize,
+    ) -> Self {
+        let transports = transports
+            .into_iter()
+            .map(|(transmission_type, client)| Transport {
+                transmission_type,
+                client,
+            })
+            .collect();
+
         Self {
-            transmission_type,
-            client,
+            transports,
+            failover_after,
+            current: Cell::new(0),
+            consecutive_failures: Cell::new(0),
+            sent_folder: None,
         }
     }
 
+    pub fn with_sent_folder(mut self, sent_folder: ImapClient) -> Self {
+        self.sent_folder = Some(sent_folder);
+        self
+    }
+
     pub fn from_args(matches: &ArgMatches) -> Result<Self, anyhow::Error> {
         if matches.get_flag(arg::DRY_RUN) {
             let client = MockClient;
             return Ok(Client::new(TransmissionType::Dry, Box::new(client)));
         }
 
-        let connection = arg::value(arg::CONNECTION, matches)?;
+        let connections = match matches.get_many::<String>(arg::CONNECTION) {
+            Some(connections) => connections,
+            None => return Err(anyhow!("Missing value for argument '{}'", arg::CONNECTION)),
+        };
+        let failover_after = matches
+            .get_one::<usize>(arg::FAILOVER_AFTER)
+            .copied()
+            .unwrap_or(DEFAULT_FAILOVER_AFTER);
+        let mut transports: Vec<(TransmissionType, Box<dyn SendEmail>)> = vec![];
+
+        for connection in connections {
+            let transport: (TransmissionType, Box<dyn SendEmail>) =
+                match connection.to_lowercase().as_str() {
+                    val::SMTP => {
+                        let client = SmtpClient::new()?;
+                        (TransmissionType::Smtp, Box::new(client))
+                    }
+                    val::AWS => {
+                        let client = AwsSesClient::new()?;
+                        (TransmissionType::Aws, Box::new(client))
+                    }
+                    val::MX => {
+                        let resolver = DnsResolver::new()?;
+                        let client = MxClient::new(Box::new(resolver), MX_PORT);
+                        (TransmissionType::Mx, Box::new(client))
+                    }
+                    other => {
+                        return Err(anyhow!(format!(
+                            "Value '{}' for argument '{}' not supported",
+                            other,
+                            arg::CONNECTION
+                        )))
+                    }
+                };
+            transports.push(transport);
+        }
+
+        let client = Client::with_failover(transports, failover_after);
+
+        match matches.get_one::<String>(arg::IMAP_FOLDER) {
+            Some(folder) => Ok(client.with_sent_folder(ImapClient::new(folder)?)),
+            None => Ok(client),
+        }
+    }
+
+    /// The transmission type of the primary transport.
+    pub fn transmission_type(&self) -> &TransmissionType {
+        &self.transports[0].transmission_type
+    }
+
+    /// The transmission types of all transports in order of precedence.
+    pub fn transmission_types(&self) -> Vec<TransmissionType> {
+        self.transports
+            .iter()
+            .map(|transport| transport.transmission_type)
+            .collect()
+    }
+
+    /// Send the email via the current transport. If it fails with a
+    /// transient error, the email is retried via the next transports until
+    /// it is sent or all transports have been tried.
+    pub fn send<'a>(&self, email: &'a Email<'a>) -> Result<SentEmail<'a>, anyhow::Error> {
+        let mut index = self.current.get();
+
+        loop {
+            let transport = &self.transports[index];
+            let mut sent_email = transport.client.send(email)?;
+
+            sent_email.transport = Some(transport.transmission_type);
+            sent_email.failover = self.transports.len() > 1;
+
+            let is_current = index == self.current.get();
 
-        match connection.to_lowercase().as_str() {
-            val::SMTP => {
-                let client = SmtpClient::new()?;
-                Ok(Client::new(TransmissionType::Smtp, Box::new(client)))
+            match sent_email.status {
+                Status::TransientError(_) => {
+                    if is_current {
+                        self.count_failure(index);
+                    }
+
+                    if index + 1 < self.transports.len() {
+                        sent_email.display_status();
+                        println!(
+                            "Retrying via '{}' ...",
+                            self.transports[index + 1].transmission_type
+                        );
+                        index += 1;
+                        continue;
+                    }
+                }
+                _ if is_current => self.consecutive_failures.set(0),
+                _ => (),
             }
-            val::AWS => {
-                let client = AwsSesClient::new(matches)?;
-                Ok(Client::new(TransmissionType::Aws, Box::new(client)))
+
+            if let (Status::SentOk(_), Some(sent_folder)) = (&sent_email.status, &self.sent_folder)
+            {
+                // The email was sent, so a failed upload is not an error
+                if let Err(err) = sent_folder.append(email.mime_format.formatted()) {
+                    println!("{}: {:#}", format_red("Warning"), err);
+                }
             }
-            other => Err(anyhow!(format!(
-                "Value '{}' for argument '{}' not supported",
-                other,
-                arg::CONNECTION
+
+            return Ok(sent_email);
+        }
+    }
+
+    /// Count a transient failure of the current transport, and fall back to
+    /// the next transport after `failover_after` failures in a row.
+    fn count_failure(&self, index: usize) {
+        let failures = self.consecutive_failures.get() + 1;
+        self.consecutive_failures.set(failures);
+
+        if failures >= self.failover_after && index + 1 < self.transports.len() {
+            println!(
+                "{}: {} consecutive failures via '{}'. Falling back to '{}' ...",
+                format_red("Warning"),
+                failures,
+                self.transports[index].transmission_type,
+                self.transports[index + 1].transmission_type
+            );
+            self.current.set(index + 1);
+            self.consecutive_failures.set(0);
+        }
+    }
+
+    /// Check if emails to `receiver_count` receivers fit into the remaining
+    /// sending quota of the primary transport. If `ignore_quota` is set, an
+    /// exceeded quota is reported as warning instead of an error.
+    pub fn check_quota(
+        &self,
+        receiver_count: usize,
+        ignore_quota: bool,
+    ) -> Result<(), anyhow::Error> {
+        let quota = match self.transports[0].client.send_quota()? {
+            Some(quota) => quota,
+            None => return Ok(()),
+        };
+
+        match quota.check(receiver_count) {
+            Ok(()) => Ok(()),
+            Err(err) if ignore_quota => {
+                println!("{}: {}", format_red("Warning"), err);
+                Ok(())
+            }
+            Err(err) => Err(err.context(format!(
+                "Sending quota exceeded. Use '--{}' to send anyway",
+                arg::IGNORE_QUOTA
             ))),
         }
     }
 
-    pub fn send(&self, email: &'a Email<'a>) -> Result<SentEmail<'a>, anyhow::Error> {
-        self.client.send(email)
+    /// Number of emails which can still be sent via the primary transport in
+    /// the current 24 hour interval, or `None` if the quota is unlimited.
+    pub fn remaining_quota(&self) -> Result<Option<u64>, anyhow::Error> {
+        let quota = self.transports[0].client.send_quota()?;
+        Ok(quota.and_then(|quota| quota.remaining()))
+    }
+
+    /// Check if the encoded attachments fit into the maximum message size.
+    /// Unless `max_message_size` is given, the smallest limit of all
+    /// transports is used.
+    pub fn check_message_size(
+        &self,
+        attachments: &[Attachment],
+        max_message_size: Option<u64>,
+    ) -> Result<(), anyhow::Error> {
+        let max_message_size = max_message_size.or_else(|| {
+            self.transports
+                .iter()
+                .filter_map(|transport| transport.client.max_message_size())
+                .min()
+        });
+        let encoded_len = attachments
+            .iter()
+            .map(|attachment| attachment.encoded_len() as u64)
+            .sum::<u64>();
+
+        match max_message_size {
+            Some(max_message_size) if encoded_len > max_message_size => Err(anyhow!(
+                "Attachments exceed the maximum message size: {} bytes encoded, but only {} bytes allowed. Use '--{}' to change the limit",
+                encoded_len,
+                max_message_size,
+                arg::MAX_MESSAGE_SIZE
+            )),
+            _ => Ok(()),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::app;
+    use crate::{
+        app,
+        email_builder::{Attachment, Message, MimeFormat, Receiver, Sender},
+        email_transmission::{imap, imap::tests::ImapStandIn, SendQuota},
+    };
+    use std::{path::Path, time::SystemTime};
+
+    struct QuotaClient(SendQuota);
+
+    impl SendEmail for QuotaClient {
+        fn send<'a>(&self, email: &'a Email<'a>) -> Result<SentEmail<'a>, anyhow::Error> {
+            MockClient.send(email)
+        }
+
+        fn send_quota(&self) -> Result<Option<SendQuota>, anyhow::Error> {
+            Ok(Some(self.0.clone()))
+        }
+    }
+
+    struct SizeClient(u64);
+
+    impl SendEmail for SizeClient {
+        fn send<'a>(&self, email: &'a Email<'a>) -> Result<SentEmail<'a>, anyhow::Error> {
+            MockClient.send(email)
+        }
+
+        fn max_message_size(&self) -> Option<u64> {
+            Some(self.0)
+        }
+    }
+
+    struct StatusClient(Status);
+
+    impl SendEmail for StatusClient {
+        fn send<'a>(&self, email: &'a Email<'a>) -> Result<SentEmail<'a>, anyhow::Error> {
+            Ok(SentEmail::new(email, self.0.clone()))
+        }
+    }
+
+    fn create_email<'a>() -> Email<'a> {
+        let sender = Sender("albert@einstein.com");
+        let receiver = Receiver("marie@curie.com");
+        let message = Message::new("Test subject", Some("This is a test message."), None);
+        let mime_format =
+            MimeFormat::new(sender, receiver, &message, &[], SystemTime::now()).unwrap();
+        Email::new(sender, receiver, &message, &mime_format).unwrap()
+    }
 
     #[test]
     fn test_client_from_args_dry() {
@@ -95,6 +354,244 @@ mod tests {
         assert!(res.is_ok());
 
         let client = res.unwrap();
-        assert_eq!(client.transmission_type, TransmissionType::Dry);
+        assert_eq!(client.transmission_type(), &TransmissionType::Dry);
+    }
+
+    #[test]
+    fn test_client_check_quota() {
+        let quota = SendQuota::new(200., 1., 198.);
+        let client = Client::new(TransmissionType::Aws, Box::new(QuotaClient(quota)));
+
+        assert!(client.check_quota(2, false).is_ok());
+        assert!(client.check_quota(3, false).is_err());
+        assert!(client.check_quota(3, true).is_ok());
+    }
+
+    #[test]
+    fn test_client_check_quota_dry() {
+        let client = Client::new(TransmissionType::Dry, Box::new(MockClient));
+        assert!(client.check_quota(1_000_000, false).is_ok());
+    }
+
+    #[test]
+    fn test_client_remaining_quota() {
+        let quota = SendQuota::new(200., 1., 198.);
+        let client = Client::new(TransmissionType::Aws, Box::new(QuotaClient(quota)));
+        assert_eq!(client.remaining_quota().unwrap(), Some(2));
+
+        let client = Client::new(TransmissionType::Dry, Box::new(MockClient));
+        assert_eq!(client.remaining_quota().unwrap(), None);
+    }
+
+    #[test]
+    fn test_client_check_message_size() {
+        let attachment = Attachment::new(Path::new("./test_data/test.pdf")).unwrap();
+        let encoded_len = attachment.encoded_len() as u64;
+        let client = Client::with_failover(
+            vec![
+                (TransmissionType::Smtp, Box::new(MockClient)),
+                (TransmissionType::Aws, Box::new(SizeClient(encoded_len - 1))),
+            ],
+            DEFAULT_FAILOVER_AFTER,
+        );
+
+        assert!(client
+            .check_message_size(std::slice::from_ref(&attachment), None)
+            .is_err());
+        assert!(client
+            .check_message_size(std::slice::from_ref(&attachment), Some(encoded_len))
+            .is_ok());
+
+        let client = Client::new(TransmissionType::Dry, Box::new(MockClient));
+        assert!(client
+            .check_message_size(std::slice::from_ref(&attachment), None)
+            .is_ok());
+        assert!(client
+            .check_message_size(std::slice::from_ref(&attachment), Some(1024))
+            .is_err());
+    }
+
+    #[test]
+    fn test_client_failover() {
+        let email = create_email();
+        let client = Client::with_failover(
+            vec![
+                (
+                    TransmissionType::Smtp,
+                    Box::new(StatusClient(Status::TransientError(
+                        "Connection refused".to_owned(),
+                    ))),
+                ),
+                (
+                    TransmissionType::Aws,
+                    Box::new(StatusClient(Status::SentOk("1234".to_owned()))),
+                ),
+            ],
+            2,
+        );
+
+        // Each failed email is retried via the next transport
+        let sent_email = client.send(&email).unwrap();
+        assert!(matches!(sent_email.status, Status::SentOk(_)));
+        assert_eq!(sent_email.transport, Some(TransmissionType::Aws));
+        assert_eq!(client.current.get(), 0);
+
+        // The second failure triggers the fallback
+        let sent_email = client.send(&email).unwrap();
+        assert!(matches!(sent_email.status, Status::SentOk(_)));
+        assert_eq!(sent_email.transport, Some(TransmissionType::Aws));
+        assert_eq!(client.current.get(), 1);
+
+        let sent_email = client.send(&email).unwrap();
+        assert_eq!(sent_email.transport, Some(TransmissionType::Aws));
+    }
+
+    #[test]
+    fn test_client_failover_permanent_error() {
+        let email = create_email();
+        let client = Client::with_failover(
+            vec![
+                (
+                    TransmissionType::Smtp,
+                    Box::new(StatusClient(Status::SentError(
+                        "Mailbox unavailable".to_owned(),
+                    ))),
+                ),
+                (
+                    TransmissionType::Aws,
+                    Box::new(StatusClient(Status::SentOk("1234".to_owned()))),
+                ),
+            ],
+            1,
+        );
+
+        for _ in 0..3 {
+            let sent_email = client.send(&email).unwrap();
+            assert!(matches!(sent_email.status, Status::SentError(_)));
+            assert_eq!(sent_email.transport, Some(TransmissionType::Smtp));
+        }
+    }
+
+    #[test]
+    fn test_client_failover_all_transports() {
+        let email = create_email();
+        let client = Client::with_failover(
+            vec![
+                (
+                    TransmissionType::Smtp,
+                    Box::new(StatusClient(Status::TransientError(
+                        "Connection refused".to_owned(),
+                    ))),
+                ),
+                (
+                    TransmissionType::Aws,
+                    Box::new(StatusClient(Status::TransientError(
+                        "Throttling".to_owned(),
+                    ))),
+                ),
+            ],
+            3,
+        );
+
+        let sent_email = client.send(&email).unwrap();
+        assert!(matches!(sent_email.status, Status::TransientError(_)));
+        assert_eq!(sent_email.transport, Some(TransmissionType::Aws));
+    }
+
+    #[test]
+    fn test_client_failover_last_transport() {
+        let email = create_email();
+        let client = Client::new(
+            TransmissionType::Smtp,
+            Box::new(StatusClient(Status::TransientError(
+                "Connection refused".to_owned(),
+            ))),
+        );
+
+        for _ in 0..5 {
+            let sent_email = client.send(&email).unwrap();
+            assert!(matches!(sent_email.status, Status::TransientError(_)));
+            assert_eq!(sent_email.transport, Some(TransmissionType::Smtp));
+            assert!(!sent_email.failover);
+        }
+    }
+
+    #[test]
+    fn test_client_sent_folder() {
+        let stand_in = ImapStandIn::spawn("secret");
+        let email = create_email();
+
+        let client = Client::new(
+            TransmissionType::Smtp,
+            Box::new(StatusClient(Status::SentOk("1234".to_owned()))),
+        )
+        .with_sent_folder(imap::tests::connect(stand_in.port, "secret").unwrap());
+        let sent_email = client.send(&email).unwrap();
+        assert!(matches!(sent_email.status, Status::SentOk(_)));
+
+        let client = Client::new(
+            TransmissionType::Smtp,
+            Box::new(StatusClient(Status::SentError(
+                "Mailbox unavailable".to_owned(),
+            ))),
+        )
+        .with_sent_folder(imap::tests::connect(stand_in.port, "secret").unwrap());
+        let sent_email = client.send(&email).unwrap();
+        assert!(matches!(sent_email.status, Status::SentError(_)));
+
+        let appended = stand_in.appended.lock().unwrap();
+        assert_eq!(appended.len(), 1);
+        assert_eq!(appended[0].0, "Sent");
+        assert_eq!(appended[0].2, email.mime_format.formatted());
+    }
+
+    #[test]
+    fn test_client_from_args_connections() {
+        let args = vec![
+            "pigeon",
+            "send-bulk",
+            "albert@einstein.com",
+            "--receiver-file",
+            "./test_data/receiver.csv",
+            "--message-file",
+            "./test_data/message.yaml",
+            "--connection",
+            "smtp,aws",
+            "--failover-after",
+            "5",
+        ];
+        let app = app();
+        let matches = app.get_matches_from(args);
+        let subcommand_matches = matches.subcommand_matches("send-bulk").unwrap();
+
+        let connections = subcommand_matches
+            .get_many::<String>(arg::CONNECTION)
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(connections, vec!["smtp", "aws"]);
+        assert_eq!(
+            subcommand_matches.get_one::<usize>(arg::FAILOVER_AFTER),
+            Some(&5)
+        );
+    }
+
+    #[test]
+    fn test_client_from_args_failover_after_zero() {
+        let args = vec![
+            "pigeon",
+            "send-bulk",
+            "albert@einstein.com",
+            "--receiver-file",
+            "./test_data/receiver.csv",
+            "--message-file",
+            "./test_data/message.yaml",
+            "--connection",
+            "smtp,aws",
+            "--failover-after",
+            "0",
+        ];
+        let app = app();
+        let res = app.try_get_matches_from(args);
+        assert!(res.is_err());
     }
 }
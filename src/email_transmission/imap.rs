@@ -0,0 +1,375 @@
+use crate::utils::{format_green, format_red};
+use anyhow::{anyhow, Context};
+use rustls::{ClientConfig, ClientConnection, OwnedTrustAnchor, RootCertStore, StreamOwned};
+use std::{
+    cell::{Cell, RefCell},
+    env,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    sync::Arc,
+};
+
+// Possible values for environment variable `IMAP_TLS`
+const TLS: &str = "tls";
+const NO_TLS: &str = "none";
+
+const TLS_PORT: u16 = 993;
+const NO_TLS_PORT: u16 = 143;
+
+trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+/// Client to store sent emails in a mailbox folder via IMAP.
+///
+/// The connection is opened once and reused for all emails. Emails are
+/// appended with the `\Seen` flag.
+pub struct ImapClient {
+    folder: String,
+    stream: RefCell<BufReader<Box<dyn Stream>>>,
+    tag: Cell<usize>,
+}
+
+impl ImapClient {
+    // Connection to IMAP server
+    //
+    // The connection is configured by the environment variables `IMAP_SERVER`,
+    // and the optional `IMAP_PORT`, `IMAP_TLS` (`tls` or `none`),
+    // `IMAP_USERNAME`, and `IMAP_PASSWORD`. The credentials default to
+    // `SMTP_USERNAME` and `SMTP_PASSWORD`.
+    pub fn new(folder: &str) -> Result<Self, anyhow::Error> {
+        let endpoint =
+            env::var("IMAP_SERVER").context("Missing environment variable 'IMAP_SERVER'")?;
+        let tls = env::var("IMAP_TLS")
+            .unwrap_or_else(|_| TLS.to_owned())
+            .to_lowercase();
+        let port = match env::var("IMAP_PORT") {
+            Ok(port) => port
+                .parse::<u16>()
+                .context("Invalid environment variable 'IMAP_PORT'")?,
+            Err(_) if tls == NO_TLS => NO_TLS_PORT,
+            Err(_) => TLS_PORT,
+        };
+        let username = env::var("IMAP_USERNAME")
+            .or_else(|_| env::var("SMTP_USERNAME"))
+            .context("Missing environment variable 'IMAP_USERNAME'")?;
+        let password = env::var("IMAP_PASSWORD")
+            .or_else(|_| env::var("SMTP_PASSWORD"))
+            .context("Missing environment variable 'IMAP_PASSWORD'")?;
+
+        let tcp_stream = TcpStream::connect((endpoint.as_str(), port)).context(format!(
+            "Connecting to IMAP server '{}' ... {}",
+            endpoint,
+            format_red("FAILED")
+        ))?;
+        let stream: Box<dyn Stream> = match tls.as_str() {
+            TLS => Box::new(tls_stream(&endpoint, tcp_stream)?),
+            NO_TLS => Box::new(tcp_stream),
+            other => {
+                return Err(anyhow!(
+                    "Value '{}' for environment variable 'IMAP_TLS' not supported",
+                    other
+                ))
+            }
+        };
+
+        let client = Self::connect(stream, folder, &username, &password).context(format!(
+            "Connecting to IMAP server '{}' ... {}",
+            endpoint,
+            format_red("FAILED")
+        ))?;
+
+        println!(
+            "Connecting to IMAP server '{}' ... {}",
+            endpoint,
+            format_green("ok")
+        );
+
+        Ok(client)
+    }
+
+    fn connect(
+        stream: Box<dyn Stream>,
+        folder: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<Self, anyhow::Error> {
+        let client = Self {
+            folder: folder.to_owned(),
+            stream: RefCell::new(BufReader::new(stream)),
+            tag: Cell::new(0),
+        };
+
+        let greeting = client.read_line()?;
+        if !greeting.starts_with("* OK") && !greeting.starts_with("* PREAUTH") {
+            return Err(anyhow!("Unexpected greeting: {}", greeting));
+        }
+
+        client
+            .command(&format!("LOGIN {} {}", quote(username), quote(password)))
+            .context("Can't log in to IMAP server")?;
+
+        Ok(client)
+    }
+
+    /// Append an email in EML format to the folder.
+    pub fn append(&self, message: &[u8]) -> Result<(), anyhow::Error> {
+        let tag = self.next_tag();
+        self.write(
+            format!(
+                "{} APPEND {} (\\Seen) {{{}}}\r\n",
+                tag,
+                quote(&self.folder),
+                message.len()
+            )
+            .as_bytes(),
+        )?;
+
+        // Wait for continuation request before sending the literal
+        let line = self.read_line()?;
+        if !line.starts_with('+') {
+            return Err(anyhow!(
+                "Can't append email to IMAP folder '{}': {}",
+                self.folder,
+                line
+            ));
+        }
+
+        self.write(message)?;
+        self.write(b"\r\n")?;
+        self.read_response(&tag).context(format!(
+            "Can't append email to IMAP folder '{}'",
+            self.folder
+        ))
+    }
+
+    fn command(&self, command: &str) -> Result<(), anyhow::Error> {
+        let tag = self.next_tag();
+        self.write(format!("{} {}\r\n", tag, command).as_bytes())?;
+        self.read_response(&tag)
+    }
+
+    /// Read lines until the tagged response, skipping untagged responses.
+    fn read_response(&self, tag: &str) -> Result<(), anyhow::Error> {
+        loop {
+            let line = self.read_line()?;
+
+            if let Some(response) = line
+                .strip_prefix(tag)
+                .and_then(|line| line.strip_prefix(' '))
+            {
+                return if response.starts_with("OK") {
+                    Ok(())
+                } else {
+                    Err(anyhow!("{}", response))
+                };
+            }
+        }
+    }
+
+    fn next_tag(&self) -> String {
+        let tag = self.tag.get() + 1;
+        self.tag.set(tag);
+        format!("A{:04}", tag)
+    }
+
+    fn write(&self, bytes: &[u8]) -> Result<(), anyhow::Error> {
+        let mut stream = self.stream.borrow_mut();
+        let stream = stream.get_mut();
+        stream
+            .write_all(bytes)
+            .context("Can't write to IMAP server")?;
+        stream.flush().context("Can't write to IMAP server")?;
+        Ok(())
+    }
+
+    fn read_line(&self) -> Result<String, anyhow::Error> {
+        let mut line = String::new();
+        let bytes = self
+            .stream
+            .borrow_mut()
+            .read_line(&mut line)
+            .context("Can't read from IMAP server")?;
+
+        if bytes == 0 {
+            return Err(anyhow!("Connection closed by IMAP server"));
+        }
+
+        Ok(line.trim_end_matches(['\r', '\n']).to_owned())
+    }
+}
+
+impl Drop for ImapClient {
+    fn drop(&mut self) {
+        let _ = self.command("LOGOUT");
+    }
+}
+
+fn tls_stream(
+    endpoint: &str,
+    tcp_stream: TcpStream,
+) -> Result<StreamOwned<ClientConnection, TcpStream>, anyhow::Error> {
+    let mut root_store = RootCertStore::empty();
+    root_store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    let server_name = endpoint
+        .try_into()
+        .context(format!("Invalid server name '{}'", endpoint))?;
+    let connection = ClientConnection::new(Arc::new(config), server_name)
+        .context("Can't create TLS connection")?;
+
+    Ok(StreamOwned::new(connection, tcp_stream))
+}
+
+/// Quote a string as IMAP quoted string.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::{
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    /// Appended emails as tuples of folder, flags, and message.
+    type Appended = Arc<Mutex<Vec<(String, String, Vec<u8>)>>>;
+
+    /// Minimal IMAP server which accepts `LOGIN`, `APPEND`, and `LOGOUT`, and
+    /// stores appended emails per folder.
+    pub struct ImapStandIn {
+        pub port: u16,
+        pub appended: Appended,
+    }
+
+    impl ImapStandIn {
+        pub fn spawn(password: &'static str) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let appended = Arc::new(Mutex::new(vec![]));
+            let appended_clone = appended.clone();
+
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let stream = stream.unwrap();
+                    let appended = appended_clone.clone();
+                    thread::spawn(move || handle_connection(stream, password, appended));
+                }
+            });
+
+            Self { port, appended }
+        }
+    }
+
+    fn handle_connection(stream: TcpStream, password: &str, appended: Appended) {
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        writer.write_all(b"* OK IMAP4rev1 ready\r\n").unwrap();
+
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                return;
+            }
+            let mut parts = line.trim_end().splitn(3, ' ');
+            let tag = parts.next().unwrap_or_default().to_owned();
+            let command = parts.next().unwrap_or_default().to_uppercase();
+            let arguments = parts.next().unwrap_or_default().to_owned();
+
+            let response = match command.as_str() {
+                "LOGIN" if arguments.ends_with(&quote(password)) => "OK LOGIN completed",
+                "LOGIN" => "NO [AUTHENTICATIONFAILED] Invalid credentials",
+                "APPEND" => {
+                    let (folder, rest) = arguments.split_once(' ').unwrap();
+                    let (flags, literal) = rest.rsplit_once(' ').unwrap();
+                    let size = literal
+                        .trim_start_matches('{')
+                        .trim_end_matches('}')
+                        .parse::<usize>()
+                        .unwrap();
+
+                    writer.write_all(b"+ Ready for literal data\r\n").unwrap();
+                    let mut message = vec![0; size];
+                    reader.read_exact(&mut message).unwrap();
+                    let mut crlf = String::new();
+                    reader.read_line(&mut crlf).unwrap();
+
+                    appended.lock().unwrap().push((
+                        folder.trim_matches('"').to_owned(),
+                        flags.to_owned(),
+                        message,
+                    ));
+                    writer.write_all(b"* 1 EXISTS\r\n").unwrap();
+                    "OK APPEND completed"
+                }
+                "LOGOUT" => {
+                    writer.write_all(b"* BYE\r\n").unwrap();
+                    writer
+                        .write_all(format!("{} OK LOGOUT completed\r\n", tag).as_bytes())
+                        .unwrap();
+                    return;
+                }
+                _ => "BAD Command not supported",
+            };
+
+            writer
+                .write_all(format!("{} {}\r\n", tag, response).as_bytes())
+                .unwrap();
+        }
+    }
+
+    pub fn connect(port: u16, password: &str) -> Result<ImapClient, anyhow::Error> {
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        ImapClient::connect(Box::new(stream), "Sent", "albert", password)
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("Sent"), "\"Sent\"");
+        assert_eq!(quote("pass\"word\\"), "\"pass\\\"word\\\\\"");
+    }
+
+    #[test]
+    fn test_imap_client_append() {
+        let stand_in = ImapStandIn::spawn("secret");
+
+        let res = connect(stand_in.port, "secret");
+        assert!(res.is_ok(), "{}", res.err().unwrap());
+
+        let client = res.unwrap();
+        let message = b"Subject: Test subject\r\n\r\nThis is a test message.\r\n";
+
+        let res = client.append(message);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let res = client.append(message);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let appended = stand_in.appended.lock().unwrap();
+        assert_eq!(appended.len(), 2);
+        assert_eq!(appended[0].0, "Sent");
+        assert_eq!(appended[0].1, "(\\Seen)");
+        assert_eq!(appended[0].2, message.to_vec());
+    }
+
+    #[test]
+    fn test_imap_client_login_failed() {
+        let stand_in = ImapStandIn::spawn("secret");
+
+        let res = connect(stand_in.port, "wrong");
+        assert!(res.is_err());
+        assert!(format!("{:#}", res.err().unwrap()).contains("Invalid credentials"));
+    }
+}
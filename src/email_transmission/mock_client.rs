@@ -4,8 +4,8 @@ use crate::email_builder::Email;
 #[derive(PartialEq)]
 pub struct MockClient;
 
-impl<'a> SendEmail<'a> for MockClient {
-    fn send(&self, email: &'a Email<'a>) -> Result<SentEmail<'a>, anyhow::Error> {
+impl SendEmail for MockClient {
+    fn send<'a>(&self, email: &'a Email<'a>) -> Result<SentEmail<'a>, anyhow::Error> {
         let email = SentEmail::new(email, Status::DryRun);
         Ok(email)
     }
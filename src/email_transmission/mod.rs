@@ -1,16 +1,34 @@
 mod client;
+mod imap;
 mod mock_client;
+mod mx;
+mod quota;
 mod sent_email;
 mod smtp;
+mod smtp_sink;
 mod status;
 
 use crate::email_builder::Email;
-pub use client::Client;
+pub use client::{Client, TransmissionType, DEFAULT_FAILOVER_AFTER};
+pub use imap::ImapClient;
 pub use mock_client::MockClient;
+pub use mx::{DnsResolver, MxClient, MX_PORT};
+pub use quota::SendQuota;
 pub use sent_email::SentEmail;
 pub use smtp::SmtpClient;
+pub use smtp_sink::SmtpSink;
 pub use status::Status;
 
-pub trait SendEmail<'a> {
-    fn send(&self, email: &'a Email<'a>) -> Result<SentEmail<'a>, anyhow::Error>;
+pub trait SendEmail {
+    fn send<'a>(&self, email: &'a Email<'a>) -> Result<SentEmail<'a>, anyhow::Error>;
+
+    /// The sending quota of the account, if the transport has one.
+    fn send_quota(&self) -> Result<Option<SendQuota>, anyhow::Error> {
+        Ok(None)
+    }
+
+    /// The maximum size of a message in bytes, if the transport has one.
+    fn max_message_size(&self) -> Option<u64> {
+        None
+    }
 }
@@ -0,0 +1,318 @@
+use super::{smtp::queue_id, SendEmail, SentEmail, Status};
+use crate::{
+    email_builder::{Email, MimeFormat, Receiver},
+    utils::format_green,
+};
+use anyhow::Context;
+use hickory_resolver::{error::ResolveErrorKind, Resolver};
+use lettre::{
+    transport::smtp::client::{Tls, TlsParameters},
+    SmtpTransport, Transport,
+};
+use std::{cell::RefCell, collections::HashMap};
+
+/// The default SMTP port for delivery between mail servers.
+pub const MX_PORT: u16 = 25;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MxRecord {
+    pub preference: u16,
+    pub exchange: String,
+}
+
+impl MxRecord {
+    pub fn new(preference: u16, exchange: &str) -> Self {
+        Self {
+            preference,
+            exchange: exchange.to_owned(),
+        }
+    }
+}
+
+/// Look up the mail exchangers of a domain.
+pub trait ResolveMx {
+    fn resolve_mx(&self, domain: &str) -> Result<Vec<MxRecord>, anyhow::Error>;
+}
+
+/// Resolve MX records via the DNS servers configured in the system.
+pub struct DnsResolver {
+    resolver: Resolver,
+}
+
+impl DnsResolver {
+    pub fn new() -> Result<Self, anyhow::Error> {
+        let resolver =
+            Resolver::from_system_conf().context("Can't read system DNS configuration")?;
+        Ok(Self { resolver })
+    }
+}
+
+impl ResolveMx for DnsResolver {
+    fn resolve_mx(&self, domain: &str) -> Result<Vec<MxRecord>, anyhow::Error> {
+        match self.resolver.mx_lookup(domain) {
+            Ok(lookup) => Ok(lookup
+                .iter()
+                .map(|mx| {
+                    let exchange = mx.exchange().to_utf8();
+                    MxRecord::new(mx.preference(), exchange.trim_end_matches('.'))
+                })
+                .collect()),
+            Err(err) => match err.kind() {
+                ResolveErrorKind::NoRecordsFound { .. } => Ok(vec![]),
+                _ => Err(err).context(format!("Can't resolve MX records of '{}'", domain)),
+            },
+        }
+    }
+}
+
+/// Deliver emails directly to the mail exchangers of the receiver domains
+/// without a relay.
+///
+/// Mail exchangers are tried in order of preference. Transports are cached
+/// per mail exchanger, so that the pooled connections are reused for all
+/// receivers of the same domain. STARTTLS is used if the mail exchanger
+/// supports it.
+pub struct MxClient {
+    resolver: Box<dyn ResolveMx>,
+    port: u16,
+    transports: RefCell<HashMap<String, SmtpTransport>>,
+}
+
+impl MxClient {
+    pub fn new(resolver: Box<dyn ResolveMx>, port: u16) -> Self {
+        println!(
+            "Using direct delivery to mail exchangers on port {} ... {}",
+            port,
+            format_green("ok")
+        );
+
+        Self {
+            resolver,
+            port,
+            transports: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The mail exchangers of a domain ordered by preference. If a domain has
+    /// no MX records, the domain itself is used as implicit mail exchanger
+    /// (compare RFC 5321, section 5.1).
+    fn exchanges(&self, domain: &str) -> Result<Vec<String>, anyhow::Error> {
+        let mut records = self.resolver.resolve_mx(domain)?;
+
+        if records.is_empty() {
+            return Ok(vec![domain.to_owned()]);
+        }
+
+        records.sort_by_key(|record| record.preference);
+
+        Ok(records.into_iter().map(|record| record.exchange).collect())
+    }
+
+    fn transport(&self, exchange: &str) -> Result<SmtpTransport, anyhow::Error> {
+        if let Some(transport) = self.transports.borrow().get(exchange) {
+            return Ok(transport.clone());
+        }
+
+        let tls_parameters = TlsParameters::new(exchange.to_owned())
+            .context(format!("Can't create TLS parameters for '{}'", exchange))?;
+        let transport = SmtpTransport::builder_dangerous(exchange)
+            .port(self.port)
+            .tls(Tls::Opportunistic(tls_parameters))
+            .build();
+
+        self.transports
+            .borrow_mut()
+            .insert(exchange.to_owned(), transport.clone());
+
+        Ok(transport)
+    }
+
+    fn deliver(&self, mime_format: &MimeFormat, receiver: Receiver) -> Status {
+        let domain = match receiver.domain() {
+            Ok(domain) => domain,
+            Err(err) => return Status::SentError(err.to_string()),
+        };
+        let exchanges = match self.exchanges(domain) {
+            Ok(exchanges) => exchanges,
+            Err(err) => return Status::TransientError(format!("{:#}", err)),
+        };
+        let mut errors = vec![];
+
+        for exchange in exchanges {
+            let transport = match self.transport(&exchange) {
+                Ok(transport) => transport,
+                Err(err) => {
+                    errors.push(format!("{}: {:#}", exchange, err));
+                    continue;
+                }
+            };
+
+            match transport.send_raw(mime_format.envelope(), mime_format.formatted()) {
+                Ok(response) => {
+                    return Status::SentOk(queue_id(&response.message().collect::<Vec<_>>()));
+                }
+                // The receiver is rejected, other mail exchangers won't accept it either
+                Err(err) if err.is_permanent() => {
+                    return Status::SentError(format!("{}: {}", exchange, err))
+                }
+                Err(err) => errors.push(format!("{}: {}", exchange, err)),
+            }
+        }
+
+        Status::TransientError(format!(
+            "Can't deliver email to mail exchangers of domain '{}' ({})",
+            domain,
+            errors.join("; ")
+        ))
+    }
+}
+
+impl SendEmail for MxClient {
+    fn send<'a>(&self, email: &'a Email<'a>) -> Result<SentEmail<'a>, anyhow::Error> {
+        let status = if email.bcc.is_empty() {
+            self.deliver(&email.mime_format, email.receiver)
+        } else {
+            Status::SentError("Batched emails are not supported for direct delivery".to_owned())
+        };
+        let sent_email = SentEmail::new(email, status);
+
+        Ok(sent_email)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        email_builder::{Message, MimeFormat, Sender},
+        email_transmission::SmtpSink,
+    };
+    use std::{fs, net::TcpListener, thread, time::SystemTime};
+    use tempfile::tempdir;
+
+    /// Resolve MX records from a fixed table instead of DNS.
+    #[derive(Default)]
+    struct StaticResolver {
+        records: HashMap<String, Vec<MxRecord>>,
+    }
+
+    impl StaticResolver {
+        fn new() -> Self {
+            Self::default()
+        }
+
+        fn insert(&mut self, domain: &str, records: Vec<MxRecord>) {
+            self.records.insert(domain.to_lowercase(), records);
+        }
+    }
+
+    impl ResolveMx for StaticResolver {
+        fn resolve_mx(&self, domain: &str) -> Result<Vec<MxRecord>, anyhow::Error> {
+            Ok(self
+                .records
+                .get(&domain.to_lowercase())
+                .cloned()
+                .unwrap_or_default())
+        }
+    }
+
+    fn create_email(receiver: &str) -> Email {
+        let sender = Sender("albert@einstein.com");
+        let receiver = Receiver(receiver);
+        let message = Message::new("Test subject", Some("This is a test message."), None);
+        let mime_format =
+            MimeFormat::new(sender, receiver, &message, &[], SystemTime::now()).unwrap();
+        Email::new(sender, receiver, &message, &mime_format).unwrap()
+    }
+
+    /// A port on 127.0.0.1 which refuses connections.
+    fn closed_port() -> u16 {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    #[test]
+    fn test_exchanges() {
+        let mut resolver = StaticResolver::new();
+        resolver.insert(
+            "curie.com",
+            vec![
+                MxRecord::new(20, "mx2.curie.com"),
+                MxRecord::new(10, "mx1.curie.com"),
+            ],
+        );
+        let client = MxClient::new(Box::new(resolver), MX_PORT);
+
+        assert_eq!(
+            client.exchanges("curie.com").unwrap(),
+            vec!["mx1.curie.com", "mx2.curie.com"]
+        );
+        assert_eq!(
+            client.exchanges("noether.com").unwrap(),
+            vec!["noether.com"]
+        );
+    }
+
+    #[test]
+    fn test_mx_client_send() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path();
+
+        let sink = SmtpSink::bind(0, temp_path).unwrap();
+        let port = sink.local_addr().unwrap().port();
+        thread::spawn(move || sink.run());
+
+        let mut resolver = StaticResolver::new();
+        resolver.insert(
+            "curie.com",
+            vec![
+                MxRecord::new(10, "mx1.curie.com"),
+                MxRecord::new(20, "127.0.0.1"),
+            ],
+        );
+        resolver.insert("noether.com", vec![MxRecord::new(10, "127.0.0.1")]);
+        let client = MxClient::new(Box::new(resolver), port);
+
+        // The first mail exchanger refuses connections
+        client.transports.borrow_mut().insert(
+            "mx1.curie.com".to_owned(),
+            SmtpTransport::builder_dangerous("127.0.0.1")
+                .port(closed_port())
+                .build(),
+        );
+
+        let emails = [
+            create_email("marie@curie.com"),
+            create_email("pierre@curie.com"),
+            create_email("emmy@noether.com"),
+        ];
+
+        for email in &emails {
+            let sent_email = client.send(email).unwrap();
+            let Status::SentOk(queue_id) = &sent_email.status else {
+                panic!("{}", sent_email.status);
+            };
+
+            // The queue id of the mail exchanger, not the Message-ID
+            assert!(!queue_id.is_empty());
+            assert!(fs::exists(temp_path.join(format!("{}.eml", queue_id))).unwrap());
+        }
+
+        assert_eq!(fs::read_dir(temp_path).unwrap().count(), 3);
+        assert_eq!(client.transports.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_mx_client_send_failed() {
+        let mut resolver = StaticResolver::new();
+        resolver.insert("curie.com", vec![MxRecord::new(10, "127.0.0.1")]);
+        let client = MxClient::new(Box::new(resolver), closed_port());
+        let email = create_email("marie@curie.com");
+
+        let sent_email = client.send(&email).unwrap();
+        match sent_email.status {
+            Status::TransientError(err) => assert!(err.contains("curie.com"), "{}", err),
+            status => panic!("Unexpected status: {}", status),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::anyhow;
+use std::fmt;
+
+/// Sending limits of an email provider account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SendQuota {
+    /// Maximum number of emails per 24 hours; a negative value signifies an
+    /// unlimited quota.
+    pub max_24_hour_send: f64,
+    /// Maximum number of emails per second.
+    pub max_send_rate: f64,
+    /// Number of emails sent during the previous 24 hours.
+    pub sent_last_24_hours: f64,
+}
+
+impl SendQuota {
+    pub fn new(max_24_hour_send: f64, max_send_rate: f64, sent_last_24_hours: f64) -> Self {
+        Self {
+            max_24_hour_send,
+            max_send_rate,
+            sent_last_24_hours,
+        }
+    }
+
+    /// Number of emails which can still be sent in the current 24 hour
+    /// interval, or `None` if the quota is unlimited.
+    pub fn remaining(&self) -> Option<u64> {
+        if self.max_24_hour_send < 0. {
+            return None;
+        }
+
+        let remaining = (self.max_24_hour_send - self.sent_last_24_hours).max(0.);
+        Some(remaining as u64)
+    }
+
+    /// Check if emails to `receiver_count` receivers fit into the remaining
+    /// quota.
+    pub fn check(&self, receiver_count: usize) -> Result<(), anyhow::Error> {
+        match self.remaining() {
+            Some(remaining) if receiver_count as u64 > remaining => Err(anyhow!(
+                "Sending to {} receivers exceeds remaining quota of {} emails (max 24 hour send: {}, sent last 24 hours: {})",
+                receiver_count,
+                remaining,
+                self.max_24_hour_send,
+                self.sent_last_24_hours
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for SendQuota {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let remaining = match self.remaining() {
+            Some(remaining) => remaining.to_string(),
+            None => "unlimited".to_owned(),
+        };
+        let max_24_hour_send = if self.max_24_hour_send < 0. {
+            "unlimited".to_owned()
+        } else {
+            self.max_24_hour_send.to_string()
+        };
+
+        writeln!(f, "Max 24 hour send: {}", max_24_hour_send)?;
+        writeln!(f, "Max send rate: {} emails/second", self.max_send_rate)?;
+        writeln!(f, "Sent last 24 hours: {}", self.sent_last_24_hours)?;
+        write!(f, "Remaining quota: {}", remaining)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remaining() {
+        let quota = SendQuota::new(200., 1., 150.);
+        assert_eq!(quota.remaining(), Some(50));
+
+        let quota = SendQuota::new(200., 1., 250.);
+        assert_eq!(quota.remaining(), Some(0));
+
+        let quota = SendQuota::new(-1., 14., 150.);
+        assert_eq!(quota.remaining(), None);
+    }
+
+    #[test]
+    fn test_check() {
+        let quota = SendQuota::new(200., 1., 150.);
+        assert!(quota.check(50).is_ok());
+
+        let res = quota.check(51);
+        assert!(res.is_err());
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("exceeds remaining quota of 50 emails"));
+
+        let quota = SendQuota::new(-1., 14., 150.);
+        assert!(quota.check(1_000_000).is_ok());
+    }
+}
@@ -1,4 +1,4 @@
-use super::Status;
+use super::{Status, TransmissionType};
 use crate::email_builder::{Email, Message, Receiver, Sender};
 
 #[derive(Debug)]
@@ -6,9 +6,15 @@ pub struct SentEmail<'a> {
     #[allow(dead_code)]
     pub sender: Sender<'a>,
     pub receiver: Receiver<'a>,
+    pub bcc: Vec<Receiver<'a>>,
     #[allow(dead_code)]
     pub message: &'a Message,
     pub status: Status,
+    /// The transport the email was sent via.
+    pub transport: Option<TransmissionType>,
+    /// Whether fallback transports are configured, in which case the
+    /// transport is displayed.
+    pub failover: bool,
 }
 
 impl<'a> SentEmail<'a> {
@@ -16,12 +22,26 @@ impl<'a> SentEmail<'a> {
         Self {
             sender: email.sender,
             receiver: email.receiver,
+            bcc: email.bcc.clone(),
             message: &email.message,
             status,
+            transport: None,
+            failover: false,
         }
     }
 
     pub fn display_status(&self) {
-        println!("{} ... {}", self.receiver.0, self.status);
+        let transport = match self.transport {
+            Some(transport) if self.failover => format!(" (via {})", transport),
+            _ => String::new(),
+        };
+
+        if self.bcc.is_empty() {
+            println!("{} ... {}{}", self.receiver.0, self.status, transport);
+        } else {
+            for receiver in &self.bcc {
+                println!("{} ... {}{}", receiver.0, self.status, transport);
+            }
+        }
     }
 }
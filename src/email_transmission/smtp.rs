@@ -3,10 +3,15 @@ use crate::{
     email_builder::Email,
     utils::{format_green, format_red},
 };
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use lettre::{transport::smtp::authentication::Credentials, SmtpTransport, Transport};
 use std::env;
 
+// Possible values for environment variable `SMTP_TLS`
+const TLS: &str = "tls";
+const STARTTLS: &str = "starttls";
+const NO_TLS: &str = "none";
+
 pub struct SmtpClient {
     #[allow(dead_code)]
     pub endpoint: String,
@@ -15,23 +20,54 @@ pub struct SmtpClient {
 
 impl SmtpClient {
     // TLS connection to SMTP server
+    //
+    // The connection is configured by the environment variables `SMTP_SERVER`,
+    // `SMTP_USERNAME`, `SMTP_PASSWORD`, and the optional `SMTP_PORT` and
+    // `SMTP_TLS` (`tls`, `starttls`, or `none`). Credentials are optional for
+    // unencrypted connections.
     pub fn new() -> Result<Self, anyhow::Error> {
         let endpoint =
             env::var("SMTP_SERVER").context("Missing environment variable 'SMTP_SERVER'")?;
-        let username =
-            env::var("SMTP_USERNAME").context("Missing environment variable 'SMTP_USERNAME'")?;
-        let password =
-            env::var("SMTP_PASSWORD").context("Missing environment variable 'SMTP_PASSWORD'")?;
-        let credentials = Credentials::new(username, password);
+        let port = match env::var("SMTP_PORT") {
+            Ok(port) => Some(
+                port.parse::<u16>()
+                    .context("Invalid environment variable 'SMTP_PORT'")?,
+            ),
+            Err(_) => None,
+        };
+        let tls = env::var("SMTP_TLS")
+            .unwrap_or_else(|_| TLS.to_owned())
+            .to_lowercase();
 
-        let transport = SmtpTransport::relay(endpoint.as_str())
-            .context(format!(
-                "Connecting to SMTP server '{}' ... {}",
-                endpoint,
-                format_red("FAILED")
-            ))?
-            .credentials(credentials)
-            .build();
+        let builder = match tls.as_str() {
+            TLS => SmtpTransport::relay(endpoint.as_str()),
+            STARTTLS => SmtpTransport::starttls_relay(endpoint.as_str()),
+            NO_TLS => Ok(SmtpTransport::builder_dangerous(endpoint.as_str())),
+            other => {
+                return Err(anyhow!(
+                    "Value '{}' for environment variable 'SMTP_TLS' not supported",
+                    other
+                ))
+            }
+        }
+        .context(format!(
+            "Connecting to SMTP server '{}' ... {}",
+            endpoint,
+            format_red("FAILED")
+        ))?;
+        let builder = match port {
+            Some(port) => builder.port(port),
+            None => builder,
+        };
+        let builder = match (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+            (Ok(username), Ok(password)) => {
+                builder.credentials(Credentials::new(username, password))
+            }
+            _ if tls == NO_TLS => builder,
+            (Err(_), _) => return Err(anyhow!("Missing environment variable 'SMTP_USERNAME'")),
+            (_, Err(_)) => return Err(anyhow!("Missing environment variable 'SMTP_PASSWORD'")),
+        };
+        let transport = builder.build();
 
         println!(
             "Connecting to SMTP server '{}' ... {}",
@@ -46,23 +82,62 @@ impl SmtpClient {
     }
 }
 
-impl<'a> SendEmail<'a> for SmtpClient {
-    fn send(&self, email: &'a Email<'a>) -> Result<SentEmail<'a>, anyhow::Error> {
+impl SendEmail for SmtpClient {
+    fn send<'a>(&self, email: &'a Email<'a>) -> Result<SentEmail<'a>, anyhow::Error> {
         let response = self
             .transport
-            .send(&email.mime_format.message)
-            .context("Can't sent email via SMTP");
+            .send_raw(email.mime_format.envelope(), email.mime_format.formatted());
         let status = match response {
-            Ok(response) => {
-                let response_string = response.message().collect::<String>();
-                let messages: Vec<&str> = response_string.split(' ').collect();
-                let message_id = messages[1];
-                Status::SentOk(message_id.to_string())
+            Ok(response) => Status::SentOk(queue_id(&response.message().collect::<Vec<_>>())),
+            Err(err) if err.is_permanent() => {
+                Status::SentError(format!("Can't send email via SMTP: {}", err))
             }
-            Err(err) => Status::SentError(err.to_string()),
+            Err(err) => Status::TransientError(format!("Can't send email via SMTP: {}", err)),
         };
         let sent_email = SentEmail::new(email, status);
 
         Ok(sent_email)
     }
 }
+
+/// The queue id of the SMTP server from the lines of its reply to the sent
+/// message, e.g. `4F1A2B3C` for `2.0.0 Ok: queued as 4F1A2B3C`. If the reply
+/// has no queue id, the whole reply is returned.
+pub fn queue_id(reply: &[&str]) -> String {
+    let reply = reply.join(" ");
+    // Lowercasing non-ASCII characters may change their length, which would
+    // shift the index into the reply
+    let queue_id = reply
+        .to_ascii_lowercase()
+        .find("queued as ")
+        .and_then(|index| {
+            reply[index + "queued as ".len()..]
+                .split_whitespace()
+                .next()
+        })
+        .map(|queue_id| queue_id.trim_end_matches(['.', ',', ';', ')']));
+
+    match queue_id {
+        Some(queue_id) if !queue_id.is_empty() => queue_id.to_owned(),
+        _ => reply.trim().to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_id() {
+        assert_eq!(queue_id(&["2.0.0 Ok: queued as 4F1A2B3C"]), "4F1A2B3C");
+        assert_eq!(queue_id(&["Ok: queued as 4F1A2B3C."]), "4F1A2B3C");
+        assert_eq!(
+            queue_id(&["2.0.0 OK  1718012345 a1b2c3d4e5f6 - gsmtp"]),
+            "2.0.0 OK  1718012345 a1b2c3d4e5f6 - gsmtp"
+        );
+        assert_eq!(queue_id(&["Ok"]), "Ok");
+        assert_eq!(queue_id(&[]), "");
+        assert_eq!(queue_id(&["2.0.0 İİİ Ok: queued as 4F1A2B3C"]), "4F1A2B3C");
+        assert_eq!(queue_id(&["Grüße, Ok: QUEUED AS 4F1A2B3C"]), "4F1A2B3C");
+    }
+}
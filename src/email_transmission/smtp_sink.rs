@@ -0,0 +1,291 @@
+use anyhow::{anyhow, Context};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    thread,
+};
+use uuid::Uuid;
+
+/// Minimal SMTP server which accepts every email and stores it in EML format
+/// instead of delivering it.
+///
+/// Authentication is accepted for any credentials, STARTTLS is not supported.
+pub struct SmtpSink {
+    listener: TcpListener,
+    target_dir: PathBuf,
+}
+
+impl SmtpSink {
+    pub fn bind(port: u16, target_dir: &Path) -> Result<Self, anyhow::Error> {
+        if !target_dir.exists() {
+            fs::create_dir_all(target_dir)
+                .context("Unable to create directory for caught emails")?;
+        }
+
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .context(format!("Can't bind SMTP sink to port {}", port))?;
+
+        Ok(Self {
+            listener,
+            target_dir: target_dir.to_owned(),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, anyhow::Error> {
+        self.listener
+            .local_addr()
+            .context("Can't get address of SMTP sink")
+    }
+
+    /// Accept connections until the process is terminated. Each connection is
+    /// handled in a separate thread.
+    pub fn run(&self) -> Result<(), anyhow::Error> {
+        for stream in self.listener.incoming() {
+            let stream = stream.context("Can't accept connection")?;
+            let target_dir = self.target_dir.clone();
+
+            thread::spawn(move || {
+                if let Err(err) = handle_connection(stream, &target_dir) {
+                    println!("Connection closed: {:#}", err);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+struct Envelope {
+    from: Option<String>,
+    to: Vec<String>,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Self {
+            from: None,
+            to: vec![],
+        }
+    }
+}
+
+fn handle_connection(stream: TcpStream, target_dir: &Path) -> Result<(), anyhow::Error> {
+    let mut writer = stream.try_clone().context("Can't clone stream")?;
+    let mut reader = BufReader::new(stream);
+    let mut envelope = Envelope::new();
+
+    reply(&mut writer, "220 localhost pigeon smtp sink ready")?;
+
+    loop {
+        let line = match read_line(&mut reader)? {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+        let (command, argument) = match line.split_once(' ') {
+            Some((command, argument)) => (command.to_uppercase(), argument.trim()),
+            None => (line.to_uppercase(), ""),
+        };
+
+        match command.as_str() {
+            "EHLO" => reply(
+                &mut writer,
+                "250-localhost\r\n250-AUTH PLAIN LOGIN\r\n250-8BITMIME\r\n250 SMTPUTF8",
+            )?,
+            "HELO" => reply(&mut writer, "250 localhost")?,
+            "AUTH" => {
+                let mechanism = argument.split(' ').next().unwrap_or_default();
+
+                match mechanism.to_uppercase().as_str() {
+                    "PLAIN" => {
+                        // Initial response is missing if the client waits for an empty challenge
+                        if !argument.contains(' ') {
+                            reply(&mut writer, "334 ")?;
+                            read_line(&mut reader)?;
+                        }
+                    }
+                    "LOGIN" => {
+                        reply(&mut writer, "334 VXNlcm5hbWU6")?;
+                        read_line(&mut reader)?;
+                        reply(&mut writer, "334 UGFzc3dvcmQ6")?;
+                        read_line(&mut reader)?;
+                    }
+                    _ => {
+                        reply(&mut writer, "504 Unrecognized authentication type")?;
+                        continue;
+                    }
+                }
+
+                reply(&mut writer, "235 Authentication successful")?;
+            }
+            "MAIL" => {
+                envelope = Envelope::new();
+                envelope.from = Some(address(argument));
+                reply(&mut writer, "250 Ok")?;
+            }
+            "RCPT" => {
+                envelope.to.push(address(argument));
+                reply(&mut writer, "250 Ok")?;
+            }
+            "DATA" => {
+                if envelope.from.is_none() || envelope.to.is_empty() {
+                    reply(&mut writer, "503 Bad sequence of commands")?;
+                    continue;
+                }
+
+                reply(&mut writer, "354 End data with <CR><LF>.<CR><LF>")?;
+                let data = read_data(&mut reader)?;
+                let message_id = Uuid::new_v4();
+                let path = target_dir.join(format!("{}.eml", message_id));
+
+                fs::write(&path, data).context("Can't save caught email")?;
+                println!(
+                    "Caught email from '{}' to '{}': {}",
+                    envelope.from.as_deref().unwrap_or_default(),
+                    envelope.to.join(", "),
+                    path.display()
+                );
+
+                envelope = Envelope::new();
+                reply(&mut writer, &format!("250 Ok: queued as {}", message_id))?;
+            }
+            "RSET" => {
+                envelope = Envelope::new();
+                reply(&mut writer, "250 Ok")?;
+            }
+            "NOOP" => reply(&mut writer, "250 Ok")?,
+            "QUIT" => {
+                reply(&mut writer, "221 Bye")?;
+                return Ok(());
+            }
+            _ => reply(&mut writer, "502 Command not implemented")?,
+        }
+    }
+}
+
+fn reply(writer: &mut impl Write, response: &str) -> Result<(), anyhow::Error> {
+    writer
+        .write_all(format!("{}\r\n", response).as_bytes())
+        .context("Can't write response")?;
+    writer.flush().context("Can't write response")?;
+    Ok(())
+}
+
+/// Read a single line without line ending. Returns `None` if the client
+/// closed the connection.
+fn read_line(reader: &mut impl BufRead) -> Result<Option<String>, anyhow::Error> {
+    let mut line = String::new();
+    let bytes = reader.read_line(&mut line).context("Can't read command")?;
+
+    if bytes == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_owned()))
+}
+
+/// Read the message content until the terminating `.` line and remove the
+/// dot-stuffing of lines starting with a dot.
+fn read_data(reader: &mut impl BufRead) -> Result<Vec<u8>, anyhow::Error> {
+    let mut data = vec![];
+
+    loop {
+        let mut line = vec![];
+        let bytes = reader
+            .read_until(b'\n', &mut line)
+            .context("Can't read data")?;
+
+        if bytes == 0 {
+            return Err(anyhow!("Connection closed before end of data"));
+        }
+
+        if line == b".\r\n" || line == b".\n" {
+            return Ok(data);
+        }
+
+        if line.starts_with(b".") {
+            data.extend_from_slice(&line[1..]);
+        } else {
+            data.extend_from_slice(&line);
+        }
+    }
+}
+
+/// Extract the address of a `MAIL FROM:<...>` or `RCPT TO:<...>` argument.
+fn address(argument: &str) -> String {
+    let argument = argument.split_once(':').map_or(argument, |(_, addr)| addr);
+
+    match (argument.find('<'), argument.find('>')) {
+        (Some(start), Some(end)) if start < end => argument[start + 1..end].to_owned(),
+        _ => argument.trim().to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lettre::{transport::smtp::authentication::Credentials, SmtpTransport, Transport};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_address() {
+        assert_eq!(address("FROM:<albert@einstein.com>"), "albert@einstein.com");
+        assert_eq!(
+            address("TO:<marie@curie.com> NOTIFY=NEVER"),
+            "marie@curie.com"
+        );
+        assert_eq!(address("FROM:<>"), "");
+    }
+
+    #[test]
+    fn test_read_data() {
+        let data = "Subject: Test\r\n\r\n..leading dot\r\nlast line\r\n.\r\nQUIT\r\n";
+        let mut reader = BufReader::new(data.as_bytes());
+
+        let res = read_data(&mut reader);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        assert_eq!(
+            res.unwrap(),
+            b"Subject: Test\r\n\r\n.leading dot\r\nlast line\r\n"
+        );
+    }
+
+    #[test]
+    fn test_smtp_sink() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().join("caught");
+
+        let sink = SmtpSink::bind(0, &temp_path).unwrap();
+        let port = sink.local_addr().unwrap().port();
+        thread::spawn(move || sink.run());
+
+        let message = lettre::Message::builder()
+            .from("albert@einstein.com".parse().unwrap())
+            .to("marie@curie.com".parse().unwrap())
+            .subject("Test subject")
+            .body(".This is a test message (plaintext).".to_owned())
+            .unwrap();
+        let transport = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(port)
+            .credentials(Credentials::new("user".to_owned(), "password".to_owned()))
+            .build();
+
+        let res = transport.send(&message);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+
+        let response = res.unwrap();
+        let message_id = response.message().collect::<String>();
+        assert!(message_id.starts_with("Ok: queued as "));
+
+        let files = fs::read_dir(&temp_path)
+            .unwrap()
+            .flatten()
+            .collect::<Vec<_>>();
+        assert_eq!(files.len(), 1);
+
+        let eml = fs::read_to_string(files[0].path()).unwrap();
+        assert!(eml.contains("Subject: Test subject"));
+        assert!(eml.ends_with(".This is a test message (plaintext).\r\n"));
+    }
+}
@@ -5,7 +5,10 @@ use std::fmt;
 pub enum Status {
     DryRun,
     SentOk(String),
+    /// The email was rejected, e.g. because of an invalid receiver.
     SentError(String),
+    /// The transport failed, e.g. because the server is not reachable.
+    TransientError(String),
 }
 
 impl fmt::Display for Status {
@@ -14,6 +17,7 @@ impl fmt::Display for Status {
             Status::DryRun => write!(f, "{}", format_green("dry run")),
             Status::SentOk(message_id) => write!(f, "{} {}", format_green("ok"), message_id),
             Status::SentError(err) => write!(f, "{} {}", format_red("FAILED"), err),
+            Status::TransientError(err) => write!(f, "{} {}", format_red("FAILED"), err),
         }
     }
 }
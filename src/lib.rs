@@ -6,7 +6,9 @@
 pub mod arg;
 /// The supported subcommands.
 pub mod cmd;
+mod email_archive;
 mod email_builder;
+mod email_feedback;
 mod email_formatter;
 mod email_provider;
 mod email_transmission;
@@ -14,7 +16,8 @@ mod sources;
 mod utils;
 
 use arg::val;
-use clap::{crate_name, crate_version, Arg, Command};
+use clap::{builder::RangedU64ValueParser, crate_name, crate_version, Arg, Command};
+use email_transmission::DEFAULT_FAILOVER_AFTER;
 
 /// Create the CLI app to get the matches.
 pub fn app() -> Command {
@@ -41,6 +44,11 @@ pub fn app() -> Command {
                         .value_parser([val::SMTP, val::AWS])
                         .default_value(val::SMTP)
                         .help("Check connection to SMTP server."),
+                    Arg::new(arg::SENDER)
+                        .long(arg::SENDER)
+                        .num_args(1)
+                        .required(false)
+                        .help("Email address or domain of the sender to check verified identities for (aws only)"),
                     verbose(),
                 ]),
         )
@@ -179,16 +187,23 @@ pub fn app() -> Command {
                         .help("Path of attachment"),
                     archive(),
                     archive_dir(),
+                    archive_format(),
+                    archive_compression(),
+                    dedup_attachments(),
                     display().help("Display email in terminal"),
+                    preview_dir().help("Render email as HTML file in the given directory instead of sending it"),
                     dry_run().help("Prepare email but do not send email"),
                     assume_yes().help("Send email without confirmation"),
-                    Arg::new(arg::CONNECTION)
-                        .long(arg::CONNECTION)
-                        .num_args(1)
-                        .required(false)
-                        .value_parser([val::SMTP, val::AWS])
-                        .default_value(val::SMTP)
-                        .help("Send emails via SMTP or AWS API"),
+                    connection(),
+                    failover_after(),
+                    imap_folder(),
+                    max_message_size(),
+                    smime_cert(),
+                    smime_key(),
+                    smime_pkcs12(),
+                    smime_cert_dir().help("Encrypts the email to the certificate '<email>.pem' or '<email>.crt' of the receiver in the given directory (S/MIME)"),
+                    pgp_key(),
+                    pgp_key_dir().help("Encrypts the email to the public key '<email>.asc' or '<email>.gpg' of the receiver in the given directory (PGP/MIME)"),
                     verbose(),
                 ]),
         )
@@ -266,6 +281,9 @@ pub fn app() -> Command {
                         .help("Path of attachment"),
                     archive(),
                     archive_dir(),
+                    archive_format(),
+                    archive_compression(),
+                    dedup_attachments(),
                     Arg::new(arg::RECEIVER_COLUMN)
                         .long(arg::RECEIVER_COLUMN)
                         .num_args(1)
@@ -277,7 +295,130 @@ pub fn app() -> Command {
                         .num_args(0..100)
                         .required(false)
                         .help("Personalizes email for variables defined in the message template"),
+                    Arg::new(arg::SENDER_COLUMN)
+                        .long(arg::SENDER_COLUMN)
+                        .value_name("column")
+                        .num_args(1)
+                        .required(false)
+                        .help("Sends each email from the address in the receiver column instead of the sender, if the row has a value"),
+                    Arg::new(arg::SUBJECT_COLUMN)
+                        .long(arg::SUBJECT_COLUMN)
+                        .value_name("column")
+                        .num_args(1)
+                        .required(false)
+                        .help("Uses the subject in the receiver column instead of the subject of the message, if the row has a value"),
+                    Arg::new(arg::TEMPLATE_COLUMN)
+                        .long(arg::TEMPLATE_COLUMN)
+                        .value_name("column")
+                        .num_args(1)
+                        .required(false)
+                        .help("Uses the message file whose path is given by the receiver column instead of the message, if the row has a value"),
+                    Arg::new(arg::LOCALE_COLUMN)
+                        .long(arg::LOCALE_COLUMN)
+                        .value_name("column")
+                        .num_args(1)
+                        .required(false)
+                        .help("Uses the variant of the message file for the locale in the receiver column, e.g. 'message.de.yaml' for 'message.yaml', and formats numbers and dates for this locale"),
+                    Arg::new(arg::DEFAULT_LOCALE)
+                        .long(arg::DEFAULT_LOCALE)
+                        .value_name("locale")
+                        .num_args(1)
+                        .required(false)
+                        .requires(arg::LOCALE_COLUMN)
+                        .help("Uses the variant of the message file for the given locale if the receiver has no locale, or no variant exists for it"),
+                    Arg::new(arg::SPLIT)
+                        .long(arg::SPLIT)
+                        .value_name("shares")
+                        .num_args(1)
+                        .required(false)
+                        .conflicts_with_all([arg::TEMPLATE_COLUMN, arg::LOCALE_COLUMN])
+                        .help("Splits the receivers into the variants of the message in percent, e.g. '50/50', or '10/10' to hold out the remaining 80 percent (A/B testing)"),
+                    Arg::new(arg::SPLIT_SEED)
+                        .long(arg::SPLIT_SEED)
+                        .num_args(1)
+                        .required(false)
+                        .requires(arg::SPLIT)
+                        .help("Assigns the receivers differently for another seed; the same split and seed always assign the same receivers"),
+                    Arg::new(arg::VARIANT)
+                        .long(arg::VARIANT)
+                        .value_name("name")
+                        .num_args(1)
+                        .required(false)
+                        .requires(arg::SPLIT)
+                        .help("Sends the given variant, e.g. the winner of the A/B test, to the receivers held out by the split"),
+                    Arg::new(arg::BATCH_SIZE)
+                        .long(arg::BATCH_SIZE)
+                        .num_args(1)
+                        .required(false)
+                        .conflicts_with_all([
+                            arg::SPLIT,
+                            arg::PERSONALIZE,
+                            arg::SENDER_COLUMN,
+                            arg::SUBJECT_COLUMN,
+                            arg::TEMPLATE_COLUMN,
+                            arg::LOCALE_COLUMN,
+                        ])
+                        .help("Sends identical emails to batches of receivers in a single transaction (receivers are not visible to each other)"),
+                    Arg::new(arg::STREAM)
+                        .long(arg::STREAM)
+                        .num_args(0)
+                        .required(false)
+                        .conflicts_with_all([arg::BATCH_SIZE, arg::PREVIEW_DIR, arg::TEST_TO])
+                        .help("Reads the receivers row by row and sends each email right after it is built, so that memory usage doesn't depend on the number of receivers unless duplicates are detected via --duplicates"),
+                    suppression_file().help("Skip receivers in the suppression list"),
+                    Arg::new(arg::DUPLICATES)
+                        .long(arg::DUPLICATES)
+                        .value_name("policy")
+                        .num_args(1)
+                        .required(false)
+                        .value_parser([val::FIRST, val::LAST, val::FAIL])
+                        .default_value(val::FIRST)
+                        .help("Keeps the first or the last row of receivers which occur in several rows, or fails; email addresses are compared case-insensitively. With --stream, duplicates are only detected if this argument is given, and all addresses are kept in memory"),
+                    Arg::new(arg::NORMALIZE_GMAIL)
+                        .long(arg::NORMALIZE_GMAIL)
+                        .num_args(0)
+                        .required(false)
+                        .help("Compares Gmail addresses without dots and '+tag' when detecting duplicate receivers"),
                     display().help("Print emails to terminal"),
+                    preview_dir().help("Render emails as HTML files in the given directory instead of sending them"),
+                    Arg::new(arg::PREVIEW_COUNT)
+                        .long(arg::PREVIEW_COUNT)
+                        .num_args(1)
+                        .required(false)
+                        .requires(arg::PREVIEW_DIR)
+                        .default_value("10")
+                        .help("Number of emails to render"),
+                    Arg::new(arg::PREVIEW_RECEIVER)
+                        .long(arg::PREVIEW_RECEIVER)
+                        .num_args(1..)
+                        .required(false)
+                        .requires(arg::PREVIEW_DIR)
+                        .help("Render only the emails to the given receivers"),
+                    Arg::new(arg::TEST_TO)
+                        .long(arg::TEST_TO)
+                        .num_args(1..)
+                        .value_delimiter(',')
+                        .required(false)
+                        // The keys in the receiver column belong to the real receivers
+                        .conflicts_with_all([arg::PREVIEW_DIR, arg::SMIME_CERT_COLUMN, arg::PGP_KEY_COLUMN])
+                        .help("Sends the emails of the first receivers, or of the rows given by --sample-rows, to the given test addresses instead (subject prefixed by [TEST])"),
+                    Arg::new(arg::SAMPLE)
+                        .long(arg::SAMPLE)
+                        .num_args(1)
+                        .required(false)
+                        .requires(arg::TEST_TO)
+                        .default_value("1")
+                        .help("Number of the first receivers whose emails are sent to the test addresses"),
+                    Arg::new(arg::SAMPLE_ROWS)
+                        .long(arg::SAMPLE_ROWS)
+                        .value_name("rows")
+                        .num_args(1..)
+                        .value_delimiter(',')
+                        .required(false)
+                        .requires(arg::TEST_TO)
+                        .conflicts_with(arg::SAMPLE)
+                        .value_parser(RangedU64ValueParser::<usize>::new().range(1..))
+                        .help("Rows of the receiver file or query, starting at one, whose emails are sent to the test addresses"),
                     dry_run().help("Prepare emails but do not send emails"),
                     assume_yes().help("Send emails without confirmation"),
                     Arg::new(arg::SSH_TUNNEL)
@@ -286,16 +427,206 @@ pub fn app() -> Command {
                         .num_args(1)
                         .required(false)
                         .help("Query db through ssh tunnel"),
-                    Arg::new(arg::CONNECTION)
-                        .long(arg::CONNECTION)
+                    connection(),
+                    failover_after(),
+                    imap_folder(),
+                    max_message_size(),
+                    smime_cert(),
+                    smime_key(),
+                    smime_pkcs12(),
+                    smime_cert_dir().help("Encrypts the emails to the certificates '<email>.pem' or '<email>.crt' of the receivers in the given directory (S/MIME)"),
+                    Arg::new(arg::SMIME_CERT_COLUMN)
+                        .long(arg::SMIME_CERT_COLUMN)
+                        .value_name("column")
                         .num_args(1)
                         .required(false)
-                        .value_parser([val::SMTP, val::AWS])
-                        .default_value(val::SMTP)
-                        .help("Send emails via SMTP or AWS API"),
+                        .conflicts_with(arg::SMIME_CERT_DIR)
+                        .help("Encrypts the emails to the certificates whose paths are given by the receiver column (S/MIME)"),
+                    pgp_key(),
+                    pgp_key_dir().help("Encrypts the emails to the public keys '<email>.asc' or '<email>.gpg' of the receivers in the given directory (PGP/MIME)"),
+                    Arg::new(arg::PGP_KEY_COLUMN)
+                        .long(arg::PGP_KEY_COLUMN)
+                        .value_name("column")
+                        .num_args(1)
+                        .required(false)
+                        .conflicts_with_all([arg::PGP_KEY_DIR, arg::SMIME_CERT_DIR, arg::SMIME_CERT_COLUMN])
+                        .help("Encrypts the emails to the public keys whose paths are given by the receiver column (PGP/MIME)"),
+                    Arg::new(arg::IGNORE_QUOTA)
+                        .long(arg::IGNORE_QUOTA)
+                        .num_args(0)
+                        .required(false)
+                        .help("Send emails even if the sending quota of the email provider is exceeded"),
                     verbose(),
                 ]),
         )
+        .subcommand(
+            Command::new(cmd::SMTP_SINK)
+                .about("Run local SMTP server which saves received emails instead of sending them")
+                .args(&[
+                    Arg::new(arg::PORT)
+                        .long(arg::PORT)
+                        .num_args(1)
+                        .required(false)
+                        .default_value("2525")
+                        .help("Port of the SMTP server on localhost"),
+                    Arg::new(arg::DIR)
+                        .long(arg::DIR)
+                        .num_args(1)
+                        .required(false)
+                        .default_value("./caught_emails")
+                        .help("Specifies the output directory for received emails"),
+                    verbose(),
+                ]),
+        )
+        .subcommand(
+            Command::new(cmd::BOUNCES)
+                .about("Process bounces and complaints")
+                .args(&[verbose()])
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new(cmd::IMPORT)
+                        .about("Import bounces and complaints from delivery status notifications or SES notifications")
+                        .args(&[
+                            Arg::new(cmd::IMPORT)
+                                .num_args(1..)
+                                .required(true)
+                                .help("Paths of eml files, mbox files, Maildirs, directories, or SES notifications in json format"),
+                            Arg::new(arg::OUTPUT)
+                                .long(arg::OUTPUT)
+                                .num_args(1)
+                                .required(false)
+                                .help("Write all bounces and complaints to csv file"),
+                            suppression_file().help("Add hard bounces and complaints to the suppression list"),
+                            display().help("Display bounces and complaints in terminal"),
+                            verbose(),
+                        ]),
+                ),
+        )
+        .subcommand(
+            Command::new(cmd::RESEND)
+                .about("Resend archived emails without rebuilding them")
+                .args(&[
+                    Arg::new(cmd::RESEND)
+                        .num_args(1)
+                        .required(true)
+                        .help("Path of archived email, campaign directory, or archive directory"),
+                    Arg::new(arg::STATUS)
+                        .long(arg::STATUS)
+                        .num_args(1)
+                        .required(false)
+                        .value_parser([val::OK, val::FAILED, val::DRY_RUN])
+                        .help("Resend only emails with the given status in the index of the campaign"),
+                    display().help("Print emails to terminal"),
+                    dry_run().help("Prepare emails but do not send emails"),
+                    assume_yes().help("Send emails without confirmation"),
+                    connection(),
+                    failover_after(),
+                    imap_folder(),
+                    verbose(),
+                ]),
+        )
+        .subcommand(
+            Command::new(cmd::ARCHIVE)
+                .about("Inspect archived emails")
+                .args(&[verbose()])
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new(cmd::LIST)
+                        .about("List archived campaigns")
+                        .args(archive_filters())
+                        .args(&[archive_dir(), verbose()]),
+                )
+                .subcommand(
+                    Command::new(cmd::SEARCH)
+                        .about("Search archived emails")
+                        .args(archive_filters())
+                        .args(&[
+                            Arg::new(cmd::SEARCH)
+                                .num_args(1)
+                                .required(false)
+                                .help("Search text in receivers, subject, and message"),
+                            archive_dir(),
+                            verbose(),
+                        ]),
+                )
+                .subcommand(
+                    Command::new(cmd::SHOW)
+                        .about("Show archived email")
+                        .args(&[
+                            Arg::new(cmd::SHOW)
+                                .num_args(1)
+                                .required(true)
+                                .help("Message-ID or path of archived email"),
+                            archive_dir(),
+                            verbose(),
+                        ]),
+                )
+                .subcommand(
+                    Command::new(cmd::PRUNE)
+                        .about("Delete archived campaigns older than the given age")
+                        .args(&[
+                            Arg::new(arg::OLDER_THAN)
+                                .long(arg::OLDER_THAN)
+                                .num_args(1)
+                                .required(true)
+                                .help("Age in seconds (s), minutes (m), hours (h), days (d), or weeks (w), e.g. 90d"),
+                            archive_dir(),
+                            assume_yes(),
+                            verbose(),
+                        ]),
+                ),
+        )
+}
+
+fn connection() -> Arg {
+    Arg::new(arg::CONNECTION)
+        .long(arg::CONNECTION)
+        .num_args(1..)
+        .value_delimiter(',')
+        .required(false)
+        .value_parser([val::SMTP, val::AWS, val::MX])
+        .default_value(val::SMTP)
+        .help("Send emails via SMTP, AWS API, or directly to the mail exchangers of the receivers. Multiple comma-separated connections are used as fallbacks in the given order")
+}
+
+fn archive_filters() -> Vec<Arg> {
+    vec![
+        Arg::new(arg::RECEIVER)
+            .long(arg::RECEIVER)
+            .num_args(1)
+            .required(false)
+            .help("Filter by receiver"),
+        Arg::new(arg::SUBJECT)
+            .long(arg::SUBJECT)
+            .num_args(1)
+            .required(false)
+            .help("Filter by subject"),
+        Arg::new(arg::SINCE)
+            .long(arg::SINCE)
+            .num_args(1)
+            .required(false)
+            .help("Filter by emails sent since date (e.g. 2024-01-01)"),
+        Arg::new(arg::UNTIL)
+            .long(arg::UNTIL)
+            .num_args(1)
+            .required(false)
+            .help("Filter by emails sent until date (e.g. 2024-01-31)"),
+        dry_run()
+            .conflicts_with(arg::NO_DRY_RUN)
+            .help("Filter by emails of dry runs"),
+        Arg::new(arg::NO_DRY_RUN)
+            .long(arg::NO_DRY_RUN)
+            .num_args(0)
+            .required(false)
+            .help("Filter by emails which are not part of dry runs"),
+    ]
+}
+
+fn preview_dir() -> Arg {
+    Arg::new(arg::PREVIEW_DIR)
+        .long(arg::PREVIEW_DIR)
+        .num_args(1)
+        .required(false)
 }
 
 fn verbose() -> Arg {
@@ -327,6 +658,99 @@ fn assume_yes() -> Arg {
         .required(false)
 }
 
+fn failover_after() -> Arg {
+    Arg::new(arg::FAILOVER_AFTER)
+        .long(arg::FAILOVER_AFTER)
+        .num_args(1)
+        .required(false)
+        .value_parser(RangedU64ValueParser::<usize>::new().range(1..))
+        .help(format!(
+            "Falls back to the next connection after this number of consecutive transient failures [default: {}]",
+            DEFAULT_FAILOVER_AFTER
+        ))
+}
+
+fn imap_folder() -> Arg {
+    Arg::new(arg::IMAP_FOLDER)
+        .long(arg::IMAP_FOLDER)
+        .num_args(1)
+        .required(false)
+        .help("Stores sent emails in this IMAP folder, e.g. 'Sent'")
+}
+
+fn max_message_size() -> Arg {
+    Arg::new(arg::MAX_MESSAGE_SIZE)
+        .long(arg::MAX_MESSAGE_SIZE)
+        .value_name("size")
+        .num_args(1)
+        .required(false)
+        .help("Maximum size of the encoded attachment, e.g. '40MB' (defaults to the limit of the email provider)")
+}
+
+fn smime_cert() -> Arg {
+    Arg::new(arg::SMIME_CERT)
+        .long(arg::SMIME_CERT)
+        .value_name("path")
+        .num_args(1)
+        .required(false)
+        .requires(arg::SMIME_KEY)
+        .help("Signs emails with the certificate in the given PEM file, followed by intermediate certificates (S/MIME)")
+}
+
+fn smime_key() -> Arg {
+    Arg::new(arg::SMIME_KEY)
+        .long(arg::SMIME_KEY)
+        .value_name("path")
+        .num_args(1)
+        .required(false)
+        .requires(arg::SMIME_CERT)
+        .help("Path of the PEM file with the private key of the signing certificate")
+}
+
+fn smime_pkcs12() -> Arg {
+    Arg::new(arg::SMIME_PKCS12)
+        .long(arg::SMIME_PKCS12)
+        .value_name("path")
+        .num_args(1)
+        .required(false)
+        .conflicts_with_all([arg::SMIME_CERT, arg::SMIME_KEY])
+        .help("Signs emails with the certificate and private key in the given PKCS#12 file, protected by the environment variable 'SMIME_PASSWORD' (S/MIME)")
+}
+
+fn smime_cert_dir() -> Arg {
+    Arg::new(arg::SMIME_CERT_DIR)
+        .long(arg::SMIME_CERT_DIR)
+        .value_name("dir")
+        .num_args(1)
+        .required(false)
+}
+
+fn pgp_key() -> Arg {
+    Arg::new(arg::PGP_KEY)
+        .long(arg::PGP_KEY)
+        .value_name("path")
+        .num_args(1)
+        .required(false)
+        .conflicts_with_all([arg::SMIME_CERT, arg::SMIME_PKCS12, arg::SMIME_CERT_DIR])
+        .help("Signs emails with the secret key in the given file, protected by the environment variable 'PGP_PASSPHRASE' (PGP/MIME)")
+}
+
+fn pgp_key_dir() -> Arg {
+    Arg::new(arg::PGP_KEY_DIR)
+        .long(arg::PGP_KEY_DIR)
+        .value_name("dir")
+        .num_args(1)
+        .required(false)
+        .conflicts_with_all([arg::SMIME_CERT, arg::SMIME_PKCS12, arg::SMIME_CERT_DIR])
+}
+
+fn suppression_file() -> Arg {
+    Arg::new(arg::SUPPRESSION_FILE)
+        .long(arg::SUPPRESSION_FILE)
+        .num_args(1)
+        .required(false)
+}
+
 fn archive() -> Arg {
     Arg::new(arg::ARCHIVE)
         .long(arg::ARCHIVE)
@@ -344,6 +768,33 @@ fn archive_dir() -> Arg {
         .help("Path of sent emails")
 }
 
+fn archive_format() -> Arg {
+    Arg::new(arg::ARCHIVE_FORMAT)
+        .long(arg::ARCHIVE_FORMAT)
+        .num_args(1)
+        .required(false)
+        .value_parser([val::EML, val::MBOX, val::MAILDIR])
+        .default_value(val::EML)
+        .help("Archives sent emails as separate .eml files, in a single mbox file per campaign, or in a Maildir")
+}
+
+fn archive_compression() -> Arg {
+    Arg::new(arg::ARCHIVE_COMPRESSION)
+        .long(arg::ARCHIVE_COMPRESSION)
+        .num_args(1)
+        .required(false)
+        .value_parser([val::GZIP, val::ZSTD])
+        .help("Compresses archived .eml files")
+}
+
+fn dedup_attachments() -> Arg {
+    Arg::new(arg::DEDUP_ATTACHMENTS)
+        .long(arg::DEDUP_ATTACHMENTS)
+        .num_args(0)
+        .required(false)
+        .help("Stores identical attachments only once per archived campaign")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
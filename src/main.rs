@@ -17,6 +17,10 @@ fn main() -> Result<(), anyhow::Error> {
         Some((cmd::READ, matches)) => cmd::read(matches),
         Some((cmd::SEND, matches)) => cmd::send(matches),
         Some((cmd::SEND_BULK, matches)) => cmd::send_bulk(matches),
+        Some((cmd::SMTP_SINK, matches)) => cmd::smtp_sink(matches),
+        Some((cmd::BOUNCES, matches)) => cmd::bounces(matches),
+        Some((cmd::RESEND, matches)) => cmd::resend(matches),
+        Some((cmd::ARCHIVE, matches)) => cmd::archive(matches),
         _ => Err(anyhow!("Subcommand not found")),
     }
 }
@@ -1,3 +1,4 @@
+use super::Row;
 use anyhow::Context;
 use chrono::{DateTime, Utc};
 use polars::prelude::{CsvReader, CsvWriter, DataFrame, SerReader, SerWriter};
@@ -10,6 +11,46 @@ pub fn read_csv(csv_file: &Path) -> Result<DataFrame, anyhow::Error> {
     Ok(df)
 }
 
+/// Read a csv file without inferring the column types. All columns are read
+/// as text, so that values like `01234` or `1234.50` are personalized as is.
+pub fn read_csv_as_text(csv_file: &Path) -> Result<DataFrame, anyhow::Error> {
+    println!("Reading csv file '{}' ...", csv_file.display());
+    let reader = CsvReader::from_path(csv_file)?
+        .has_header(true)
+        .infer_schema(Some(0));
+    let df = reader.finish()?;
+    Ok(df)
+}
+
+/// Read a csv file row by row instead of loading the whole file into memory.
+pub fn stream_csv(
+    csv_file: &Path,
+) -> Result<impl Iterator<Item = Result<Row, anyhow::Error>>, anyhow::Error> {
+    println!("Streaming csv file '{}' ...", csv_file.display());
+    let mut reader = csv::Reader::from_path(csv_file)
+        .context(format!("Can't read csv file '{}'", csv_file.display()))?;
+    let headers = reader
+        .headers()
+        .context(format!("Can't read header of '{}'", csv_file.display()))?
+        .iter()
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    let csv_file = csv_file.to_owned();
+
+    Ok(reader.into_records().map(move |record| {
+        let record = record.context(format!("Can't read csv file '{}'", csv_file.display()))?;
+        let row = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(header, value)| {
+                let value = (!value.is_empty()).then(|| value.to_owned());
+                (header.to_owned(), value)
+            })
+            .collect();
+        Ok(row)
+    }))
+}
+
 pub fn write_csv(
     df: &mut DataFrame,
     save_dir: &Path,
@@ -41,7 +82,12 @@ pub fn write_csv(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use polars::{datatypes::AnyValue, frame::row::Row, prelude::NamedFrom, series::Series};
+    use polars::{
+        datatypes::{AnyValue, DataType},
+        frame::row::Row,
+        prelude::NamedFrom,
+        series::Series,
+    };
     use tempfile::tempdir;
 
     #[test]
@@ -75,6 +121,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_read_csv_as_text() {
+        let csv_file = Path::new("./test_data/receiver_locales.csv");
+        let df_receiver = read_csv_as_text(csv_file).unwrap();
+        assert_eq!(
+            df_receiver.get_row(0).unwrap(),
+            Row::new(
+                ["Lise", "lise@meitner.com", "de-AT", "1234.5", "2024-06-01"]
+                    .into_iter()
+                    .map(AnyValue::Utf8)
+                    .collect()
+            )
+        );
+
+        // Column types are still inferred for other commands, e.g. `read`
+        let df = read_csv(csv_file).unwrap();
+        assert_eq!(df.column("amount").unwrap().dtype(), &DataType::Float64);
+    }
+
+    #[test]
+    fn test_stream_csv() {
+        let csv_file = Path::new("./test_data/receiver.csv");
+        let rows = stream_csv(csv_file)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[0],
+            crate::sources::Row::from([
+                ("first_name".to_owned(), Some("Marie".to_owned())),
+                ("last_name".to_owned(), Some("Curie".to_owned())),
+                ("email".to_owned(), Some("marie@curie.com".to_owned())),
+            ])
+        );
+        assert_eq!(
+            rows[1].get("email"),
+            Some(&Some("alexandre@grothendieck.com".to_owned()))
+        );
+    }
+
     #[test]
     fn test_write_csv() {
         let timestamp = chrono::DateTime::parse_from_rfc3339("2024-01-01T14:00:00Z").unwrap();
@@ -4,8 +4,13 @@ mod postgres;
 mod ssh_tunnel;
 
 pub use self::{
-    csv::{read_csv, write_csv},
+    csv::{read_csv, read_csv_as_text, stream_csv, write_csv},
     image::write_image,
-    postgres::{query_postgres, ConnVars, DbConnection},
+    postgres::{query_postgres, ConnVars, DbConnection, PostgresCursor},
 };
 pub use ssh_tunnel::SshTunnel;
+use std::collections::BTreeMap;
+
+/// A single row of a csv file or query result by column name. Empty values
+/// and nulls are `None`.
+pub type Row = BTreeMap<String, Option<String>>;
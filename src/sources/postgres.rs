@@ -1,4 +1,4 @@
-use super::SshTunnel;
+use super::{Row, SshTunnel};
 use anyhow::{Context, Result};
 use connectorx::{
     destinations::arrow2::Arrow2Destination,
@@ -7,10 +7,15 @@ use connectorx::{
     sql::CXQuery,
 };
 use polars::frame::DataFrame;
-use postgres::NoTls;
-use std::{env, fmt};
+use postgres::{Client, NoTls, SimpleQueryMessage};
+use std::{collections::VecDeque, env, fmt};
 use url::Url;
 
+/// Name of the cursor used to stream query results.
+const CURSOR_NAME: &str = "pigeon_cursor";
+/// Number of rows fetched from the cursor at a time.
+const FETCH_SIZE: usize = 1000;
+
 pub struct Password(pub String);
 
 impl fmt::Debug for Password {
@@ -116,3 +121,80 @@ pub fn query_postgres(connection: &DbConnection, query: &str) -> Result<DataFram
 
     Ok(df)
 }
+
+/// Cursor which fetches the result of a query in chunks of `FETCH_SIZE` rows,
+/// so that the result doesn't have to fit into memory.
+pub struct PostgresCursor {
+    client: Client,
+    connection: DbConnection,
+    rows: VecDeque<Row>,
+    is_exhausted: bool,
+}
+
+impl PostgresCursor {
+    pub fn new(connection: DbConnection, query: &str) -> Result<Self, anyhow::Error> {
+        let mut client =
+            Client::connect(connection.url.as_str(), NoTls).context("Can't connect to database")?;
+        client
+            .batch_execute(&format!(
+                "BEGIN; DECLARE {} NO SCROLL CURSOR FOR {}",
+                CURSOR_NAME,
+                query.trim().trim_end_matches(';')
+            ))
+            .context("Can't declare cursor for query")?;
+
+        Ok(Self {
+            client,
+            connection,
+            rows: VecDeque::new(),
+            is_exhausted: false,
+        })
+    }
+
+    fn fetch(&mut self) -> Result<(), anyhow::Error> {
+        let messages = self
+            .client
+            .simple_query(&format!("FETCH {} FROM {}", FETCH_SIZE, CURSOR_NAME))
+            .context("Can't fetch rows from cursor")?;
+
+        for message in messages {
+            if let SimpleQueryMessage::Row(row) = message {
+                let row = row
+                    .columns()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, column)| (column.name().to_owned(), row.get(i).map(str::to_owned)))
+                    .collect();
+                self.rows.push_back(row);
+            }
+        }
+
+        if self.rows.len() < FETCH_SIZE {
+            self.is_exhausted = true;
+            self.client
+                .batch_execute(&format!("CLOSE {}; COMMIT", CURSOR_NAME))
+                .context("Can't close cursor")?;
+
+            if let Some(tunnel) = &self.connection.ssh_tunnel {
+                tunnel.kill()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Iterator for PostgresCursor {
+    type Item = Result<Row, anyhow::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rows.is_empty() && !self.is_exhausted {
+            if let Err(err) = self.fetch() {
+                self.is_exhausted = true;
+                return Some(Err(err));
+            }
+        }
+
+        self.rows.pop_front().map(Ok)
+    }
+}
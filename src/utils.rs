@@ -1,4 +1,6 @@
-use std::{fs, path::Path};
+use crate::email_builder::Confirmed;
+use anyhow::{anyhow, Context};
+use std::{fs, io, path::Path};
 
 pub fn format_green(text: &str) -> String {
     const GREEN: &str = "\x1b[32m";
@@ -14,8 +16,68 @@ pub fn format_red(text: &str) -> String {
     red_text
 }
 
+/// Parse a size in bytes, or with unit `KB`, `MB`, or `GB` (multiples of
+/// 1024), e.g. `10MB`.
+pub fn parse_size(value: &str) -> Result<u64, anyhow::Error> {
+    let invalid = || anyhow!("Invalid size '{}', expected e.g. '10MB'", value);
+    let value = value.trim();
+    let unit_index = value
+        .find(|char: char| !char.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(unit_index);
+    let amount = amount.parse::<u64>().map_err(|_| invalid())?;
+
+    let factor = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" => 1024,
+        "MB" => 1024 * 1024,
+        "GB" => 1024 * 1024 * 1024,
+        _ => return Err(invalid()),
+    };
+
+    amount.checked_mul(factor).ok_or_else(invalid)
+}
+
+/// Read yes or no from stdin until the input is valid. The question is
+/// printed by the caller.
+pub fn confirm() -> Result<Confirmed, anyhow::Error> {
+    let mut input = String::new();
+
+    loop {
+        io::stdin()
+            .read_line(&mut input)
+            .context("Can't read input")?;
+        match input.trim() {
+            "y" | "yes" | "Yes" => return Ok(Confirmed::Yes),
+            "n" | "no" | "No" => {
+                println!("Aborted ...");
+                return Ok(Confirmed::No);
+            }
+            _ => {
+                println!("Choose yes (y) or no (n). Try again.");
+                input.clear();
+            }
+        }
+    }
+}
+
 pub fn read_file(path: &Path) -> Result<String, anyhow::Error> {
     println!("Reading file '{}' ...", path.display());
     let content = fs::read_to_string(path)?;
     Ok(content)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("1KB").unwrap(), 1024);
+        assert_eq!(parse_size("10MB").unwrap(), 10 * 1024 * 1024);
+        assert_eq!(parse_size("40 mb").unwrap(), 40 * 1024 * 1024);
+        assert!(parse_size("MB").is_err());
+        assert!(parse_size("10TB").is_err());
+    }
+}
@@ -1,6 +1,10 @@
+mod test_archive;
+mod test_bounces;
 mod test_connect;
 mod test_init;
 mod test_query;
 mod test_read;
+mod test_resend;
 mod test_send;
 mod test_send_bulk;
+mod utils;
@@ -0,0 +1,203 @@
+use assert_cmd::Command;
+use predicates::{boolean::PredicateBooleanExt, str};
+use std::{fs, path::Path};
+use tempfile::tempdir;
+
+fn send_bulk_dry(temp_path: &Path, args: &[&str]) {
+    fs::copy("./test_data/receiver.csv", temp_path.join("receiver.csv")).unwrap();
+    fs::copy("./test_data/message.yaml", temp_path.join("message.yaml")).unwrap();
+
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args([
+        "send-bulk",
+        "albert@einstein.com",
+        "--receiver-file",
+        "./receiver.csv",
+        "--message-file",
+        "./message.yaml",
+        "--archive",
+        "--assume-yes",
+        "--dry-run",
+    ]);
+    cmd.args(args);
+    cmd.assert().success();
+}
+
+#[test]
+fn test_archive_list() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    send_bulk_dry(temp_path, &[]);
+
+    println!("Execute 'pigeon archive list'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args(["archive", "list"]);
+    cmd.assert().success().stdout(
+        str::contains("Reading archive './sent_emails' ...")
+            .and(str::contains("CAMPAIGN"))
+            .and(str::contains(
+                "_dry-run  2       0       yes      albert@einstein.com",
+            ))
+            .and(str::contains("Found 1 campaigns")),
+    );
+
+    println!("Execute 'pigeon archive list --no-dry-run'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args(["archive", "list", "--no-dry-run"]);
+    cmd.assert()
+        .success()
+        .stdout(str::contains("Found 0 campaigns"));
+}
+
+#[test]
+fn test_archive_search_and_show() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    send_bulk_dry(temp_path, &[]);
+
+    println!("Execute 'pigeon archive search --receiver marie'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args([
+        "archive",
+        "search",
+        "--receiver",
+        "marie",
+        "--since",
+        "2024-01-01",
+    ]);
+    let output = cmd.assert().success().stdout(
+        str::contains("dry-run  marie@curie.com")
+            .and(str::contains("grothendieck").not())
+            .and(str::contains("Found 1 emails")),
+    );
+
+    let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    let message_id = stdout
+        .split_whitespace()
+        .find(|word| word.starts_with('<') && word.ends_with("@einstein.com>"))
+        .unwrap();
+
+    println!("Execute 'pigeon archive show {}'", message_id);
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args(["archive", "show", message_id]);
+    cmd.assert().success().stdout(
+        str::contains(format!("Message-ID: {}", message_id))
+            .and(str::contains("To: marie@curie.com"))
+            .and(str::contains("Status: dry-run"))
+            .and(str::contains("--- text/plain ---")),
+    );
+
+    println!("Execute 'pigeon archive show' for unknown email");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args(["archive", "show", "<unknown@einstein.com>"]);
+    cmd.assert().failure().stderr(str::contains(
+        "Archived email '<unknown@einstein.com>' not found",
+    ));
+}
+
+#[test]
+fn test_archive_compression_dedup_attachments() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    fs::copy("./test_data/test.pdf", temp_path.join("test.pdf")).unwrap();
+    send_bulk_dry(
+        temp_path,
+        &[
+            "--attachment",
+            "./test.pdf",
+            "--archive-compression",
+            "gzip",
+            "--dedup-attachments",
+        ],
+    );
+
+    let campaign_dir = fs::read_dir(temp_path.join("sent_emails"))
+        .unwrap()
+        .flatten()
+        .next()
+        .unwrap()
+        .path();
+    let files = fs::read_dir(&campaign_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        files
+            .iter()
+            .filter(|file| file.ends_with("_dry-run.eml.gz"))
+            .count(),
+        2
+    );
+    assert_eq!(
+        fs::read_dir(campaign_dir.join("attachments"))
+            .unwrap()
+            .count(),
+        1
+    );
+
+    println!("Execute 'pigeon archive search test'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args(["archive", "search", "test message"]);
+    let output = cmd
+        .assert()
+        .success()
+        .stdout(str::contains("Found 2 emails"));
+
+    let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    let message_id = stdout
+        .split_whitespace()
+        .find(|word| word.starts_with('<') && word.ends_with("@einstein.com>"))
+        .unwrap();
+
+    println!("Execute 'pigeon archive show {}'", message_id);
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args(["archive", "show", message_id]);
+    cmd.assert().success().stdout(str::contains(format!(
+        "--- Attachment 'test.pdf' (application/pdf, {} bytes) ---",
+        fs::metadata("./test_data/test.pdf").unwrap().len()
+    )));
+}
+
+#[test]
+fn test_archive_prune() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    send_bulk_dry(temp_path, &[]);
+
+    println!("Execute 'pigeon archive prune --older-than 90d'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args(["archive", "prune", "--older-than", "90d", "--assume-yes"]);
+    cmd.assert()
+        .success()
+        .stdout(str::contains("No archived campaigns older than 90d"));
+
+    println!("Execute 'pigeon archive prune --older-than 0s'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args(["archive", "prune", "--older-than", "0s", "--assume-yes"]);
+    cmd.assert()
+        .success()
+        .stdout(str::contains("Pruned 1 archived campaigns"));
+    assert_eq!(
+        fs::read_dir(temp_path.join("sent_emails")).unwrap().count(),
+        0
+    );
+
+    println!("Execute 'pigeon archive prune --older-than 90y'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args(["archive", "prune", "--older-than", "90y"]);
+    cmd.assert()
+        .failure()
+        .stderr(str::contains("Invalid age '90y'"));
+}
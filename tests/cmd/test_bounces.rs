@@ -0,0 +1,136 @@
+use assert_cmd::Command;
+use predicates::{boolean::PredicateBooleanExt, str};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn test_bounces_import() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    let output = temp_path.join("bounces.csv");
+    let suppression_file = temp_path.join("suppressions.csv");
+
+    println!("Execute 'pigeon bounces import ./test_data/bounces'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.args([
+        "bounces",
+        "import",
+        "./test_data/bounces",
+        "--output",
+        output.to_str().unwrap(),
+        "--suppression-file",
+        suppression_file.to_str().unwrap(),
+        "--display",
+    ]);
+    cmd.assert().success().stdout(
+        str::contains("Importing bounces from './test_data/bounces' ...")
+            .and(str::contains(
+                "Found 2 hard bounces, 1 soft bounces, and 2 complaints",
+            ))
+            .and(str::contains("marie@curie.com ... hard 5.1.1"))
+            .and(str::contains("Adding 4 receivers to suppression list")),
+    );
+
+    let bounces = fs::read_to_string(&output).unwrap();
+    assert_eq!(bounces.lines().count(), 6);
+    assert!(bounces.starts_with("email,bounce_type,status,diagnostic,date,message_id,source"));
+
+    let suppressions = fs::read_to_string(&suppression_file).unwrap();
+    assert_eq!(suppressions.lines().count(), 5);
+    assert!(!suppressions.contains("alexandre@grothendieck.com"));
+
+    println!("Execute 'pigeon bounces import' again");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.args([
+        "bounces",
+        "import",
+        "./test_data/bounces/dsn.eml",
+        "--suppression-file",
+        suppression_file.to_str().unwrap(),
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(str::contains("Adding 0 receivers to suppression list"));
+}
+
+#[test]
+fn test_send_bulk_suppression_file() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    let suppression_file = temp_path.join("suppressions.csv");
+
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.args([
+        "bounces",
+        "import",
+        "./test_data/bounces/dsn.eml",
+        "--suppression-file",
+        suppression_file.to_str().unwrap(),
+    ]);
+    cmd.assert().success();
+
+    println!("Execute 'pigeon send-bulk --suppression-file'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.args([
+        "send-bulk",
+        "albert@einstein.com",
+        "--receiver-file",
+        "./test_data/receiver.csv",
+        "--message-file",
+        "./test_data/message.yaml",
+        "--suppression-file",
+        suppression_file.to_str().unwrap(),
+        "--dry-run",
+        "--assume-yes",
+    ]);
+    cmd.assert().success().stdout(
+        str::contains("Skipping 1 receivers in suppression list")
+            .and(str::contains("Sending email to 1 receivers ..."))
+            .and(str::contains(
+                "alexandre@grothendieck.com ... \u{1b}[32mdry run\u{1b}[0m",
+            ))
+            .and(str::contains("marie@curie.com ...").not()),
+    );
+}
+
+#[test]
+fn test_send_bulk_missing_suppression_file() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    let suppression_file = temp_path.join("suppresions.csv");
+
+    for stream in [false, true] {
+        println!(
+            "Execute 'pigeon send-bulk --suppression-file' for missing file (stream: {})",
+            stream
+        );
+        let mut cmd = Command::cargo_bin("pigeon").unwrap();
+        cmd.args([
+            "send-bulk",
+            "albert@einstein.com",
+            "--receiver-file",
+            "./test_data/receiver.csv",
+            "--message-file",
+            "./test_data/message.yaml",
+            "--suppression-file",
+            suppression_file.to_str().unwrap(),
+            "--dry-run",
+            "--assume-yes",
+        ]);
+
+        if stream {
+            cmd.arg("--stream");
+        }
+
+        cmd.assert()
+            .failure()
+            .stderr(str::contains("Missing suppression file"))
+            .stdout(str::contains("marie@curie.com ...").not());
+    }
+}
@@ -22,7 +22,12 @@ fn test_connect_aws() {
     println!("Execute 'pigeon connect aws'");
     let mut cmd = Command::cargo_bin("pigeon").unwrap();
     cmd.args(["connect", "aws"]);
-    cmd.assert()
-        .success()
-        .stdout(str::contains("Connecting to aws server in region").and(str::contains("ok")));
+    cmd.assert().success().stdout(
+        str::contains("Connecting to aws server in region")
+            .and(str::contains("Checking aws credentials ..."))
+            .and(str::contains("Max 24 hour send:"))
+            .and(str::contains("Remaining quota:"))
+            .and(str::contains("Sandbox (estimated from sending quota):"))
+            .and(str::contains("Verified identities:")),
+    );
 }
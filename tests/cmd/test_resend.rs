@@ -0,0 +1,101 @@
+use crate::utils::SmtpSink;
+use assert_cmd::Command;
+use predicates::{boolean::PredicateBooleanExt, str};
+use std::{fs, path::PathBuf};
+use tempfile::tempdir;
+
+#[test]
+fn test_resend_smtp_sink() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::copy("./test_data/receiver.csv", temp_path.join("receiver.csv")).unwrap();
+    fs::copy("./test_data/message.yaml", temp_path.join("message.yaml")).unwrap();
+
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args([
+        "send-bulk",
+        "albert@einstein.com",
+        "--receiver-file",
+        "./receiver.csv",
+        "--message-file",
+        "./message.yaml",
+        "--archive",
+        "--assume-yes",
+        "--dry-run",
+    ]);
+    cmd.assert().success();
+
+    let campaign_dir = fs::read_dir(temp_path.join("sent_emails"))
+        .unwrap()
+        .flatten()
+        .next()
+        .unwrap()
+        .path();
+    let archived_emls = fs::read_dir(&campaign_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "eml"))
+        .map(|path| fs::read(path).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(archived_emls.len(), 2);
+
+    println!("Execute 'pigeon resend --status failed'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args([
+        "resend",
+        campaign_dir.to_str().unwrap(),
+        "--status",
+        "failed",
+        "--assume-yes",
+        "--dry-run",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(str::contains("No archived emails found"));
+
+    let caught_dir = temp_path.join("caught");
+    let sink = SmtpSink::spawn(&caught_dir);
+    let port = sink.port;
+
+    println!("Execute 'pigeon resend --connection smtp'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.env("SMTP_SERVER", "127.0.0.1");
+    cmd.env("SMTP_PORT", port.to_string());
+    cmd.env("SMTP_TLS", "none");
+    cmd.args([
+        "resend",
+        campaign_dir.to_str().unwrap(),
+        "--status",
+        "dry-run",
+        "--assume-yes",
+        "--connection",
+        "smtp",
+    ]);
+    cmd.assert().success().stdout(
+        str::contains("Sending email to 2 receivers ...")
+            .and(str::contains("marie@curie.com ... \u{1b}[32mok\u{1b}[0m"))
+            .and(str::contains("All emails resent")),
+    );
+
+    let mut caught_emls = fs::read_dir(&caught_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.path())
+        .collect::<Vec<PathBuf>>();
+    caught_emls.sort();
+    assert_eq!(caught_emls.len(), 2);
+
+    // The archived emails are sent as is, the SMTP transport only appends a
+    // line break before the end of data
+    for caught_eml in caught_emls {
+        let caught_eml = fs::read(caught_eml).unwrap();
+        let caught_eml = caught_eml.strip_suffix(b"\r\n").unwrap_or(&caught_eml);
+        assert!(archived_emls.iter().any(|eml| eml == caught_eml));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::utils::SmtpSink;
 use assert_cmd::Command;
 use predicates::{boolean::PredicateBooleanExt, str};
 use std::{env, fs};
@@ -194,3 +195,312 @@ fn test_send_aws_dry() {
 
     assert!(temp_path.join("my-sent-emails").exists());
 }
+
+#[test]
+fn test_send_smtp_sink() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::copy("./test_data/message.yaml", temp_path.join("message.yaml")).unwrap();
+    fs::copy("./test_data/test.pdf", temp_path.join("test.pdf")).unwrap();
+
+    let caught_dir = temp_path.join("caught");
+    let sink = SmtpSink::spawn(&caught_dir);
+    let port = sink.port;
+
+    println!("Execute 'pigeon send --connection smtp'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.env("SMTP_SERVER", "127.0.0.1");
+    cmd.env("SMTP_PORT", port.to_string());
+    cmd.env("SMTP_TLS", "none");
+    cmd.args([
+        "send",
+        "albert@einstein.com",
+        "marie@curie.com",
+        "--message-file",
+        "./message.yaml",
+        "--attachment",
+        "./test.pdf",
+        "--assume-yes",
+        "--connection",
+        "smtp",
+    ]);
+    cmd.assert().success().stdout(
+        str::contains("Connecting to SMTP server '127.0.0.1' ... \u{1b}[32mok\u{1b}[0m")
+            .and(str::contains("Sending email to 1 receiver ..."))
+            .and(str::contains("marie@curie.com ... \u{1b}[32mok\u{1b}[0m"))
+            .and(str::contains("Email sent")),
+    );
+
+    let files = fs::read_dir(&caught_dir)
+        .unwrap()
+        .flatten()
+        .collect::<Vec<_>>();
+    assert_eq!(files.len(), 1);
+
+    let eml = fs::read_to_string(files[0].path()).unwrap();
+    assert!(eml.contains("From: albert@einstein.com"));
+    assert!(eml.contains("To: marie@curie.com"));
+    assert!(eml.contains("Subject: Test subject"));
+    assert!(eml.contains("This is a test message (plaintext)."));
+    assert!(eml.contains("Content-Type: application/pdf"));
+}
+
+#[test]
+fn test_send_attachments_smtp_sink() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::copy(
+        "./test_data/message_attachments.yaml",
+        temp_path.join("message.yaml"),
+    )
+    .unwrap();
+    fs::copy("./test_data/test.pdf", temp_path.join("test.pdf")).unwrap();
+    fs::copy("./test_data/test.txt", temp_path.join("test.txt")).unwrap();
+    fs::copy("./test_data/test.png", temp_path.join("test.png")).unwrap();
+
+    let caught_dir = temp_path.join("caught");
+    let sink = SmtpSink::spawn(&caught_dir);
+    let port = sink.port;
+
+    println!("Execute 'pigeon send --message-file ./message.yaml --attachment ./test.png'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.env("SMTP_SERVER", "127.0.0.1");
+    cmd.env("SMTP_PORT", port.to_string());
+    cmd.env("SMTP_TLS", "none");
+    cmd.args([
+        "send",
+        "albert@einstein.com",
+        "marie@curie.com",
+        "--message-file",
+        "./message.yaml",
+        "--attachment",
+        "./test.png",
+        "--assume-yes",
+        "--connection",
+        "smtp",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(str::contains("marie@curie.com ... \u{1b}[32mok\u{1b}[0m"));
+
+    let emls = fs::read_dir(&caught_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| fs::read_to_string(entry.path()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(emls.len(), 1);
+
+    let eml = &emls[0];
+    assert!(eml.contains("Content-Type: multipart/mixed"));
+    assert!(eml.contains(
+        "Content-Type: application/pdf\r\nContent-Disposition: attachment; filename=\"test.pdf\""
+    ));
+    assert!(eml.contains(
+        "Content-Type: text/csv\r\nContent-Disposition: inline;\r\n filename*0*=utf-8''Pr%C3%BCfbericht%20M%C3%A4rz.csv"
+    ));
+    assert!(eml.contains(
+        "Content-Type: image/png\r\nContent-Disposition: attachment; filename=\"test.png\""
+    ));
+}
+
+#[test]
+fn test_send_smime_smtp_sink() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    let certs_dir = temp_path.join("certs");
+    fs::create_dir(&certs_dir).unwrap();
+    for extension in ["pem", "key"] {
+        fs::copy(
+            format!("./test_data/smime/albert@einstein.com.{}", extension),
+            temp_path.join(format!("albert@einstein.com.{}", extension)),
+        )
+        .unwrap();
+    }
+    fs::copy(
+        "./test_data/smime/marie@curie.com.pem",
+        certs_dir.join("marie@curie.com.pem"),
+    )
+    .unwrap();
+
+    let caught_dir = temp_path.join("caught");
+    let sink = SmtpSink::spawn(&caught_dir);
+    let port = sink.port;
+
+    println!("Execute 'pigeon send --smime-cert --smime-key --smime-cert-dir'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.env("SMTP_SERVER", "127.0.0.1");
+    cmd.env("SMTP_PORT", port.to_string());
+    cmd.env("SMTP_TLS", "none");
+    cmd.args([
+        "send",
+        "albert@einstein.com",
+        "marie@curie.com",
+        "--subject",
+        "Test subject",
+        "--content",
+        "This is a test message (plaintext).",
+        "--smime-cert",
+        "./albert@einstein.com.pem",
+        "--smime-key",
+        "./albert@einstein.com.key",
+        "--smime-cert-dir",
+        "./certs",
+        "--archive",
+        "--assume-yes",
+        "--connection",
+        "smtp",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(str::contains("marie@curie.com ... \u{1b}[32mok\u{1b}[0m"));
+
+    let emls = fs::read_dir(&caught_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| fs::read_to_string(entry.path()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(emls.len(), 1);
+
+    let eml = &emls[0];
+    assert!(eml.contains("Subject: Test subject"));
+    assert!(eml.contains("Content-Type: application/pkcs7-mime; smime-type=enveloped-data;"));
+    assert!(!eml.contains("This is a test message"));
+
+    println!("Execute 'pigeon send --smime-cert-dir' without certificate of the receiver");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args([
+        "send",
+        "albert@einstein.com",
+        "emmy@noether.com",
+        "--subject",
+        "Test subject",
+        "--content",
+        "This is a test message (plaintext).",
+        "--smime-cert-dir",
+        "./certs",
+        "--dry-run",
+        "--assume-yes",
+    ]);
+    cmd.assert().failure().stderr(str::contains(
+        "Missing certificate of receiver 'emmy@noether.com'",
+    ));
+}
+
+#[test]
+fn test_send_pgp_smtp_sink() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    let keys_dir = temp_path.join("keys");
+    fs::create_dir(&keys_dir).unwrap();
+    fs::copy(
+        "./test_data/pgp/albert@einstein.com.key",
+        temp_path.join("albert@einstein.com.key"),
+    )
+    .unwrap();
+    fs::copy(
+        "./test_data/pgp/marie@curie.com.asc",
+        keys_dir.join("marie@curie.com.asc"),
+    )
+    .unwrap();
+
+    let caught_dir = temp_path.join("caught");
+    let sink = SmtpSink::spawn(&caught_dir);
+    let port = sink.port;
+
+    println!("Execute 'pigeon send --pgp-key --pgp-key-dir'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.env("SMTP_SERVER", "127.0.0.1");
+    cmd.env("SMTP_PORT", port.to_string());
+    cmd.env("SMTP_TLS", "none");
+    cmd.args([
+        "send",
+        "albert@einstein.com",
+        "marie@curie.com",
+        "--subject",
+        "Test subject",
+        "--content",
+        "This is a test message (plaintext).",
+        "--pgp-key",
+        "./albert@einstein.com.key",
+        "--pgp-key-dir",
+        "./keys",
+        "--assume-yes",
+        "--connection",
+        "smtp",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(str::contains("marie@curie.com ... \u{1b}[32mok\u{1b}[0m"));
+
+    let emls = fs::read_dir(&caught_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| fs::read_to_string(entry.path()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(emls.len(), 1);
+
+    let eml = &emls[0];
+    assert!(eml.contains("Subject: Test subject"));
+    assert!(eml.contains("Content-Type: multipart/encrypted;"));
+    assert!(eml.contains("-----BEGIN PGP MESSAGE-----"));
+    assert!(!eml.contains("This is a test message"));
+
+    println!("Execute 'pigeon send --pgp-key-dir' without public key of the receiver");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args([
+        "send",
+        "albert@einstein.com",
+        "emmy@noether.com",
+        "--subject",
+        "Test subject",
+        "--content",
+        "This is a test message (plaintext).",
+        "--pgp-key-dir",
+        "./keys",
+        "--assume-yes",
+        "--connection",
+        "smtp",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(str::contains("emmy@noether.com"));
+
+    println!("Execute 'pigeon send --pgp-key-dir' without GnuPG");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.env("PATH", "");
+    cmd.args([
+        "send",
+        "albert@einstein.com",
+        "marie@curie.com",
+        "--subject",
+        "Test subject",
+        "--content",
+        "This is a test message (plaintext).",
+        "--pgp-key-dir",
+        "./keys",
+        "--assume-yes",
+        "--connection",
+        "smtp",
+    ]);
+    cmd.assert()
+        .failure()
+        .stdout(str::contains("Sending email").not())
+        .stderr(str::contains(
+            "Can't run gpg, which is required for PGP/MIME",
+        ));
+}
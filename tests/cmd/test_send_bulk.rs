@@ -1,3 +1,4 @@
+use crate::utils::SmtpSink;
 use assert_cmd::Command;
 use predicates::{boolean::PredicateBooleanExt, str};
 use std::fs;
@@ -47,7 +48,81 @@ fn test_send_bulk_smtp_dry() {
             .and(str::contains("All emails sent (dry run)")),
     );
 
-    assert!(temp_path.join("my-sent-emails").exists());
+    let campaigns = fs::read_dir(temp_path.join("my-sent-emails"))
+        .unwrap()
+        .flatten()
+        .collect::<Vec<_>>();
+    assert_eq!(campaigns.len(), 1);
+
+    let campaign_dir = campaigns[0].path();
+    assert!(campaign_dir.to_str().unwrap().ends_with("_dry-run"));
+
+    let campaign = fs::read_to_string(campaign_dir.join("campaign.json")).unwrap();
+    assert!(campaign.contains(r#""sender": "albert@einstein.com""#));
+    assert!(campaign.contains(r#""file": "./receiver.csv""#));
+
+    let index = fs::read_to_string(campaign_dir.join("index.jsonl")).unwrap();
+    assert_eq!(index.lines().count(), 2);
+    assert!(index.contains(r#""receivers":["marie@curie.com"]"#));
+    assert!(index.contains(r#""status":"dry-run""#));
+    assert!(index.contains(r#""message_id":"<"#));
+}
+
+#[test]
+fn test_send_bulk_archive_mbox_dry() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::copy("./test_data/receiver.csv", temp_path.join("receiver.csv")).unwrap();
+    fs::copy("./test_data/message.yaml", temp_path.join("message.yaml")).unwrap();
+
+    println!("Execute 'pigeon send-bulk --archive-format mbox'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args([
+        "send-bulk",
+        "albert@einstein.com",
+        "--receiver-file",
+        "./receiver.csv",
+        "--message-file",
+        "./message.yaml",
+        "--archive",
+        "--archive-dir",
+        "./my-sent-emails",
+        "--archive-format",
+        "mbox",
+        "--assume-yes",
+        "--dry-run",
+    ]);
+    cmd.assert().success().stdout(
+        str::contains("Sending email to 2 receivers ...")
+            .and(str::contains("Archiving './my-sent-emails"))
+            .and(str::contains("_dry-run.mbox' ..."))
+            .and(str::contains("All emails sent (dry run)")),
+    );
+
+    let campaigns = fs::read_dir(temp_path.join("my-sent-emails"))
+        .unwrap()
+        .flatten()
+        .collect::<Vec<_>>();
+    assert_eq!(campaigns.len(), 1);
+
+    let files = fs::read_dir(campaigns[0].path())
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "mbox")
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(files.len(), 1);
+
+    let mbox = fs::read_to_string(&files[0]).unwrap();
+    assert_eq!(mbox.matches("From albert@einstein.com ").count(), 2);
+    assert!(mbox.contains("To: marie@curie.com"));
+    assert!(mbox.contains("To: alexandre@grothendieck.com"));
 }
 
 #[test]
@@ -98,3 +173,1010 @@ fn test_send_bulk_aws_dry() {
 
     assert!(temp_path.join("my-sent-emails").exists());
 }
+
+#[test]
+fn test_send_bulk_smtp_sink() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::copy("./test_data/receiver.csv", temp_path.join("receiver.csv")).unwrap();
+    fs::copy(
+        "./test_data/message_personalized.yaml",
+        temp_path.join("message.yaml"),
+    )
+    .unwrap();
+
+    let caught_dir = temp_path.join("caught");
+    let sink = SmtpSink::spawn(&caught_dir);
+    let port = sink.port;
+
+    println!("Execute 'pigeon send-bulk --connection smtp'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.env("SMTP_SERVER", "127.0.0.1");
+    cmd.env("SMTP_PORT", port.to_string());
+    cmd.env("SMTP_TLS", "none");
+    cmd.args([
+        "send-bulk",
+        "albert@einstein.com",
+        "--receiver-file",
+        "./receiver.csv",
+        "--message-file",
+        "./message.yaml",
+        "--personalize",
+        "first_name",
+        "last_name",
+        "--archive",
+        "--assume-yes",
+        "--connection",
+        "smtp",
+    ]);
+    cmd.assert().success().stdout(
+        str::contains("Sending email to 2 receivers ...")
+            .and(str::contains("marie@curie.com ... \u{1b}[32mok\u{1b}[0m"))
+            .and(str::contains(
+                "alexandre@grothendieck.com ... \u{1b}[32mok\u{1b}[0m",
+            ))
+            .and(str::contains("All emails sent")),
+    );
+
+    let emls = fs::read_dir(&caught_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| fs::read_to_string(entry.path()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(emls.len(), 2);
+    assert!(emls
+        .iter()
+        .any(|eml| eml.contains("To: marie@curie.com") && eml.contains("Dear Marie Curie")));
+    assert!(emls
+        .iter()
+        .any(|eml| eml.contains("To: alexandre@grothendieck.com")
+            && eml.contains("Dear Alexandre Grothendieck")));
+
+    // The index contains the queue id returned by the SMTP server
+    let campaign_dir = fs::read_dir(temp_path.join("sent_emails"))
+        .unwrap()
+        .flatten()
+        .next()
+        .unwrap()
+        .path();
+    let index = fs::read_to_string(campaign_dir.join("index.jsonl")).unwrap();
+    for entry in fs::read_dir(&caught_dir).unwrap().flatten() {
+        let queue_id = entry
+            .path()
+            .file_stem()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned();
+        assert!(index.contains(&format!(r#""provider_message_id":"{}""#, queue_id)));
+    }
+    assert!(!campaign_dir.join("failed").exists());
+}
+
+#[test]
+fn test_send_bulk_smtp_sink_batch_size() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::copy("./test_data/receiver.csv", temp_path.join("receiver.csv")).unwrap();
+    fs::copy("./test_data/message.yaml", temp_path.join("message.yaml")).unwrap();
+
+    let caught_dir = temp_path.join("caught");
+    let sink = SmtpSink::spawn(&caught_dir);
+    let port = sink.port;
+
+    println!("Execute 'pigeon send-bulk --batch-size 10'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.env("SMTP_SERVER", "127.0.0.1");
+    cmd.env("SMTP_PORT", port.to_string());
+    cmd.env("SMTP_TLS", "none");
+    cmd.args([
+        "send-bulk",
+        "albert@einstein.com",
+        "--receiver-file",
+        "./receiver.csv",
+        "--message-file",
+        "./message.yaml",
+        "--batch-size",
+        "10",
+        "--assume-yes",
+        "--connection",
+        "smtp",
+    ]);
+    cmd.assert().success().stdout(
+        str::contains("Sending email to 2 receivers ...")
+            .and(str::contains("marie@curie.com ... \u{1b}[32mok\u{1b}[0m"))
+            .and(str::contains(
+                "alexandre@grothendieck.com ... \u{1b}[32mok\u{1b}[0m",
+            ))
+            .and(str::contains("All emails sent")),
+    );
+
+    let emls = fs::read_dir(&caught_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| fs::read_to_string(entry.path()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(emls.len(), 1);
+    assert!(emls[0].contains("To: albert@einstein.com"));
+    assert!(!emls[0].contains("marie@curie.com"));
+    assert!(!emls[0].contains("alexandre@grothendieck.com"));
+}
+
+#[test]
+fn test_send_bulk_preview() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::copy("./test_data/receiver.csv", temp_path.join("receiver.csv")).unwrap();
+    fs::copy(
+        "./test_data/message_personalized.yaml",
+        temp_path.join("message.yaml"),
+    )
+    .unwrap();
+
+    println!("Execute 'pigeon send-bulk --preview-dir ./previews'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args([
+        "send-bulk",
+        "albert@einstein.com",
+        "--receiver-file",
+        "./receiver.csv",
+        "--message-file",
+        "./message.yaml",
+        "--personalize",
+        "first_name",
+        "last_name",
+        "--preview-dir",
+        "./previews",
+        "--preview-receiver",
+        "alexandre@grothendieck.com",
+    ]);
+    cmd.assert().success().stdout(
+        str::contains("Rendered 1 of 2 emails in './previews'")
+            .and(str::contains("Sending email").not()),
+    );
+
+    let preview_path = temp_path.join("previews/0002_alexandre@grothendieck.com.html");
+    assert!(
+        preview_path.exists(),
+        "Missing path: {}",
+        preview_path.display()
+    );
+    let preview = fs::read_to_string(preview_path).unwrap();
+    assert!(preview.contains("<tr><th>To</th><td>alexandre@grothendieck.com</td></tr>"));
+    assert!(preview.contains("Dear Alexandre Grothendieck"));
+}
+
+#[test]
+fn test_send_bulk_test_to_smtp_sink() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::copy("./test_data/receiver.csv", temp_path.join("receiver.csv")).unwrap();
+    fs::copy(
+        "./test_data/message_personalized.yaml",
+        temp_path.join("message.yaml"),
+    )
+    .unwrap();
+
+    let caught_dir = temp_path.join("caught");
+    let sink = SmtpSink::spawn(&caught_dir);
+    let port = sink.port;
+
+    println!("Execute 'pigeon send-bulk --test-to'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.env("SMTP_SERVER", "127.0.0.1");
+    cmd.env("SMTP_PORT", port.to_string());
+    cmd.env("SMTP_TLS", "none");
+    cmd.args([
+        "send-bulk",
+        "albert@einstein.com",
+        "--receiver-file",
+        "./receiver.csv",
+        "--message-file",
+        "./message.yaml",
+        "--personalize",
+        "first_name",
+        "last_name",
+        "--archive",
+        "--test-to",
+        "albert@einstein.com,max@planck.com",
+        "--sample",
+        "1",
+        "--assume-yes",
+        "--connection",
+        "smtp",
+    ]);
+    cmd.assert().success().stdout(
+        str::contains("albert@einstein.com ... \u{1b}[32mok\u{1b}[0m")
+            .and(str::contains("max@planck.com ... \u{1b}[32mok\u{1b}[0m"))
+            .and(str::contains("marie@curie.com ...").not())
+            .and(str::contains("All test emails sent")),
+    );
+
+    let emls = fs::read_dir(&caught_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| fs::read_to_string(entry.path()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(emls.len(), 2);
+    assert!(emls.iter().all(|eml| eml.contains("Subject: [TEST] ")
+        && eml.contains("Dear Marie Curie")
+        && !eml.contains("To: marie@curie.com")));
+
+    // Test emails are not archived
+    assert!(!temp_path.join("sent_emails").exists());
+
+    let send_bulk = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("pigeon").unwrap();
+        cmd.current_dir(temp_path);
+        cmd.env("SMTP_SERVER", "127.0.0.1");
+        cmd.env("SMTP_PORT", port.to_string());
+        cmd.env("SMTP_TLS", "none");
+        cmd.args([
+            "send-bulk",
+            "albert@einstein.com",
+            "--receiver-file",
+            "./receiver.csv",
+            "--message-file",
+            "./message.yaml",
+            "--personalize",
+            "first_name",
+            "last_name",
+            "--test-to",
+            "albert@einstein.com",
+            "--assume-yes",
+            "--connection",
+            "smtp",
+        ]);
+        cmd.args(args);
+        cmd.assert()
+    };
+
+    println!("Execute 'pigeon send-bulk --test-to --sample-rows'");
+    send_bulk(&["--sample-rows", "2"]).success().stdout(
+        str::contains("Selecting 1 rows for test emails")
+            .and(str::contains("All test emails sent")),
+    );
+
+    let emls = fs::read_dir(&caught_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| fs::read_to_string(entry.path()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(emls.len(), 3);
+    assert_eq!(
+        emls.iter()
+            .filter(|eml| eml.contains("Dear Alexandre Grothendieck"))
+            .count(),
+        1
+    );
+
+    println!("Execute 'pigeon send-bulk --test-to --sample-rows' with missing row");
+    send_bulk(&["--sample-rows", "3"])
+        .failure()
+        .stderr(str::contains("Row 3 doesn't exist"));
+
+    println!("Execute 'pigeon send-bulk --test-to --pgp-key-column'");
+    send_bulk(&["--pgp-key-column", "public_key"])
+        .failure()
+        .stderr(str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_send_bulk_stream_smtp_sink() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::copy("./test_data/receiver.csv", temp_path.join("receiver.csv")).unwrap();
+    fs::copy(
+        "./test_data/message_personalized.yaml",
+        temp_path.join("message.yaml"),
+    )
+    .unwrap();
+    fs::copy("./test_data/test.pdf", temp_path.join("test.pdf")).unwrap();
+
+    let caught_dir = temp_path.join("caught");
+    let sink = SmtpSink::spawn(&caught_dir);
+    let port = sink.port;
+
+    println!("Execute 'pigeon send-bulk --stream'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.env("SMTP_SERVER", "127.0.0.1");
+    cmd.env("SMTP_PORT", port.to_string());
+    cmd.env("SMTP_TLS", "none");
+    cmd.args([
+        "send-bulk",
+        "albert@einstein.com",
+        "--receiver-file",
+        "./receiver.csv",
+        "--message-file",
+        "./message.yaml",
+        "--attachment",
+        "./test.pdf",
+        "--personalize",
+        "first_name",
+        "last_name",
+        "--stream",
+        "--archive",
+        "--assume-yes",
+        "--connection",
+        "smtp",
+    ]);
+    cmd.assert().success().stdout(
+        str::contains("Streaming csv file './receiver.csv' ...")
+            .and(str::contains(
+                "Sending email to receivers in './receiver.csv' ...",
+            ))
+            .and(str::contains("marie@curie.com ... \u{1b}[32mok\u{1b}[0m"))
+            .and(str::contains(
+                "alexandre@grothendieck.com ... \u{1b}[32mok\u{1b}[0m",
+            ))
+            .and(str::contains("All emails sent to 2 receivers")),
+    );
+
+    let emls = fs::read_dir(&caught_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| fs::read_to_string(entry.path()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(emls.len(), 2);
+    assert!(emls
+        .iter()
+        .any(|eml| eml.contains("To: marie@curie.com") && eml.contains("Dear Marie Curie")));
+    assert!(emls.iter().all(|eml| eml.contains("filename=\"test.pdf\"")));
+
+    let campaign_dir = fs::read_dir(temp_path.join("sent_emails"))
+        .unwrap()
+        .flatten()
+        .next()
+        .unwrap()
+        .path();
+    let index = fs::read_to_string(campaign_dir.join("index.jsonl")).unwrap();
+    assert_eq!(index.lines().count(), 2);
+}
+
+#[test]
+fn test_send_bulk_stream_skip_row() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::write(
+        temp_path.join("receiver.csv"),
+        "first_name,last_name,email\n\
+         Marie,Curie,marie@curie.com\n\
+         Emmy,,emmy@noether.com\n\
+         Alexandre,Grothendieck,alexandre@grothendieck.com\n",
+    )
+    .unwrap();
+    fs::copy(
+        "./test_data/message_personalized.yaml",
+        temp_path.join("message.yaml"),
+    )
+    .unwrap();
+
+    println!("Execute 'pigeon send-bulk --stream' with a missing value");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args([
+        "send-bulk",
+        "albert@einstein.com",
+        "--receiver-file",
+        "./receiver.csv",
+        "--message-file",
+        "./message.yaml",
+        "--personalize",
+        "first_name",
+        "last_name",
+        "--stream",
+        "--dry-run",
+        "--assume-yes",
+    ]);
+    cmd.assert().success().stdout(
+        str::contains(
+            "Skipping receiver 'emmy@noether.com': Missing value for column 'last_name' in row 2",
+        )
+        .and(str::contains(
+            "marie@curie.com ... \u{1b}[32mdry run\u{1b}[0m",
+        ))
+        .and(str::contains(
+            "alexandre@grothendieck.com ... \u{1b}[32mdry run\u{1b}[0m",
+        ))
+        .and(str::contains(
+            "Skipped 1 rows which couldn't be built (rows 2)",
+        ))
+        .and(str::contains("All emails sent to 2 receivers (dry run)")),
+    );
+}
+
+#[test]
+fn test_send_bulk_stream_missing_receiver() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::write(
+        temp_path.join("receiver.csv"),
+        "first_name,last_name,email\n\
+         Marie,Curie,marie@curie.com\n\
+         Emmy,Noether,\n\
+         Alexandre,Grothendieck,alexandre@grothendieck.com\n",
+    )
+    .unwrap();
+    fs::copy(
+        "./test_data/message_personalized.yaml",
+        temp_path.join("message.yaml"),
+    )
+    .unwrap();
+
+    println!("Execute 'pigeon send-bulk --stream' with a missing receiver");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args([
+        "send-bulk",
+        "albert@einstein.com",
+        "--receiver-file",
+        "./receiver.csv",
+        "--message-file",
+        "./message.yaml",
+        "--personalize",
+        "first_name",
+        "last_name",
+        "--stream",
+        "--dry-run",
+        "--assume-yes",
+    ]);
+    cmd.assert().success().stdout(
+        str::contains("Skipping row 2: Missing value for column 'email'")
+            .and(str::contains(
+                "alexandre@grothendieck.com ... \u{1b}[32mdry run\u{1b}[0m",
+            ))
+            .and(str::contains(
+                "Skipped 1 rows which couldn't be built (rows 2)",
+            ))
+            .and(str::contains("All emails sent to 2 receivers (dry run)")),
+    );
+}
+
+#[test]
+fn test_send_bulk_max_message_size() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::copy("./test_data/receiver.csv", temp_path.join("receiver.csv")).unwrap();
+    fs::copy("./test_data/message.yaml", temp_path.join("message.yaml")).unwrap();
+    fs::copy("./test_data/test.pdf", temp_path.join("test.pdf")).unwrap();
+
+    println!("Execute 'pigeon send-bulk --max-message-size 1KB'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args([
+        "send-bulk",
+        "albert@einstein.com",
+        "--receiver-file",
+        "./receiver.csv",
+        "--message-file",
+        "./message.yaml",
+        "--attachment",
+        "./test.pdf",
+        "--max-message-size",
+        "1KB",
+        "--assume-yes",
+        "--dry-run",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(str::contains("Attachments exceed the maximum message size"))
+        .stdout(str::contains("Sending email").not());
+
+    println!("Execute 'pigeon send-bulk --max-message-size 40MB'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args([
+        "send-bulk",
+        "albert@einstein.com",
+        "--receiver-file",
+        "./receiver.csv",
+        "--message-file",
+        "./message.yaml",
+        "--attachment",
+        "./test.pdf",
+        "--max-message-size",
+        "40MB",
+        "--assume-yes",
+        "--dry-run",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(str::contains("All emails sent (dry run)"));
+}
+
+#[test]
+fn test_send_bulk_event_smtp_sink() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::copy("./test_data/receiver.csv", temp_path.join("receiver.csv")).unwrap();
+    fs::copy(
+        "./test_data/message_event.yaml",
+        temp_path.join("message.yaml"),
+    )
+    .unwrap();
+
+    let caught_dir = temp_path.join("caught");
+    let sink = SmtpSink::spawn(&caught_dir);
+    let port = sink.port;
+
+    println!("Execute 'pigeon send-bulk --message-file ./message.yaml'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.env("SMTP_SERVER", "127.0.0.1");
+    cmd.env("SMTP_PORT", port.to_string());
+    cmd.env("SMTP_TLS", "none");
+    cmd.args([
+        "send-bulk",
+        "albert@einstein.com",
+        "--receiver-file",
+        "./receiver.csv",
+        "--message-file",
+        "./message.yaml",
+        "--personalize",
+        "first_name",
+        "email",
+        "--assume-yes",
+        "--connection",
+        "smtp",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(str::contains("marie@curie.com ... \u{1b}[32mok\u{1b}[0m"));
+
+    let emls = fs::read_dir(&caught_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| fs::read_to_string(entry.path()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(emls.len(), 2);
+
+    let eml = emls
+        .iter()
+        .find(|eml| eml.contains("To: marie@curie.com"))
+        .unwrap();
+    assert!(eml.contains("Content-Type: multipart/alternative"));
+    assert!(eml.contains("Content-Type: text/calendar; charset=utf-8; method=REQUEST"));
+    assert!(eml.contains("UID:meetup-2024-marie@curie.com@einstein.com"));
+    assert!(eml.contains("DTSTART:20240601T120000Z"));
+    assert!(eml.contains("DESCRIPTION:See you there\\, Marie!"));
+}
+
+#[test]
+fn test_send_bulk_message_columns_smtp_sink() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::create_dir(temp_path.join("test_data")).unwrap();
+    fs::copy(
+        "./test_data/receiver_columns.csv",
+        temp_path.join("receiver.csv"),
+    )
+    .unwrap();
+    fs::copy(
+        "./test_data/message_fr.yaml",
+        temp_path.join("test_data/message_fr.yaml"),
+    )
+    .unwrap();
+    fs::copy("./test_data/message.yaml", temp_path.join("message.yaml")).unwrap();
+
+    let caught_dir = temp_path.join("caught");
+    let sink = SmtpSink::spawn(&caught_dir);
+    let port = sink.port;
+
+    for stream in [false, true] {
+        println!(
+            "Execute 'pigeon send-bulk --sender-column --subject-column --template-column' (stream: {})",
+            stream
+        );
+        let mut cmd = Command::cargo_bin("pigeon").unwrap();
+        cmd.current_dir(temp_path);
+        cmd.env("SMTP_SERVER", "127.0.0.1");
+        cmd.env("SMTP_PORT", port.to_string());
+        cmd.env("SMTP_TLS", "none");
+        cmd.args([
+            "send-bulk",
+            "albert@einstein.com",
+            "--receiver-file",
+            "./receiver.csv",
+            "--message-file",
+            "./message.yaml",
+            "--personalize",
+            "first_name",
+            "--sender-column",
+            "sender",
+            "--subject-column",
+            "subject",
+            "--template-column",
+            "template",
+            "--assume-yes",
+            "--connection",
+            "smtp",
+        ]);
+
+        if stream {
+            cmd.arg("--stream");
+        }
+
+        cmd.assert()
+            .success()
+            .stdout(str::contains("marie@curie.com ... \u{1b}[32mok\u{1b}[0m"));
+    }
+
+    let emls = fs::read_dir(&caught_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| fs::read_to_string(entry.path()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(emls.len(), 4);
+
+    for eml in emls.iter() {
+        if eml.contains("To: marie@curie.com") {
+            assert!(eml.contains("From: paris@einstein.com"));
+            assert!(eml.contains("Subject: Message de test"));
+            assert!(eml.contains("Ceci est un message de test (texte brut)."));
+        } else {
+            assert!(eml.contains("From: albert@einstein.com"));
+            assert!(eml.contains("Subject: Hello Alexandre"));
+            assert!(eml.contains("This is a test message (plaintext)."));
+        }
+    }
+}
+
+#[test]
+fn test_send_bulk_locale_smtp_sink() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::copy(
+        "./test_data/receiver_locales.csv",
+        temp_path.join("receiver.csv"),
+    )
+    .unwrap();
+    fs::copy(
+        "./test_data/locales/message.yaml",
+        temp_path.join("message.yaml"),
+    )
+    .unwrap();
+    fs::copy(
+        "./test_data/locales/message.de.yaml",
+        temp_path.join("message.de.yaml"),
+    )
+    .unwrap();
+
+    let caught_dir = temp_path.join("caught");
+    let sink = SmtpSink::spawn(&caught_dir);
+    let port = sink.port;
+
+    for stream in [false, true] {
+        println!(
+            "Execute 'pigeon send-bulk --locale-column --default-locale' (stream: {})",
+            stream
+        );
+        let mut cmd = Command::cargo_bin("pigeon").unwrap();
+        cmd.current_dir(temp_path);
+        cmd.env("SMTP_SERVER", "127.0.0.1");
+        cmd.env("SMTP_PORT", port.to_string());
+        cmd.env("SMTP_TLS", "none");
+        cmd.args([
+            "send-bulk",
+            "albert@einstein.com",
+            "--receiver-file",
+            "./receiver.csv",
+            "--message-file",
+            "./message.yaml",
+            "--personalize",
+            "first_name",
+            "amount",
+            "due_date",
+            "--locale-column",
+            "locale",
+            "--default-locale",
+            "en-GB",
+            "--assume-yes",
+            "--connection",
+            "smtp",
+        ]);
+
+        if stream {
+            cmd.arg("--stream");
+        }
+
+        cmd.assert()
+            .success()
+            .stdout(str::contains("lise@meitner.com ... \u{1b}[32mok\u{1b}[0m"));
+    }
+
+    let emls = fs::read_dir(&caught_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| fs::read_to_string(entry.path()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(emls.len(), 6);
+
+    for eml in emls.iter() {
+        if eml.contains("To: lise@meitner.com") {
+            assert!(eml.contains("Subject: Ihre Rechnung"));
+        } else if eml.contains("To: marie@curie.com") {
+            assert!(eml.contains("Subject: Your invoice"));
+            assert!(eml.contains("Dear Marie, your invoice of 99 EUR is due on 15/06/2024."));
+        } else {
+            assert!(eml.contains("Dear Emmy, your invoice of 12,000 EUR is due on 01/07/2024."));
+        }
+    }
+}
+
+#[test]
+fn test_send_bulk_split_smtp_sink() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::copy("./test_data/receiver.csv", temp_path.join("receiver.csv")).unwrap();
+    fs::copy(
+        "./test_data/message_variants.yaml",
+        temp_path.join("message.yaml"),
+    )
+    .unwrap();
+
+    let caught_dir = temp_path.join("caught");
+    let sink = SmtpSink::spawn(&caught_dir);
+    let port = sink.port;
+
+    let send_bulk = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("pigeon").unwrap();
+        cmd.current_dir(temp_path);
+        cmd.env("SMTP_SERVER", "127.0.0.1");
+        cmd.env("SMTP_PORT", port.to_string());
+        cmd.env("SMTP_TLS", "none");
+        cmd.args([
+            "send-bulk",
+            "albert@einstein.com",
+            "--receiver-file",
+            "./receiver.csv",
+            "--message-file",
+            "./message.yaml",
+            "--personalize",
+            "first_name",
+            "--archive",
+            "--assume-yes",
+            "--connection",
+            "smtp",
+        ]);
+        cmd.args(args);
+        cmd.assert().success()
+    };
+
+    for stream in [false, true] {
+        println!(
+            "Execute 'pigeon send-bulk --split 50/50' (stream: {})",
+            stream
+        );
+        let args = if stream {
+            vec!["--split", "50/50", "--stream"]
+        } else {
+            vec!["--split", "50/50"]
+        };
+        send_bulk(&args).stdout(str::contains("marie@curie.com ... \u{1b}[32mok\u{1b}[0m"));
+    }
+
+    println!("Execute 'pigeon send-bulk --split 50/50 --variant short'");
+    send_bulk(&["--split", "50/50", "--variant", "short"]).stdout(str::contains(
+        "Skipping 2 receivers not assigned in this run",
+    ));
+
+    println!("Execute 'pigeon send-bulk --split 50/50 --variant C'");
+    let mut cmd = Command::cargo_bin("pigeon").unwrap();
+    cmd.current_dir(temp_path);
+    cmd.args([
+        "send-bulk",
+        "albert@einstein.com",
+        "--receiver-file",
+        "./receiver.csv",
+        "--message-file",
+        "./message.yaml",
+        "--split",
+        "50/50",
+        "--variant",
+        "C",
+        "--dry-run",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(str::contains("Missing variant 'C' in message"));
+
+    let emls = fs::read_dir(&caught_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| fs::read_to_string(entry.path()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(emls.len(), 4);
+
+    // Each receiver gets the same variant in both runs
+    for receiver in ["marie@curie.com", "alexandre@grothendieck.com"] {
+        let subjects = emls
+            .iter()
+            .filter(|eml| eml.contains(&format!("To: {}", receiver)))
+            .map(|eml| eml.contains("Subject: New brochure"))
+            .collect::<Vec<_>>();
+        assert_eq!(subjects.len(), 2);
+        assert_eq!(subjects[0], subjects[1]);
+    }
+
+    let index = fs::read_dir(temp_path.join("sent_emails"))
+        .unwrap()
+        .flatten()
+        .map(|entry| fs::read_to_string(entry.path().join("index.jsonl")).unwrap_or_default())
+        .collect::<String>();
+    assert_eq!(index.matches("\"variant\":").count(), 4);
+}
+
+#[test]
+fn test_send_bulk_duplicates_dry() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::copy(
+        "./test_data/receiver_duplicates.csv",
+        temp_path.join("receiver.csv"),
+    )
+    .unwrap();
+    fs::copy("./test_data/message.yaml", temp_path.join("message.yaml")).unwrap();
+
+    let send_bulk = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("pigeon").unwrap();
+        cmd.current_dir(temp_path);
+        cmd.args([
+            "send-bulk",
+            "albert@einstein.com",
+            "--receiver-file",
+            "./receiver.csv",
+            "--message-file",
+            "./message.yaml",
+            "--personalize",
+            "first_name",
+            "--assume-yes",
+            "--dry-run",
+        ]);
+        cmd.args(args);
+        cmd.assert()
+    };
+
+    println!("Execute 'pigeon send-bulk' with duplicate receivers");
+    send_bulk(&[]).success().stdout(
+        str::contains("Skipping 1 rows of duplicate receivers ...")
+            .and(str::contains("Sending email to 4 receivers ..."))
+            .and(str::contains(
+                "marie@curie.com ... \u{1b}[32mdry run\u{1b}[0m",
+            ))
+            .and(str::contains("Marie@Curie.com").not()),
+    );
+
+    println!("Execute 'pigeon send-bulk --duplicates last --normalize-gmail'");
+    send_bulk(&["--duplicates", "last", "--normalize-gmail"])
+        .success()
+        .stdout(
+            str::contains("Skipping 2 rows of duplicate receivers ...")
+                .and(str::contains("Sending email to 3 receivers ..."))
+                .and(str::contains(
+                    "Marie@Curie.com ... \u{1b}[32mdry run\u{1b}[0m",
+                ))
+                .and(str::contains(
+                    "emmynoether+news@gmail.com ... \u{1b}[32mdry run\u{1b}[0m",
+                )),
+        );
+
+    println!("Execute 'pigeon send-bulk --duplicates fail'");
+    send_bulk(&["--duplicates", "fail"])
+        .failure()
+        .stderr(str::contains(
+            "Found duplicate receivers: marie@curie.com (rows 1, 3, keeping row 1)",
+        ));
+
+    println!("Execute 'pigeon send-bulk --duplicates fail --suppression-file'");
+    fs::write(
+        temp_path.join("suppressions.csv"),
+        "email,bounce_type,status,diagnostic,date,message_id,source\n\
+         emmy.noether@gmail.com,hard,5.1.1,user unknown,2024-01-01T14:00:05.000Z,1234,dsn.eml\n",
+    )
+    .unwrap();
+    // The rows of the duplicates are the rows of the receiver file
+    send_bulk(&[
+        "--duplicates",
+        "fail",
+        "--suppression-file",
+        "./suppressions.csv",
+    ])
+    .failure()
+    .stderr(str::contains(
+        "Found duplicate receivers: marie@curie.com (rows 1, 3, keeping row 1)",
+    ));
+
+    println!("Execute 'pigeon send-bulk --stream' without detecting duplicates");
+    send_bulk(&["--stream"])
+        .success()
+        .stdout(str::contains("All emails sent to 5 receivers (dry run)"));
+
+    println!("Execute 'pigeon send-bulk --stream' with duplicate receivers");
+    send_bulk(&["--stream", "--normalize-gmail"])
+        .success()
+        .stdout(
+            str::contains("Skipping duplicate receiver 'Marie@Curie.com' in row 3")
+                .and(str::contains(
+                    "Skipping duplicate receiver 'emmynoether+news@gmail.com' in row 4",
+                ))
+                .and(str::contains("All emails sent to 3 receivers (dry run)")),
+        );
+
+    println!("Execute 'pigeon send-bulk --stream --duplicates last'");
+    send_bulk(&["--stream", "--duplicates", "last"])
+        .failure()
+        .stderr(str::contains(
+            "Value 'last' for argument 'duplicates' is not supported for argument 'stream'",
+        ));
+}
+
+#[test]
+fn test_send_bulk_declined_no_campaign() {
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path();
+    assert!(temp_path.exists(), "Missing path: {}", temp_path.display());
+
+    fs::copy("./test_data/receiver.csv", temp_path.join("receiver.csv")).unwrap();
+    fs::copy("./test_data/message.yaml", temp_path.join("message.yaml")).unwrap();
+
+    for stream in [false, true] {
+        println!(
+            "Execute 'pigeon send-bulk --archive' and decline (stream: {})",
+            stream
+        );
+        let mut cmd = Command::cargo_bin("pigeon").unwrap();
+        cmd.current_dir(temp_path);
+        cmd.args([
+            "send-bulk",
+            "albert@einstein.com",
+            "--receiver-file",
+            "./receiver.csv",
+            "--message-file",
+            "./message.yaml",
+            "--archive",
+            "--dry-run",
+        ]);
+
+        if stream {
+            cmd.arg("--stream");
+        }
+
+        cmd.write_stdin("n\n")
+            .assert()
+            .success()
+            .stdout(str::contains("Aborted ..."));
+    }
+
+    assert!(!temp_path.join("sent_emails").exists());
+}
@@ -0,0 +1,56 @@
+use assert_cmd::cargo::CommandCargoExt;
+use std::{
+    io::{BufRead, BufReader},
+    net::SocketAddr,
+    path::Path,
+    process::{Child, Command, Stdio},
+    thread,
+};
+
+/// Running `pigeon smtp-sink` process which is killed when dropped.
+pub struct SmtpSink {
+    process: Child,
+    /// Port chosen by the operating system, as the sink is bound to port 0.
+    pub port: u16,
+}
+
+impl SmtpSink {
+    pub fn spawn(target_dir: &Path) -> Self {
+        let mut process = Command::cargo_bin("pigeon")
+            .unwrap()
+            .args([
+                "smtp-sink",
+                "--port",
+                "0",
+                "--dir",
+                target_dir.to_str().unwrap(),
+            ])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        // The sink accepts connections as soon as it prints its address, e.g.
+        // "Starting SMTP sink on '127.0.0.1:41234' ... ok"
+        let mut stdout = BufReader::new(process.stdout.take().unwrap());
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        let port = line
+            .split('\'')
+            .nth(1)
+            .and_then(|addr| addr.parse::<SocketAddr>().ok())
+            .map(|addr| addr.port())
+            .unwrap_or_else(|| panic!("Can't start SMTP sink: {}", line));
+
+        // Keep reading the output so that the sink doesn't block on a full pipe
+        thread::spawn(move || for _ in stdout.lines().map_while(Result::ok) {});
+
+        Self { process, port }
+    }
+}
+
+impl Drop for SmtpSink {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}